@@ -1,5 +1,22 @@
+// `#[program]` expands its IDL instructions at the crate root, beside the
+// program module, and they still call `AccountInfo::realloc`; a lint attribute
+// on the module cannot reach them, so the module opts back in below.
+#![allow(deprecated)]
+
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::program_option::COption;
 use anchor_lang::system_program;
+use anchor_spl::token_interface::spl_token_2022::{
+    self,
+    extension::{
+        default_account_state::DefaultAccountState, transfer_fee::TransferFeeConfig,
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+    state::AccountState,
+};
+use anchor_spl::metadata::{self, MetadataAccount};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface};
 
 declare_id!("B5jR7EVRTkbJBc7zmRXmMAW1EwYpS9MfniGtRGxPoZ3u");
 
@@ -11,9 +28,19 @@ declare_id!("B5jR7EVRTkbJBc7zmRXmMAW1EwYpS9MfniGtRGxPoZ3u");
 /// Flow:
 /// 1. Player 1 (host) creates a match and stakes SOL
 /// 2. Player 2 (challenger) joins and stakes matching SOL
-/// 3. After game ends, the winner (or arbiter) calls claim_reward
+/// 3. After game ends, a participant calls claim_winner_reward
 /// 4. Winner receives total pot minus platform fee
+///
+/// Draws need both players: one calls offer_draw, the other accept_draw
+/// before the offer expires, and both stakes are refunded.
+///
+/// Settled matches stay open so either player can create_rematch; once the
+/// rematch window has passed, close_match returns the rent to whoever paid it.
+///
+/// Every settlement drains its escrow vault completely: a winner takes whatever
+/// is left after the fee, while refunds send any remainder to the treasury.
 #[program]
+#[warn(deprecated)]
 pub mod pokechess_staking {
     use super::*;
 
@@ -26,11 +53,100 @@ pub mod pokechess_staking {
     /// Maximum stake amount (10 SOL for devnet safety)
     pub const MAX_STAKE_LAMPORTS: u64 = 10_000_000_000;
 
-    /// Creates a new match with initial stake from the host
+    /// Highest fee an allowed mint can be configured with (10%)
+    pub const MAX_MINT_FEE_BPS: u16 = 1_000;
+
+    /// Flat platform fee each player pays into escrow for an NFT match (0.01 SOL)
+    pub const NFT_MATCH_FEE_LAMPORTS: u64 = 10_000_000;
+
+    /// Oldest SOL/USD price a USD match will convert with
+    pub const PRICE_MAX_AGE_SECONDS: i64 = 60;
+
+    /// Widest price confidence interval accepted, relative to the price (1%)
+    pub const PRICE_MAX_CONF_BPS: u64 = 100;
+
+    /// How far the lamport value of a USD stake may move between create and join (2%)
+    pub const PRICE_MAX_DRIFT_BPS: u64 = 200;
+
+    /// Longest series allowed (best of 9)
+    pub const MAX_SERIES_GAMES: u8 = 9;
+
+    /// Largest tournament bracket (players)
+    pub const MAX_TOURNAMENT_SIZE: u8 = 16;
+
+    /// Most players a league can hold
+    pub const MAX_LEAGUE_PLAYERS: u8 = 16;
+
+    /// Most players that can buy into one pot
+    pub const MAX_POT_ENTRANTS: u8 = 32;
+
+    /// Most placements a payout schedule can pay
+    pub const MAX_PAYOUT_PLACES: usize = 16;
+
+    /// Fee on spectator bet pools (3%), separate from the match fee
+    pub const BET_POOL_FEE_BPS: u64 = 300;
+
+    /// Smallest spectator bet (0.001 SOL)
+    pub const MIN_BET_LAMPORTS: u64 = 1_000_000;
+
+    /// Highest markup a backed player can charge backers (50%)
+    pub const MAX_BACKING_MARKUP_BPS: u16 = 5_000;
+
+    /// Smallest sponsor bonus (0.001 SOL), above the rent-exempt minimum so a
+    /// draw refund can always be paid to an empty wallet
+    pub const MIN_SPONSOR_LAMPORTS: u64 = 1_000_000;
+
+    /// Lower stake bound (lamports) of each lobby bucket after the first:
+    /// under 0.05 SOL, 0.05-0.1, 0.1-0.5, 0.5-1, 1-5 and 5+ SOL. Bucket
+    /// `len()` is the open-ended top one, so there are `len() + 1` buckets.
+    pub const LOBBY_BUCKET_BOUNDS: [u64; 5] = [
+        50_000_000,
+        100_000_000,
+        500_000_000,
+        1_000_000_000,
+        5_000_000_000,
+    ];
+
+    /// Most open matches one lobby bucket lists
+    pub const MAX_LOBBY_ENTRIES: usize = 32;
+
+    /// Most players waiting in one matchmaking queue
+    pub const MAX_QUEUE_ENTRIES: usize = 32;
+
+    /// Most a relayer can claim for transaction fees per relayed instruction
+    pub const MAX_RELAYER_FEE_LAMPORTS: u64 = 50_000;
+
+    /// Longest starting clock a match can advertise (3 hours)
+    pub const MAX_TIME_CONTROL_BASE_SECONDS: u32 = 3 * 60 * 60;
+
+    /// Largest per-move increment a match can advertise
+    pub const MAX_TIME_CONTROL_INCREMENT_SECONDS: u32 = 180;
+
+    /// How long a draw offer stays open (two turns at 1 minute per turn)
+    pub const DRAW_OFFER_TTL_SECONDS: i64 = 120;
+
+    /// Longest a session key can stay valid for (one day)
+    pub const MAX_SESSION_SECONDS: i64 = 24 * 60 * 60;
+
+    /// How long a finished match stays open for create_rematch before anyone
+    /// can close it
+    pub const REMATCH_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+
+    /// How long after initialize_config matches left in Completed by the
+    /// retired declare_winner can be finalized (90 days)
+    pub const LEGACY_COMPAT_WINDOW_SECONDS: i64 = 90 * 24 * 60 * 60;
+
+    /// Current MatchAccount layout version (see migrate_match)
+    pub const MATCH_ACCOUNT_VERSION: u8 = 1;
+
+    /// Creates a new match with initial stake from the host. `metadata`
+    /// describes the game for lobby filtering; pass the lobby bucket for the
+    /// stake to list the match there.
     pub fn create_match(
         ctx: Context<CreateMatch>,
         match_id: [u8; 32],
         stake_amount: u64,
+        metadata: MatchMetadata,
     ) -> Result<()> {
         require!(
             stake_amount >= MIN_STAKE_LAMPORTS,
@@ -40,16 +156,22 @@ pub mod pokechess_staking {
             stake_amount <= MAX_STAKE_LAMPORTS,
             StakingError::StakeTooHigh
         );
+        require!(
+            metadata.time_control_base_seconds <= MAX_TIME_CONTROL_BASE_SECONDS
+                && metadata.time_control_increment_seconds <= MAX_TIME_CONTROL_INCREMENT_SECONDS,
+            StakingError::InvalidTimeControl
+        );
 
         let match_account = &mut ctx.accounts.match_account;
-        match_account.match_id = match_id;
-        match_account.host = ctx.accounts.host.key();
-        match_account.challenger = Pubkey::default();
-        match_account.stake_amount = stake_amount;
-        match_account.status = MatchStatus::WaitingForChallenger;
-        match_account.winner = Pubkey::default();
-        match_account.created_at = Clock::get()?.unix_timestamp;
-        match_account.bump = ctx.bumps.match_account;
+        match_account.set_inner(MatchAccount {
+            metadata,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                stake_amount,
+                ctx.bumps.match_account,
+            )?
+        });
 
         // Transfer stake from host to escrow
         system_program::transfer(
@@ -63,16 +185,67 @@ pub mod pokechess_staking {
             stake_amount,
         )?;
 
+        list_in_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+
         emit!(MatchCreated {
             match_id,
             host: ctx.accounts.host.key(),
             stake_amount,
+            metadata: match_account.metadata,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a SOL match at odds: the host and challenger stake different
+    /// amounts (e.g. 3:1) and the winner takes the whole pot.
+    pub fn create_odds_match(
+        ctx: Context<CreateMatch>,
+        match_id: [u8; 32],
+        host_stake: u64,
+        challenger_stake: u64,
+    ) -> Result<()> {
+        for stake in [host_stake, challenger_stake] {
+            require!(stake >= MIN_STAKE_LAMPORTS, StakingError::StakeTooLow);
+            require!(stake <= MAX_STAKE_LAMPORTS, StakingError::StakeTooHigh);
+        }
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            challenger_stake,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                host_stake,
+                ctx.bumps.match_account,
+            )?
+        });
+
+        // Transfer the host's stake to escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.host.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            host_stake,
+        )?;
+
+        list_in_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+
+        emit!(OddsMatchCreated {
+            match_id,
+            host: ctx.accounts.host.key(),
+            host_stake,
+            challenger_stake,
         });
 
         Ok(())
     }
 
-    /// Challenger joins an existing match by staking the same amount
+    /// Challenger joins an existing match by staking their side of it
     pub fn join_match(ctx: Context<JoinMatch>) -> Result<()> {
         let match_account = &mut ctx.accounts.match_account;
 
@@ -80,13 +253,21 @@ pub mod pokechess_staking {
             match_account.status == MatchStatus::WaitingForChallenger,
             StakingError::MatchNotJoinable
         );
+        // USD matches must re-check the price through join_usd_match
+        require!(
+            match_account.stake_usd_cents == 0,
+            StakingError::WrongMatchKind
+        );
         require!(
             ctx.accounts.challenger.key() != match_account.host,
             StakingError::CannotPlaySelf
         );
+        match_account.check_invite(&ctx.accounts.challenger.key(), &[])?;
 
         match_account.challenger = ctx.accounts.challenger.key();
         match_account.status = MatchStatus::InProgress;
+        delist_from_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+        release_host_markup(match_account, ctx.accounts.host_backing_pool.as_mut())?;
 
         // Transfer matching stake from challenger to escrow
         system_program::transfer(
@@ -97,203 +278,257 @@ pub mod pokechess_staking {
                     to: ctx.accounts.escrow_vault.to_account_info(),
                 },
             ),
-            match_account.stake_amount,
+            match_account.challenger_stake,
         )?;
 
         emit!(MatchStarted {
             match_id: match_account.match_id,
             host: match_account.host,
             challenger: ctx.accounts.challenger.key(),
-            total_pot: match_account.stake_amount * 2,
+            total_pot: match_account.total_pot()?,
         });
 
         Ok(())
     }
 
-    /// Declares the winner and immediately distributes rewards in ONE transaction
-    /// The winner account does NOT need to sign - we're just sending SOL to them
-    /// Can only be called by a match participant (honest reporting)
-    pub fn claim_winner_reward(ctx: Context<ClaimWinnerReward>, winner: Pubkey) -> Result<()> {
-        let match_account = &mut ctx.accounts.match_account;
-
-        require!(
-            match_account.status == MatchStatus::InProgress,
-            StakingError::MatchNotInProgress
-        );
-        require!(
-            winner == match_account.host || winner == match_account.challenger,
-            StakingError::InvalidWinner
-        );
-
-        // Verify caller is a participant
-        let caller = ctx.accounts.caller.key();
+    /// Creates a SOL match only an invited challenger, or a member of a group
+    /// committed to by a merkle root of pubkeys, can join. Private matches are
+    /// never listed in the lobby.
+    pub fn create_private_match(
+        ctx: Context<CreateMatch>,
+        match_id: [u8; 32],
+        stake_amount: u64,
+        invited_challenger: Pubkey,
+        invite_root: [u8; 32],
+    ) -> Result<()> {
         require!(
-            caller == match_account.host || caller == match_account.challenger,
-            StakingError::NotParticipant
+            stake_amount >= MIN_STAKE_LAMPORTS,
+            StakingError::StakeTooLow
         );
-
-        // Verify winner account matches the declared winner
         require!(
-            ctx.accounts.winner_account.key() == winner,
-            StakingError::InvalidWinner
+            stake_amount <= MAX_STAKE_LAMPORTS,
+            StakingError::StakeTooHigh
         );
 
-        // Update match state
-        match_account.winner = winner;
-        match_account.status = MatchStatus::Completed;
-
-        // Calculate payouts
-        let total_pot = match_account.stake_amount * 2;
-        let platform_fee = (total_pot * PLATFORM_FEE_BPS) / 10_000;
-        let winner_reward = total_pot - platform_fee;
-        let match_id = match_account.match_id;
-
-        // Get the escrow bump for signing
-        let escrow_bump = ctx.bumps.escrow_vault;
-        let escrow_seeds = &[
-            b"escrow".as_ref(),
-            match_id.as_ref(),
-            &[escrow_bump],
-        ];
-        let signer_seeds = &[&escrow_seeds[..]];
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            invited_challenger,
+            invite_root,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                stake_amount,
+                ctx.bumps.match_account,
+            )?
+        });
 
-        // Transfer winner reward - winner does NOT need to sign!
+        // Transfer stake from host to escrow
         system_program::transfer(
-            CpiContext::new_with_signer(
+            CpiContext::new(
                 ctx.accounts.system_program.to_account_info(),
                 system_program::Transfer {
-                    from: ctx.accounts.escrow_vault.to_account_info(),
-                    to: ctx.accounts.winner_account.to_account_info(),
+                    from: ctx.accounts.host.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
                 },
-                signer_seeds,
             ),
-            winner_reward,
+            stake_amount,
         )?;
 
-        // Transfer platform fee to treasury
+        emit!(MatchCreated {
+            match_id,
+            host: ctx.accounts.host.key(),
+            stake_amount,
+            metadata: match_account.metadata,
+        });
+        emit!(MatchInviteUpdated {
+            match_id,
+            invited_challenger,
+            invite_root,
+        });
+
+        Ok(())
+    }
+
+    /// Joins an invite-only match as a member of its invited group, proving
+    /// membership with a merkle proof of the challenger's pubkey
+    pub fn join_private_match(ctx: Context<JoinMatch>, proof: Vec<[u8; 32]>) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
+        );
+        // USD matches must re-check the price through join_usd_match
+        require!(
+            match_account.stake_usd_cents == 0,
+            StakingError::WrongMatchKind
+        );
+        require!(
+            ctx.accounts.challenger.key() != match_account.host,
+            StakingError::CannotPlaySelf
+        );
+        match_account.check_invite(&ctx.accounts.challenger.key(), &proof)?;
+
+        match_account.challenger = ctx.accounts.challenger.key();
+        match_account.status = MatchStatus::InProgress;
+        delist_from_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+        release_host_markup(match_account, ctx.accounts.host_backing_pool.as_mut())?;
+
+        // Transfer matching stake from challenger to escrow
         system_program::transfer(
-            CpiContext::new_with_signer(
+            CpiContext::new(
                 ctx.accounts.system_program.to_account_info(),
                 system_program::Transfer {
-                    from: ctx.accounts.escrow_vault.to_account_info(),
-                    to: ctx.accounts.platform_treasury.to_account_info(),
+                    from: ctx.accounts.challenger.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
                 },
-                signer_seeds,
             ),
-            platform_fee,
+            match_account.challenger_stake,
         )?;
 
-        emit!(RewardClaimed {
-            match_id,
-            winner,
-            amount: winner_reward,
-            platform_fee,
+        emit!(MatchStarted {
+            match_id: match_account.match_id,
+            host: match_account.host,
+            challenger: ctx.accounts.challenger.key(),
+            total_pot: match_account.total_pot()?,
         });
 
         Ok(())
     }
 
-    /// DEPRECATED: Use claim_winner_reward instead
-    /// Kept for backwards compatibility
-    pub fn declare_winner(ctx: Context<DeclareWinner>, winner: Pubkey) -> Result<()> {
+    /// Change who may join a match that's still waiting for a challenger.
+    /// Default pubkey and an all-zero root open it to anyone again. Invite
+    /// groups are only accepted on matches join_private_match can join.
+    pub fn update_match_invite(
+        ctx: Context<UpdateMatchInvite>,
+        invited_challenger: Pubkey,
+        invite_root: [u8; 32],
+    ) -> Result<()> {
         let match_account = &mut ctx.accounts.match_account;
 
         require!(
-            match_account.status == MatchStatus::InProgress,
-            StakingError::MatchNotInProgress
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
         );
         require!(
-            winner == match_account.host || winner == match_account.challenger,
-            StakingError::InvalidWinner
+            ctx.accounts.host.key() == match_account.host,
+            StakingError::NotHost
         );
-
-        // Verify caller is a participant
-        let caller = ctx.accounts.caller.key();
+        // Group members can only get in through join_private_match
         require!(
-            caller == match_account.host || caller == match_account.challenger,
-            StakingError::NotParticipant
+            invite_root == [0u8; 32]
+                || (match_account.kind == MatchKind::Sol && match_account.stake_usd_cents == 0),
+            StakingError::InviteGroupNotSupported
         );
 
-        match_account.winner = winner;
-        match_account.status = MatchStatus::Completed;
+        match_account.invited_challenger = invited_challenger;
+        match_account.invite_root = invite_root;
+        // Invite-only matches are not listed in the lobby
+        if invited_challenger != Pubkey::default() || invite_root != [0u8; 32] {
+            delist_from_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+        }
 
-        emit!(WinnerDeclared {
+        emit!(MatchInviteUpdated {
             match_id: match_account.match_id,
-            winner,
-            declared_by: caller,
+            invited_challenger,
+            invite_root,
         });
 
         Ok(())
     }
 
-    /// DEPRECATED: Use claim_winner_reward instead
-    /// Kept for backwards compatibility
-    pub fn claim_reward(ctx: Context<ClaimReward>) -> Result<()> {
-        let match_account = &ctx.accounts.match_account;
+    /// Declares the winner and immediately distributes rewards in ONE transaction
+    /// The winner account does NOT need to sign - we're just sending SOL to them
+    /// Can only be called by a match participant (honest reporting), or by
+    /// their session key
+    pub fn claim_winner_reward(ctx: Context<ClaimWinnerReward>, winner: Pubkey) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
 
         require!(
-            match_account.status == MatchStatus::Completed,
-            StakingError::MatchNotCompleted
+            match_account.status == MatchStatus::InProgress,
+            StakingError::MatchNotInProgress
         );
         require!(
-            ctx.accounts.winner.key() == match_account.winner,
-            StakingError::NotWinner
+            winner == match_account.host || winner == match_account.challenger,
+            StakingError::InvalidWinner
+        );
+
+        // Verify caller is a participant
+        let caller = acting_player(&ctx.accounts.caller.key(), ctx.accounts.session.as_deref())?;
+        require!(
+            caller == match_account.host || caller == match_account.challenger,
+            StakingError::NotParticipant
+        );
+
+        // Verify winner account matches the declared winner (or their vault)
+        require!(
+            ctx.accounts.winner_account.key() == match_account.payout_address(winner),
+            StakingError::InvalidWinner
         );
 
-        let total_pot = match_account.stake_amount * 2;
-        let platform_fee = (total_pot * PLATFORM_FEE_BPS) / 10_000;
-        let winner_reward = total_pot - platform_fee;
+        // Update match state (the account stays open so a rematch can be derived from it)
+        match_account.winner = winner;
+        match_account.finish(MatchStatus::Settled)?;
 
+        // Calculate payouts
+        let total_pot = match_account.total_pot()?;
+        let platform_fee = platform_fee(total_pot, match_account.fee_bps as u64);
         let match_id = match_account.match_id;
-
-        // Get the escrow bump for signing
         let escrow_bump = ctx.bumps.escrow_vault;
-        let escrow_seeds = &[
-            b"escrow".as_ref(),
-            match_id.as_ref(),
-            &[escrow_bump],
-        ];
-        let signer_seeds = &[&escrow_seeds[..]];
 
-        // Transfer winner reward using CPI with PDA signature
-        system_program::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.escrow_vault.to_account_info(),
-                    to: ctx.accounts.winner.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            winner_reward,
+        // Transfer platform fee to treasury
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            escrow_bump,
+            platform_fee,
         )?;
 
-        // Transfer platform fee to treasury using CPI with PDA signature
-        system_program::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.escrow_vault.to_account_info(),
-                    to: ctx.accounts.platform_treasury.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            platform_fee,
+        // Pay back a relayer's fees before the winner's share
+        reimburse_relayer(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.relayer.as_ref(),
+            match_account,
+            escrow_bump,
+        )?;
+
+        // Winner sweeps the rest of the vault, rounding dust included -
+        // winner does NOT need to sign!
+        let winner_reward = ctx.accounts.escrow_vault.lamports();
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.winner_account.to_account_info(),
+            &match_id,
+            escrow_bump,
+            winner_reward,
         )?;
 
         emit!(RewardClaimed {
             match_id,
-            winner: ctx.accounts.winner.key(),
+            winner,
             amount: winner_reward,
             platform_fee,
         });
 
+        // The sponsor bonus went to the winner with the rest of the vault
+        if match_account.sponsor_bonus > 0 {
+            emit!(SponsorBonusPaid {
+                match_id,
+                recipient: winner,
+                amount: match_account.sponsor_bonus,
+            });
+        }
+
         Ok(())
     }
 
     /// Cancel a match before challenger joins (refund host)
     pub fn cancel_match(ctx: Context<CancelMatch>) -> Result<()> {
-        let match_account = &ctx.accounts.match_account;
+        let match_account = &mut ctx.accounts.match_account;
 
         require!(
             match_account.status == MatchStatus::WaitingForChallenger,
@@ -304,31 +539,41 @@ pub mod pokechess_staking {
             StakingError::NotHost
         );
 
-        let stake_amount = match_account.stake_amount;
         let match_id = match_account.match_id;
-
-        // Get the escrow bump for signing
         let escrow_bump = ctx.bumps.escrow_vault;
-        let escrow_seeds = &[
-            b"escrow".as_ref(),
-            match_id.as_ref(),
-            &[escrow_bump],
-        ];
-        let signer_seeds = &[&escrow_seeds[..]];
 
-        // Refund host using CPI with PDA signature
-        system_program::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.escrow_vault.to_account_info(),
-                    to: ctx.accounts.host.to_account_info(),
-                },
-                signer_seeds,
-            ),
+        // A relayer is paid back out of the host's refund
+        let reimbursed = reimburse_relayer(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.relayer.as_ref(),
+            match_account,
+            escrow_bump,
+        )?;
+        let stake_amount = match_account.stake_amount.saturating_sub(reimbursed);
+
+        // Refund host (or their vault) using PDA signature
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.host_refund.to_account_info(),
+            &match_id,
+            escrow_bump,
             stake_amount,
         )?;
 
+        // Anything else in the vault was never staked - sweep it to the treasury
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            escrow_bump,
+            ctx.accounts.escrow_vault.lamports(),
+        )?;
+
+        delist_from_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+
         emit!(MatchCancelled {
             match_id,
             refunded_to: ctx.accounts.host.key(),
@@ -338,8 +583,9 @@ pub mod pokechess_staking {
         Ok(())
     }
 
-    /// Declare a draw and refund both players
-    pub fn declare_draw(ctx: Context<DeclareDraw>) -> Result<()> {
+    /// Offer a draw to the opponent. The offer must be accepted by the other
+    /// participant before it expires; a fresh offer replaces the caller's own one.
+    pub fn offer_draw(ctx: Context<OfferDraw>, reason: DrawReason) -> Result<()> {
         let match_account = &mut ctx.accounts.match_account;
 
         require!(
@@ -347,377 +593,11827 @@ pub mod pokechess_staking {
             StakingError::MatchNotInProgress
         );
 
-        let caller = ctx.accounts.caller.key();
+        let caller = acting_player(&ctx.accounts.caller.key(), ctx.accounts.session.as_deref())?;
         require!(
             caller == match_account.host || caller == match_account.challenger,
             StakingError::NotParticipant
         );
 
-        match_account.status = MatchStatus::Draw;
-        let stake_amount = match_account.stake_amount;
-        let match_id = match_account.match_id;
-
-        // Get the escrow bump for signing
-        let escrow_bump = ctx.bumps.escrow_vault;
-        let escrow_seeds = &[
-            b"escrow".as_ref(),
-            match_id.as_ref(),
-            &[escrow_bump],
-        ];
-        let signer_seeds = &[&escrow_seeds[..]];
-
-        // Refund host using CPI with PDA signature
-        system_program::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.escrow_vault.to_account_info(),
-                    to: ctx.accounts.host_account.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            stake_amount,
-        )?;
+        // Don't let a player talk over an offer they should be answering
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            match_account.draw_offered_by == Pubkey::default()
+                || match_account.draw_offered_by == caller
+                || match_account.draw_offer_expires_at < now,
+            StakingError::DrawOfferPending
+        );
 
-        // Refund challenger using CPI with PDA signature
-        system_program::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.escrow_vault.to_account_info(),
-                    to: ctx.accounts.challenger_account.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            stake_amount,
-        )?;
+        let expires_at = now + DRAW_OFFER_TTL_SECONDS;
+        match_account.draw_offered_by = caller;
+        match_account.draw_offer_expires_at = expires_at;
+        match_account.draw_reason = reason;
 
-        emit!(MatchDraw {
-            match_id,
-            refund_amount: stake_amount,
+        emit!(DrawOffered {
+            match_id: match_account.match_id,
+            offered_by: caller,
+            reason,
+            expires_at,
         });
 
         Ok(())
     }
-}
 
+    /// Accept the opponent's pending draw offer and refund both players. The
+    /// sponsor bonus is split between them, except what sponsors asked back,
+    /// which stays in escrow for them to claim with close_sponsorship.
+    pub fn accept_draw(ctx: Context<AcceptDraw>) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+
+        let caller = acting_player(&ctx.accounts.caller.key(), ctx.accounts.session.as_deref())?;
+        require!(
+            caller == match_account.host || caller == match_account.challenger,
+            StakingError::NotParticipant
+        );
+        require!(
+            match_account.draw_offered_by != Pubkey::default(),
+            StakingError::NoDrawOffer
+        );
+        require!(
+            match_account.draw_offered_by != caller,
+            StakingError::CannotAcceptOwnDrawOffer
+        );
+        require!(
+            match_account.draw_offer_expires_at >= Clock::get()?.unix_timestamp,
+            StakingError::DrawOfferExpired
+        );
+
+        match_account.finish(MatchStatus::Draw)?;
+        let match_id = match_account.match_id;
+        let reason = match_account.draw_reason;
+        let escrow_bump = ctx.bumps.escrow_vault;
+
+        // A relayer is paid back first, split evenly between both refunds
+        let reimbursed = reimburse_relayer(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.relayer.as_ref(),
+            match_account,
+            escrow_bump,
+        )?;
+        let host_refund = match_account
+            .stake_amount
+            .saturating_sub(reimbursed.div_ceil(2));
+        let challenger_refund = match_account
+            .challenger_stake
+            .saturating_sub(reimbursed.div_ceil(2));
+
+        // Sponsors who chose a split share their bonus evenly between the players
+        let split_bonus = match_account.sponsor_bonus - match_account.sponsor_draw_refund;
+        let host_refund = host_refund + split_bonus / 2;
+        let challenger_refund = challenger_refund + (split_bonus - split_bonus / 2);
+
+        // Refund host using PDA signature
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.host_account.to_account_info(),
+            &match_id,
+            escrow_bump,
+            host_refund,
+        )?;
+
+        // Refund challenger using PDA signature
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.challenger_account.to_account_info(),
+            &match_id,
+            escrow_bump,
+            challenger_refund,
+        )?;
+
+        // Sweep any remainder to the treasury, keeping the sponsors' refunds
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            escrow_bump,
+            ctx.accounts
+                .escrow_vault
+                .lamports()
+                .saturating_sub(match_account.sponsor_draw_refund),
+        )?;
+
+        emit!(MatchDraw {
+            match_id,
+            refund_amount: host_refund,
+            challenger_refund_amount: challenger_refund,
+            reason,
+        });
+
+        Ok(())
+    }
+
+    /// Start a rematch from a finished match. Both players carry over (colors
+    /// optionally swapped) at the same stake; the proposer stakes immediately and
+    /// the opponent only needs accept_rematch - no new room code required.
+    pub fn create_rematch(
+        ctx: Context<CreateRematch>,
+        rematch_id: [u8; 32],
+        swap_colors: bool,
+    ) -> Result<()> {
+        let previous = &ctx.accounts.previous_match;
+
+        require!(previous.is_finished(), StakingError::MatchNotFinished);
+        require!(
+            rematch_id == previous.rematch_id(),
+            StakingError::InvalidRematchId
+        );
+
+        let proposer = ctx.accounts.proposer.key();
+        require!(
+            proposer == previous.host || proposer == previous.challenger,
+            StakingError::NotParticipant
+        );
+
+        // Each player keeps their side of the odds, whichever color they get
+        let (host, challenger) = if swap_colors {
+            (previous.challenger, previous.host)
+        } else {
+            (previous.host, previous.challenger)
+        };
+        let stake_amount = previous.stake_of(host);
+        let challenger_stake = previous.stake_of(challenger);
+        let proposer_stake = previous.stake_of(proposer);
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            challenger,
+            status: MatchStatus::WaitingForRematch,
+            rematch_of: previous.match_id,
+            rematch_proposer: proposer,
+            rent_payer: proposer,
+            challenger_stake,
+            metadata: previous.metadata,
+            ..MatchAccount::new(rematch_id, host, stake_amount, ctx.bumps.match_account)?
+        });
+
+        // Transfer the proposer's stake to escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.proposer.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            proposer_stake,
+        )?;
+
+        emit!(RematchCreated {
+            match_id: rematch_id,
+            rematch_of: previous.match_id,
+            host,
+            challenger,
+            proposed_by: proposer,
+            stake_amount,
+            challenger_stake,
+        });
+
+        Ok(())
+    }
+
+    /// The opponent of the rematch proposer stakes and the rematch starts
+    pub fn accept_rematch(ctx: Context<AcceptRematch>) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForRematch,
+            StakingError::MatchNotJoinable
+        );
+
+        let caller = ctx.accounts.caller.key();
+        require!(
+            caller == match_account.host || caller == match_account.challenger,
+            StakingError::NotParticipant
+        );
+        require!(
+            caller != match_account.rematch_proposer,
+            StakingError::AlreadyStaked
+        );
+
+        match_account.status = MatchStatus::InProgress;
+
+        // Transfer matching stake from the accepting player to escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.caller.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            match_account.stake_of(caller),
+        )?;
+
+        emit!(MatchStarted {
+            match_id: match_account.match_id,
+            host: match_account.host,
+            challenger: match_account.challenger,
+            total_pot: match_account.total_pot()?,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw a rematch proposal nobody accepted (refund the proposer)
+    pub fn cancel_rematch(ctx: Context<CancelRematch>) -> Result<()> {
+        let match_account = &ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForRematch,
+            StakingError::CannotCancelStartedMatch
+        );
+        require!(
+            ctx.accounts.proposer.key() == match_account.rematch_proposer,
+            StakingError::NotRematchProposer
+        );
+
+        let stake_amount = match_account.stake_of(match_account.rematch_proposer);
+        let match_id = match_account.match_id;
+        let escrow_bump = ctx.bumps.escrow_vault;
+
+        // Refund proposer using PDA signature
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.proposer.to_account_info(),
+            &match_id,
+            escrow_bump,
+            stake_amount,
+        )?;
+
+        // Anything else in the vault was never staked - sweep it to the treasury
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            escrow_bump,
+            ctx.accounts.escrow_vault.lamports(),
+        )?;
+
+        emit!(MatchCancelled {
+            match_id,
+            refunded_to: ctx.accounts.proposer.key(),
+            amount: stake_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Close a finished match once its rematch window has passed, returning the
+    /// rent to whoever paid it. Anyone can call this.
+    pub fn close_match(ctx: Context<CloseMatch>) -> Result<()> {
+        let match_account = &ctx.accounts.match_account;
+
+        require!(match_account.is_finished(), StakingError::MatchNotFinished);
+        require!(
+            Clock::get()?.unix_timestamp >= match_account.finished_at + REMATCH_WINDOW_SECONDS,
+            StakingError::RematchWindowOpen
+        );
+        require!(
+            !match_account.bet_pool_open,
+            StakingError::BetPoolNotSettled
+        );
+        require!(
+            match_account.status != MatchStatus::Draw || match_account.draw_refund_sponsors == 0,
+            StakingError::SponsorRefundsOutstanding
+        );
+
+        Ok(())
+    }
+
+    /// Creates a match staked in an SPL or Token-2022 token instead of SOL. The
+    /// stake moves into a token escrow account whose authority is the match PDA.
+    ///
+    /// Only allowlisted mints are accepted, within their configured stake range.
+    /// For mints with a transfer fee the stake recorded is what the escrow
+    /// actually received; transfer-hook accounts go in remaining accounts.
+    pub fn create_token_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreateTokenMatch<'info>>,
+        match_id: [u8; 32],
+        stake_amount: u64,
+    ) -> Result<()> {
+        validate_stake_mint(&ctx.accounts.stake_mint.to_account_info())?;
+
+        // Transfer stake from host to the token escrow
+        transfer_stake_tokens(
+            &ctx.accounts.token_program,
+            ctx.accounts.host_token_account.to_account_info(),
+            &ctx.accounts.stake_mint,
+            ctx.accounts.token_escrow.to_account_info(),
+            ctx.accounts.host.to_account_info(),
+            ctx.remaining_accounts,
+            stake_amount,
+            &[],
+        )?;
+
+        // Both sides are matched on what landed in escrow, after any transfer fee
+        ctx.accounts.token_escrow.reload()?;
+        let received = ctx.accounts.token_escrow.amount;
+        let mint_config = &ctx.accounts.mint_config;
+        require!(mint_config.enabled, StakingError::MintNotAllowed);
+        require!(
+            received >= mint_config.min_stake,
+            StakingError::StakeTooLow
+        );
+        require!(
+            received <= mint_config.max_stake,
+            StakingError::StakeTooHigh
+        );
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            kind: MatchKind::Token,
+            stake_mint: ctx.accounts.stake_mint.key(),
+            fee_bps: mint_config.fee_bps,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                received,
+                ctx.bumps.match_account,
+            )?
+        });
+
+        emit!(TokenMatchCreated {
+            match_id,
+            host: ctx.accounts.host.key(),
+            stake_mint: ctx.accounts.stake_mint.key(),
+            stake_amount: received,
+        });
+
+        Ok(())
+    }
+
+    /// Challenger joins a token match. With a transfer-fee mint the challenger
+    /// sends enough extra that the escrow receives exactly the host's stake.
+    /// Session keys can't sign this: they can't move the challenger's tokens,
+    /// and their limits are in lamports.
+    pub fn join_token_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, JoinTokenMatch<'info>>,
+    ) -> Result<()> {
+        let match_account = &ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            ctx.accounts.challenger.key() != match_account.host,
+            StakingError::CannotPlaySelf
+        );
+        match_account.check_invite(&ctx.accounts.challenger.key(), &[])?;
+
+        let stake_amount = match_account.challenger_stake;
+        let gross_amount =
+            gross_for_net_amount(&ctx.accounts.stake_mint.to_account_info(), stake_amount)?;
+        let escrowed_before = ctx.accounts.token_escrow.amount;
+
+        // Transfer matching stake from challenger to the token escrow
+        transfer_stake_tokens(
+            &ctx.accounts.token_program,
+            ctx.accounts.challenger_token_account.to_account_info(),
+            &ctx.accounts.stake_mint,
+            ctx.accounts.token_escrow.to_account_info(),
+            ctx.accounts.challenger.to_account_info(),
+            ctx.remaining_accounts,
+            gross_amount,
+            &[],
+        )?;
+
+        ctx.accounts.token_escrow.reload()?;
+        require!(
+            ctx.accounts.token_escrow.amount - escrowed_before >= stake_amount,
+            StakingError::TransferFeeMismatch
+        );
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.challenger = ctx.accounts.challenger.key();
+        match_account.status = MatchStatus::InProgress;
+
+        emit!(MatchStarted {
+            match_id: match_account.match_id,
+            host: match_account.host,
+            challenger: ctx.accounts.challenger.key(),
+            total_pot: match_account.total_pot()?,
+        });
+
+        Ok(())
+    }
+
+    /// Token counterpart of claim_winner_reward: pays the fee to the treasury's
+    /// token account, the rest of the escrow to the winner, and closes the escrow
+    pub fn claim_token_winner_reward<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimTokenWinnerReward<'info>>,
+        winner: Pubkey,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+        require!(
+            winner == match_account.host || winner == match_account.challenger,
+            StakingError::InvalidWinner
+        );
+
+        // Verify caller is a participant
+        let caller = acting_player(&ctx.accounts.caller.key(), ctx.accounts.session.as_deref())?;
+        require!(
+            caller == match_account.host || caller == match_account.challenger,
+            StakingError::NotParticipant
+        );
+
+        match_account.winner = winner;
+        match_account.finish(MatchStatus::Settled)?;
+
+        let total_pot = match_account.total_pot()?;
+        let platform_fee = platform_fee(total_pot, match_account.fee_bps as u64);
+        let match_id = match_account.match_id;
+        let match_bump = match_account.bump;
+        let match_info = match_account.to_account_info();
+
+        // Transfer platform fee to the treasury token account
+        transfer_from_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.treasury_token_account.to_account_info(),
+            match_info.clone(),
+            ctx.remaining_accounts,
+            &match_id,
+            match_bump,
+            platform_fee,
+        )?;
+
+        // Winner sweeps everything left in the token escrow
+        ctx.accounts.token_escrow.reload()?;
+        let winner_reward = ctx.accounts.token_escrow.amount;
+        transfer_from_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.winner_token_account.to_account_info(),
+            match_info.clone(),
+            ctx.remaining_accounts,
+            &match_id,
+            match_bump,
+            winner_reward,
+        )?;
+
+        close_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.caller.to_account_info(),
+            match_info,
+            &match_id,
+            match_bump,
+        )?;
+
+        emit!(RewardClaimed {
+            match_id,
+            winner,
+            amount: winner_reward,
+            platform_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Token counterpart of accept_draw: accepts the opponent's draw offer and
+    /// refunds both stakes from the token escrow
+    pub fn accept_token_draw<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AcceptTokenDraw<'info>>,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+
+        let caller = acting_player(&ctx.accounts.caller.key(), ctx.accounts.session.as_deref())?;
+        require!(
+            caller == match_account.host || caller == match_account.challenger,
+            StakingError::NotParticipant
+        );
+        require!(
+            match_account.draw_offered_by != Pubkey::default(),
+            StakingError::NoDrawOffer
+        );
+        require!(
+            match_account.draw_offered_by != caller,
+            StakingError::CannotAcceptOwnDrawOffer
+        );
+        require!(
+            match_account.draw_offer_expires_at >= Clock::get()?.unix_timestamp,
+            StakingError::DrawOfferExpired
+        );
+
+        match_account.finish(MatchStatus::Draw)?;
+        let host_refund = match_account.stake_amount;
+        let challenger_refund = match_account.challenger_stake;
+        let match_id = match_account.match_id;
+        let reason = match_account.draw_reason;
+        let match_bump = match_account.bump;
+        let match_info = match_account.to_account_info();
+
+        // Refund host
+        transfer_from_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.host_token_account.to_account_info(),
+            match_info.clone(),
+            ctx.remaining_accounts,
+            &match_id,
+            match_bump,
+            host_refund,
+        )?;
+
+        // Refund challenger
+        transfer_from_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.challenger_token_account.to_account_info(),
+            match_info.clone(),
+            ctx.remaining_accounts,
+            &match_id,
+            match_bump,
+            challenger_refund,
+        )?;
+
+        // Sweep any remainder to the treasury so the escrow can be closed
+        ctx.accounts.token_escrow.reload()?;
+        transfer_from_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.treasury_token_account.to_account_info(),
+            match_info.clone(),
+            ctx.remaining_accounts,
+            &match_id,
+            match_bump,
+            ctx.accounts.token_escrow.amount,
+        )?;
+
+        close_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.caller.to_account_info(),
+            match_info,
+            &match_id,
+            match_bump,
+        )?;
+
+        emit!(MatchDraw {
+            match_id,
+            refund_amount: host_refund,
+            challenger_refund_amount: challenger_refund,
+            reason,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel a token match before challenger joins (refund host)
+    pub fn cancel_token_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelTokenMatch<'info>>,
+    ) -> Result<()> {
+        let match_account = &ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::CannotCancelStartedMatch
+        );
+        require!(
+            ctx.accounts.host.key() == match_account.host,
+            StakingError::NotHost
+        );
+
+        let stake_amount = match_account.stake_amount;
+        let match_id = match_account.match_id;
+        let match_bump = match_account.bump;
+        let match_info = match_account.to_account_info();
+
+        // Refund host
+        transfer_from_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.host_token_account.to_account_info(),
+            match_info.clone(),
+            ctx.remaining_accounts,
+            &match_id,
+            match_bump,
+            stake_amount,
+        )?;
+
+        // Anything else in the escrow was never staked - sweep it to the treasury
+        ctx.accounts.token_escrow.reload()?;
+        transfer_from_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.treasury_token_account.to_account_info(),
+            match_info.clone(),
+            ctx.remaining_accounts,
+            &match_id,
+            match_bump,
+            ctx.accounts.token_escrow.amount,
+        )?;
+
+        close_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.stake_mint,
+            ctx.accounts.host.to_account_info(),
+            match_info,
+            &match_id,
+            match_bump,
+        )?;
+
+        emit!(MatchCancelled {
+            match_id,
+            refunded_to: ctx.accounts.host.key(),
+            amount: stake_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a SOL match where a relayer, not the host, pays the match
+    /// account's rent and the transaction fees. `relayer_fee` is paid back
+    /// from escrow at settlement; the rent comes back when the match is closed.
+    pub fn create_match_relayed(
+        ctx: Context<CreateMatchRelayed>,
+        match_id: [u8; 32],
+        stake_amount: u64,
+        relayer_fee: u64,
+    ) -> Result<()> {
+        require!(
+            stake_amount >= MIN_STAKE_LAMPORTS,
+            StakingError::StakeTooLow
+        );
+        require!(
+            stake_amount <= MAX_STAKE_LAMPORTS,
+            StakingError::StakeTooHigh
+        );
+        require!(
+            relayer_fee <= MAX_RELAYER_FEE_LAMPORTS,
+            StakingError::RelayerFeeTooHigh
+        );
+        require!(
+            ctx.accounts.relayer.key() != ctx.accounts.host.key(),
+            StakingError::RelayerIsPlayer
+        );
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            relayer: ctx.accounts.relayer.key(),
+            rent_payer: ctx.accounts.relayer.key(),
+            relayer_advanced: relayer_fee,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                stake_amount,
+                ctx.bumps.match_account,
+            )?
+        });
+
+        // Transfer stake from host to escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.host.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            stake_amount,
+        )?;
+
+        emit!(MatchCreated {
+            match_id,
+            host: ctx.accounts.host.key(),
+            stake_amount,
+            metadata: match_account.metadata,
+        });
+
+        Ok(())
+    }
+
+    /// Challenger joins a SOL match through a relayer paying the transaction
+    /// fee. A match can only have one relayer.
+    pub fn join_match_relayed(ctx: Context<JoinMatchRelayed>, relayer_fee: u64) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
+        );
+        // USD matches must re-check the price through join_usd_match
+        require!(
+            match_account.stake_usd_cents == 0,
+            StakingError::WrongMatchKind
+        );
+        require!(
+            ctx.accounts.challenger.key() != match_account.host,
+            StakingError::CannotPlaySelf
+        );
+        match_account.check_invite(&ctx.accounts.challenger.key(), &[])?;
+        require!(
+            relayer_fee <= MAX_RELAYER_FEE_LAMPORTS,
+            StakingError::RelayerFeeTooHigh
+        );
+
+        let relayer = ctx.accounts.relayer.key();
+        require!(
+            relayer != ctx.accounts.challenger.key() && relayer != match_account.host,
+            StakingError::RelayerIsPlayer
+        );
+        require!(
+            match_account.relayer == Pubkey::default() || match_account.relayer == relayer,
+            StakingError::RelayerMismatch
+        );
+
+        match_account.challenger = ctx.accounts.challenger.key();
+        match_account.status = MatchStatus::InProgress;
+        match_account.relayer = relayer;
+        match_account.relayer_advanced = match_account
+            .relayer_advanced
+            .checked_add(relayer_fee)
+            .ok_or(StakingError::MathOverflow)?;
+        delist_from_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+        release_host_markup(match_account, ctx.accounts.host_backing_pool.as_mut())?;
+
+        // Transfer matching stake from challenger to escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.challenger.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            match_account.challenger_stake,
+        )?;
+
+        emit!(MatchStarted {
+            match_id: match_account.match_id,
+            host: match_account.host,
+            challenger: ctx.accounts.challenger.key(),
+            total_pot: match_account.total_pot()?,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a SOL match staked from the host's PlayerVault balance instead
+    /// of their wallet. Winnings and refunds go back to the vault.
+    pub fn create_match_from_vault(
+        ctx: Context<CreateMatchFromVault>,
+        match_id: [u8; 32],
+        stake_amount: u64,
+    ) -> Result<()> {
+        require!(
+            stake_amount >= MIN_STAKE_LAMPORTS,
+            StakingError::StakeTooLow
+        );
+        require!(
+            stake_amount <= MAX_STAKE_LAMPORTS,
+            StakingError::StakeTooHigh
+        );
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            host_uses_vault: true,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                stake_amount,
+                ctx.bumps.match_account,
+            )?
+        });
+
+        // Move the stake from the host's vault to escrow
+        debit_player_vault(
+            &ctx.accounts.player_vault,
+            &ctx.accounts.escrow_vault.to_account_info(),
+            stake_amount,
+        )?;
+
+        emit!(MatchCreated {
+            match_id,
+            host: ctx.accounts.host.key(),
+            stake_amount,
+            metadata: match_account.metadata,
+        });
+
+        Ok(())
+    }
+
+    /// Challenger joins an existing SOL match, staking from their PlayerVault.
+    /// A session key may sign instead, within its stake limits.
+    pub fn join_match_from_vault(ctx: Context<JoinMatchFromVault>) -> Result<()> {
+        let challenger = acting_player(
+            &ctx.accounts.challenger.key(),
+            ctx.accounts.session.as_deref(),
+        )?;
+        require_keys_eq!(
+            ctx.accounts.player_vault.owner,
+            challenger,
+            StakingError::WrongPlayerVault
+        );
+
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
+        );
+        // USD matches must re-check the price through join_usd_match
+        require!(
+            match_account.stake_usd_cents == 0,
+            StakingError::WrongMatchKind
+        );
+        require!(
+            challenger != match_account.host,
+            StakingError::CannotPlaySelf
+        );
+        match_account.check_invite(&challenger, &[])?;
+
+        if let Some(session) = ctx.accounts.session.as_mut() {
+            session.record_stake(match_account.challenger_stake)?;
+        }
+
+        match_account.challenger = challenger;
+        match_account.challenger_uses_vault = true;
+        match_account.status = MatchStatus::InProgress;
+        delist_from_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+        release_host_markup(match_account, ctx.accounts.host_backing_pool.as_mut())?;
+
+        // Move the challenger's stake from their vault to escrow
+        debit_player_vault(
+            &ctx.accounts.player_vault,
+            &ctx.accounts.escrow_vault.to_account_info(),
+            match_account.challenger_stake,
+        )?;
+
+        emit!(MatchStarted {
+            match_id: match_account.match_id,
+            host: match_account.host,
+            challenger,
+            total_pot: match_account.total_pot()?,
+        });
+
+        Ok(())
+    }
+
+    /// Opens a pool for backers to fund `player`'s stake in match `match_id`.
+    /// Backers buy shares of the `target` stake, paying `markup_bps` on top to
+    /// the player once the match starts.
+    pub fn open_backing_pool(
+        ctx: Context<OpenBackingPool>,
+        match_id: [u8; 32],
+        target: u64,
+        markup_bps: u16,
+    ) -> Result<()> {
+        require!(target >= MIN_STAKE_LAMPORTS, StakingError::StakeTooLow);
+        require!(target <= MAX_STAKE_LAMPORTS, StakingError::StakeTooHigh);
+        require!(
+            markup_bps <= MAX_BACKING_MARKUP_BPS,
+            StakingError::MarkupTooHigh
+        );
+
+        let backing_pool = &mut ctx.accounts.backing_pool;
+        backing_pool.match_id = match_id;
+        backing_pool.player = ctx.accounts.player.key();
+        backing_pool.target = target;
+        backing_pool.markup_bps = markup_bps;
+        backing_pool.total_backed = 0;
+        backing_pool.total_markup = 0;
+        backing_pool.markup_released = false;
+        backing_pool.returned = 0;
+        backing_pool.status = BackingStatus::Open;
+        backing_pool.match_created_at = 0;
+        backing_pool.open_backings = 0;
+        backing_pool.bump = ctx.bumps.backing_pool;
+
+        emit!(BackingPoolOpened {
+            match_id,
+            player: ctx.accounts.player.key(),
+            target,
+            markup_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Back a player with `amount` of their stake, plus the pool's markup.
+    /// Backing again tops up the same position.
+    pub fn back_player(ctx: Context<BackPlayer>, amount: u64) -> Result<()> {
+        let backing_pool = &mut ctx.accounts.backing_pool;
+
+        require!(
+            backing_pool.status == BackingStatus::Open,
+            StakingError::BackingClosed
+        );
+        require!(amount > 0, StakingError::StakeTooLow);
+        let total_backed = backing_pool
+            .total_backed
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        require!(
+            total_backed <= backing_pool.target,
+            StakingError::StakeTooHigh
+        );
+
+        let markup = platform_fee(amount, backing_pool.markup_bps as u64);
+        let paid = amount
+            .checked_add(markup)
+            .ok_or(StakingError::MathOverflow)?;
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.backer.to_account_info(),
+                    to: backing_pool.to_account_info(),
+                },
+            ),
+            paid,
+        )?;
+
+        let backing = &mut ctx.accounts.backing;
+        if backing.amount == 0 {
+            backing.match_id = backing_pool.match_id;
+            backing.player = backing_pool.player;
+            backing.backer = ctx.accounts.backer.key();
+            backing.bump = ctx.bumps.backing;
+            backing_pool.open_backings += 1;
+        }
+        backing.amount = backing
+            .amount
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        backing.markup_paid = backing
+            .markup_paid
+            .checked_add(markup)
+            .ok_or(StakingError::MathOverflow)?;
+        backing_pool.total_backed = total_backed;
+        backing_pool.total_markup = backing_pool
+            .total_markup
+            .checked_add(markup)
+            .ok_or(StakingError::MathOverflow)?;
+
+        emit!(PlayerBacked {
+            match_id: backing_pool.match_id,
+            player: backing_pool.player,
+            backer: ctx.accounts.backer.key(),
+            amount,
+            markup,
+        });
+
+        Ok(())
+    }
+
+    /// Call off a backing pool before its stake is placed. Backers get back
+    /// what they paid, markup included.
+    pub fn cancel_backing_pool(ctx: Context<CancelBackingPool>) -> Result<()> {
+        let backing_pool = &mut ctx.accounts.backing_pool;
+
+        require!(
+            backing_pool.status == BackingStatus::Open,
+            StakingError::BackingClosed
+        );
+
+        backing_pool.status = BackingStatus::Cancelled;
+
+        Ok(())
+    }
+
+    /// Creates a SOL match staked with the host's fully funded BackingPool.
+    /// Winnings and refunds go back to the pool for backers to claim.
+    pub fn create_backed_match(ctx: Context<CreateBackedMatch>, match_id: [u8; 32]) -> Result<()> {
+        let backing_pool = &mut ctx.accounts.backing_pool;
+
+        require!(
+            backing_pool.status == BackingStatus::Open,
+            StakingError::BackingClosed
+        );
+        require!(
+            backing_pool.total_backed == backing_pool.target,
+            StakingError::BackingNotFunded
+        );
+        let stake_amount = backing_pool.target;
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            host_backed: true,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                stake_amount,
+                ctx.bumps.match_account,
+            )?
+        });
+
+        place_backed_stake(
+            backing_pool,
+            &ctx.accounts.escrow_vault.to_account_info(),
+            match_account.created_at,
+        )?;
+
+        emit!(MatchCreated {
+            match_id,
+            host: ctx.accounts.host.key(),
+            stake_amount,
+            metadata: match_account.metadata,
+        });
+
+        Ok(())
+    }
+
+    /// Challenger joins an existing SOL match, staking with their fully
+    /// funded BackingPool. The pool must hold exactly the challenger's stake.
+    pub fn join_backed_match(ctx: Context<JoinBackedMatch>) -> Result<()> {
+        let challenger = ctx.accounts.challenger.key();
+        let match_account = &mut ctx.accounts.match_account;
+        let backing_pool = &mut ctx.accounts.backing_pool;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
+        );
+        // USD matches must re-check the price through join_usd_match
+        require!(
+            match_account.stake_usd_cents == 0,
+            StakingError::WrongMatchKind
+        );
+        require!(
+            challenger != match_account.host,
+            StakingError::CannotPlaySelf
+        );
+        match_account.check_invite(&challenger, &[])?;
+        require!(
+            backing_pool.status == BackingStatus::Open,
+            StakingError::BackingClosed
+        );
+        require!(
+            backing_pool.total_backed == backing_pool.target
+                && backing_pool.target == match_account.challenger_stake,
+            StakingError::BackingNotFunded
+        );
+
+        match_account.challenger = challenger;
+        match_account.challenger_backed = true;
+        match_account.status = MatchStatus::InProgress;
+        delist_from_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+        release_host_markup(match_account, ctx.accounts.host_backing_pool.as_mut())?;
+
+        place_backed_stake(
+            backing_pool,
+            &ctx.accounts.escrow_vault.to_account_info(),
+            match_account.created_at,
+        )?;
+        backing_pool.markup_released = true;
+
+        emit!(MatchStarted {
+            match_id: match_account.match_id,
+            host: match_account.host,
+            challenger,
+            total_pot: match_account.total_pot()?,
+        });
+
+        Ok(())
+    }
+
+    /// Record what a backed side got back once its match is over: the
+    /// winnings, draw refund or cancel refund paid into the pool. Anyone can
+    /// call this after the match is decided or closed.
+    pub fn settle_backing_pool(ctx: Context<SettleBackingPool>) -> Result<()> {
+        let backing_pool = &mut ctx.accounts.backing_pool;
+
+        require!(
+            backing_pool.status == BackingStatus::Staked,
+            StakingError::BackingClosed
+        );
+
+        let info = ctx.accounts.match_account.to_account_info();
+        if !info.data_is_empty() {
+            let match_account = MatchAccount::load_any(&info)?;
+            // A closed and reused match id is a different match
+            require!(
+                match_account.is_finished()
+                    || match_account.created_at != backing_pool.match_created_at,
+                StakingError::MatchNotFinished
+            );
+        }
+
+        // The markup is held apart: the player's if the match started, else the backers'
+        backing_pool.returned = PlayerVault::available_balance(&backing_pool.to_account_info())?
+            .saturating_sub(backing_pool.total_markup);
+        backing_pool.status = BackingStatus::Settled;
+
+        emit!(BackingPoolSettled {
+            match_id: backing_pool.match_id,
+            player: backing_pool.player,
+            total_backed: backing_pool.total_backed,
+            returned: backing_pool.returned,
+        });
+
+        Ok(())
+    }
+
+    /// Pay a backer their pro-rata share of what the pool got back (or their
+    /// full payment if the pool was cancelled), closing their Backing account.
+    /// Markup is refunded too if the match never started. Anyone can call this
+    /// on the backer's behalf.
+    pub fn claim_backing(ctx: Context<ClaimBacking>) -> Result<()> {
+        let backing_pool = &mut ctx.accounts.backing_pool;
+        let backing = &ctx.accounts.backing;
+
+        let amount = match backing_pool.status {
+            BackingStatus::Settled => {
+                let share = (backing_pool.returned as u128 * backing.amount as u128
+                    / backing_pool.total_backed as u128) as u64;
+                if backing_pool.markup_released {
+                    share
+                } else {
+                    share + backing.markup_paid
+                }
+            }
+            BackingStatus::Cancelled => backing.amount + backing.markup_paid,
+            _ => return err!(StakingError::BackingNotSettled),
+        };
+
+        backing_pool.sub_lamports(amount)?;
+        ctx.accounts.backer.add_lamports(amount)?;
+        backing_pool.open_backings -= 1;
+
+        emit!(BackingClaimed {
+            match_id: backing_pool.match_id,
+            player: backing_pool.player,
+            backer: backing.backer,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Close a backing pool once every backer has claimed, paying the player
+    /// the markup if their match started, plus the rent and any rounding dust
+    pub fn close_backing_pool(ctx: Context<CloseBackingPool>) -> Result<()> {
+        let backing_pool = &ctx.accounts.backing_pool;
+
+        require!(
+            matches!(
+                backing_pool.status,
+                BackingStatus::Settled | BackingStatus::Cancelled
+            ),
+            StakingError::BackingNotSettled
+        );
+        require!(
+            backing_pool.open_backings == 0,
+            StakingError::BackersOutstanding
+        );
+
+        Ok(())
+    }
+
+    /// Authorize an ephemeral key to join from the PlayerVault, report results
+    /// and claim on the owner's behalf until `expires_at`, at most
+    /// MAX_SESSION_SECONDS away. An earlier session must be revoked first.
+    pub fn create_session(
+        ctx: Context<CreateSession>,
+        session_key: Pubkey,
+        expires_at: i64,
+        max_stake_per_match: u64,
+        spend_cap: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(expires_at > now, StakingError::SessionExpired);
+        require!(
+            expires_at <= now + MAX_SESSION_SECONDS,
+            StakingError::SessionTooLong
+        );
+
+        let session = &mut ctx.accounts.session;
+        session.owner = ctx.accounts.owner.key();
+        session.session_key = session_key;
+        session.expires_at = expires_at;
+        session.max_stake_per_match = max_stake_per_match;
+        session.spend_cap = spend_cap;
+        session.spent = 0;
+        session.bump = ctx.bumps.session;
+
+        emit!(SessionCreated {
+            owner: session.owner,
+            session_key,
+            expires_at,
+            max_stake_per_match,
+            spend_cap,
+        });
+
+        Ok(())
+    }
+
+    /// Revoke the owner's session key right away (rent back to the owner)
+    pub fn revoke_session(ctx: Context<RevokeSession>) -> Result<()> {
+        emit!(SessionRevoked {
+            owner: ctx.accounts.owner.key(),
+            session_key: ctx.accounts.session.session_key,
+        });
+
+        Ok(())
+    }
+
+    /// Add SOL to the caller's PlayerVault, opening it on first deposit
+    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
+        let player_vault = &mut ctx.accounts.player_vault;
+        player_vault.owner = ctx.accounts.owner.key();
+        player_vault.bump = ctx.bumps.player_vault;
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.owner.to_account_info(),
+                    to: ctx.accounts.player_vault.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        emit!(VaultDeposited {
+            owner: ctx.accounts.owner.key(),
+            amount,
+            balance: PlayerVault::available_balance(&ctx.accounts.player_vault.to_account_info())?,
+        });
+
+        Ok(())
+    }
+
+    /// Take SOL out of the caller's PlayerVault back to their wallet
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        debit_player_vault(
+            &ctx.accounts.player_vault,
+            &ctx.accounts.owner.to_account_info(),
+            amount,
+        )?;
+
+        emit!(VaultWithdrawn {
+            owner: ctx.accounts.owner.key(),
+            amount,
+            balance: PlayerVault::available_balance(&ctx.accounts.player_vault.to_account_info())?,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a SOL match whose stake is given in US cents. The lamport
+    /// amount is locked at the current SOL/USD price from the configured feed.
+    pub fn create_usd_match(
+        ctx: Context<CreateUsdMatch>,
+        match_id: [u8; 32],
+        stake_usd_cents: u64,
+    ) -> Result<()> {
+        let price = PriceFeed::load(&ctx.accounts.price_feed)?;
+        let stake_amount = price.usd_cents_to_lamports(stake_usd_cents)?;
+        require!(
+            stake_amount >= MIN_STAKE_LAMPORTS,
+            StakingError::StakeTooLow
+        );
+        require!(
+            stake_amount <= MAX_STAKE_LAMPORTS,
+            StakingError::StakeTooHigh
+        );
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            stake_usd_cents,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                stake_amount,
+                ctx.bumps.match_account,
+            )?
+        });
+
+        // Transfer stake from host to escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.host.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            stake_amount,
+        )?;
+
+        emit!(UsdMatchCreated {
+            match_id,
+            host: ctx.accounts.host.key(),
+            stake_usd_cents,
+            stake_amount,
+            price: price.price,
+            expo: price.expo,
+        });
+
+        Ok(())
+    }
+
+    /// Joins a USD match. The challenger stakes the same locked lamports as
+    /// the host, provided the current price hasn't moved them too far from
+    /// the match's USD value.
+    pub fn join_usd_match(ctx: Context<JoinUsdMatch>) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            ctx.accounts.challenger.key() != match_account.host,
+            StakingError::CannotPlaySelf
+        );
+        match_account.check_invite(&ctx.accounts.challenger.key(), &[])?;
+
+        let price = PriceFeed::load(&ctx.accounts.price_feed)?;
+        let current_amount = price.usd_cents_to_lamports(match_account.stake_usd_cents)?;
+        let drift = current_amount.abs_diff(match_account.stake_amount) as u128;
+        require!(
+            drift * 10_000 <= match_account.stake_amount as u128 * PRICE_MAX_DRIFT_BPS as u128,
+            StakingError::PriceMoved
+        );
+
+        match_account.challenger = ctx.accounts.challenger.key();
+        match_account.status = MatchStatus::InProgress;
+        delist_from_lobby(ctx.accounts.lobby.as_mut(), match_account)?;
+
+        // Transfer matching stake from challenger to escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.challenger.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            match_account.challenger_stake,
+        )?;
+
+        emit!(MatchStarted {
+            match_id: match_account.match_id,
+            host: match_account.host,
+            challenger: ctx.accounts.challenger.key(),
+            total_pot: match_account.total_pot()?,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a match wagering NFTs instead of a stake. The host escrows one
+    /// NFT (optionally required to be in a verified collection) plus the flat
+    /// SOL fee; the winner takes both NFTs.
+    pub fn create_nft_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreateNftMatch<'info>>,
+        match_id: [u8; 32],
+        collection: Option<Pubkey>,
+    ) -> Result<()> {
+        let nft_collection = collection.unwrap_or_default();
+        verify_wager_nft(
+            &ctx.accounts.host_nft_mint,
+            ctx.accounts.host_nft_metadata.as_deref(),
+            nft_collection,
+        )?;
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            kind: MatchKind::Nft,
+            fee_bps: 0,
+            host_nft_mint: ctx.accounts.host_nft_mint.key(),
+            nft_collection,
+            flat_fee_lamports: NFT_MATCH_FEE_LAMPORTS,
+            ..MatchAccount::new(
+                match_id,
+                ctx.accounts.host.key(),
+                0,
+                ctx.bumps.match_account,
+            )?
+        });
+
+        // Escrow the host's NFT
+        transfer_stake_tokens(
+            &ctx.accounts.token_program,
+            ctx.accounts.host_nft_account.to_account_info(),
+            &ctx.accounts.host_nft_mint,
+            ctx.accounts.host_nft_escrow.to_account_info(),
+            ctx.accounts.host.to_account_info(),
+            ctx.remaining_accounts,
+            1,
+            &[],
+        )?;
+
+        // Transfer the host's flat fee to the SOL escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.host.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            NFT_MATCH_FEE_LAMPORTS,
+        )?;
+
+        emit!(NftMatchCreated {
+            match_id,
+            host: ctx.accounts.host.key(),
+            host_nft_mint: ctx.accounts.host_nft_mint.key(),
+            collection: nft_collection,
+            flat_fee_lamports: NFT_MATCH_FEE_LAMPORTS,
+        });
+
+        Ok(())
+    }
+
+    /// Challenger joins an NFT match by escrowing their own NFT and the flat
+    /// fee. Session keys can't sign this: they can't move the challenger's NFT.
+    pub fn join_nft_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, JoinNftMatch<'info>>,
+    ) -> Result<()> {
+        let match_account = &ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            ctx.accounts.challenger.key() != match_account.host,
+            StakingError::CannotPlaySelf
+        );
+        match_account.check_invite(&ctx.accounts.challenger.key(), &[])?;
+        verify_wager_nft(
+            &ctx.accounts.challenger_nft_mint,
+            ctx.accounts.challenger_nft_metadata.as_deref(),
+            match_account.nft_collection,
+        )?;
+
+        let flat_fee_lamports = match_account.flat_fee_lamports;
+
+        // Escrow the challenger's NFT
+        transfer_stake_tokens(
+            &ctx.accounts.token_program,
+            ctx.accounts.challenger_nft_account.to_account_info(),
+            &ctx.accounts.challenger_nft_mint,
+            ctx.accounts.challenger_nft_escrow.to_account_info(),
+            ctx.accounts.challenger.to_account_info(),
+            ctx.remaining_accounts,
+            1,
+            &[],
+        )?;
+
+        // Transfer the challenger's flat fee to the SOL escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.challenger.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            flat_fee_lamports,
+        )?;
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.challenger = ctx.accounts.challenger.key();
+        match_account.challenger_nft_mint = ctx.accounts.challenger_nft_mint.key();
+        match_account.status = MatchStatus::InProgress;
+
+        emit!(MatchStarted {
+            match_id: match_account.match_id,
+            host: match_account.host,
+            challenger: ctx.accounts.challenger.key(),
+            total_pot: 0,
+        });
+
+        Ok(())
+    }
+
+    /// NFT counterpart of claim_winner_reward: both NFTs go to the winner and
+    /// both flat fees to the treasury
+    pub fn claim_nft_winner_reward<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimNftWinnerReward<'info>>,
+        winner: Pubkey,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+        require!(
+            winner == match_account.host || winner == match_account.challenger,
+            StakingError::InvalidWinner
+        );
+
+        // Verify caller is a participant
+        let caller = acting_player(&ctx.accounts.caller.key(), ctx.accounts.session.as_deref())?;
+        require!(
+            caller == match_account.host || caller == match_account.challenger,
+            StakingError::NotParticipant
+        );
+
+        match_account.winner = winner;
+        match_account.finish(MatchStatus::Settled)?;
+
+        let platform_fee = match_account.flat_fee_lamports * 2;
+        let match_id = match_account.match_id;
+        let match_bump = match_account.bump;
+        let match_info = match_account.to_account_info();
+
+        // Both NFTs go to the winner
+        for (nft_escrow, nft_mint, winner_nft_account) in [
+            (
+                &ctx.accounts.host_nft_escrow,
+                &ctx.accounts.host_nft_mint,
+                &ctx.accounts.winner_host_nft_account,
+            ),
+            (
+                &ctx.accounts.challenger_nft_escrow,
+                &ctx.accounts.challenger_nft_mint,
+                &ctx.accounts.winner_challenger_nft_account,
+            ),
+        ] {
+            transfer_from_token_escrow(
+                &ctx.accounts.token_program,
+                nft_escrow,
+                nft_mint,
+                winner_nft_account.to_account_info(),
+                match_info.clone(),
+                ctx.remaining_accounts,
+                &match_id,
+                match_bump,
+                1,
+            )?;
+            close_token_escrow(
+                &ctx.accounts.token_program,
+                nft_escrow,
+                nft_mint,
+                ctx.accounts.caller.to_account_info(),
+                match_info.clone(),
+                &match_id,
+                match_bump,
+            )?;
+        }
+
+        // Flat fees to the treasury, any dust to the winner
+        let escrow_bump = ctx.bumps.escrow_vault;
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            escrow_bump,
+            platform_fee,
+        )?;
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.winner_account.to_account_info(),
+            &match_id,
+            escrow_bump,
+            ctx.accounts.escrow_vault.lamports(),
+        )?;
+
+        emit!(NftRewardClaimed {
+            match_id,
+            winner,
+            host_nft_mint: ctx.accounts.host_nft_mint.key(),
+            challenger_nft_mint: ctx.accounts.challenger_nft_mint.key(),
+            platform_fee,
+        });
+
+        Ok(())
+    }
+
+    /// NFT counterpart of accept_draw: each player gets their NFT and flat fee back
+    pub fn accept_nft_draw<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AcceptNftDraw<'info>>,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+
+        let caller = acting_player(&ctx.accounts.caller.key(), ctx.accounts.session.as_deref())?;
+        require!(
+            caller == match_account.host || caller == match_account.challenger,
+            StakingError::NotParticipant
+        );
+        require!(
+            match_account.draw_offered_by != Pubkey::default(),
+            StakingError::NoDrawOffer
+        );
+        require!(
+            match_account.draw_offered_by != caller,
+            StakingError::CannotAcceptOwnDrawOffer
+        );
+        require!(
+            match_account.draw_offer_expires_at >= Clock::get()?.unix_timestamp,
+            StakingError::DrawOfferExpired
+        );
+
+        match_account.finish(MatchStatus::Draw)?;
+        let flat_fee_lamports = match_account.flat_fee_lamports;
+        let match_id = match_account.match_id;
+        let reason = match_account.draw_reason;
+        let match_bump = match_account.bump;
+        let match_info = match_account.to_account_info();
+
+        // Each NFT goes back to its owner
+        for (nft_escrow, nft_mint, owner_nft_account) in [
+            (
+                &ctx.accounts.host_nft_escrow,
+                &ctx.accounts.host_nft_mint,
+                &ctx.accounts.host_nft_account,
+            ),
+            (
+                &ctx.accounts.challenger_nft_escrow,
+                &ctx.accounts.challenger_nft_mint,
+                &ctx.accounts.challenger_nft_account,
+            ),
+        ] {
+            transfer_from_token_escrow(
+                &ctx.accounts.token_program,
+                nft_escrow,
+                nft_mint,
+                owner_nft_account.to_account_info(),
+                match_info.clone(),
+                ctx.remaining_accounts,
+                &match_id,
+                match_bump,
+                1,
+            )?;
+            close_token_escrow(
+                &ctx.accounts.token_program,
+                nft_escrow,
+                nft_mint,
+                ctx.accounts.caller.to_account_info(),
+                match_info.clone(),
+                &match_id,
+                match_bump,
+            )?;
+        }
+
+        // Refund both flat fees, sweep any remainder to the treasury
+        let escrow_bump = ctx.bumps.escrow_vault;
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.host_account.to_account_info(),
+            &match_id,
+            escrow_bump,
+            flat_fee_lamports,
+        )?;
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.challenger_account.to_account_info(),
+            &match_id,
+            escrow_bump,
+            flat_fee_lamports,
+        )?;
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            escrow_bump,
+            ctx.accounts.escrow_vault.lamports(),
+        )?;
+
+        emit!(MatchDraw {
+            match_id,
+            refund_amount: flat_fee_lamports,
+            challenger_refund_amount: flat_fee_lamports,
+            reason,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel an NFT match before challenger joins (return the host's NFT and fee)
+    pub fn cancel_nft_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelNftMatch<'info>>,
+    ) -> Result<()> {
+        let match_account = &ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::CannotCancelStartedMatch
+        );
+        require!(
+            ctx.accounts.host.key() == match_account.host,
+            StakingError::NotHost
+        );
+
+        let flat_fee_lamports = match_account.flat_fee_lamports;
+        let match_id = match_account.match_id;
+        let match_bump = match_account.bump;
+        let match_info = match_account.to_account_info();
+
+        transfer_from_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.host_nft_escrow,
+            &ctx.accounts.host_nft_mint,
+            ctx.accounts.host_nft_account.to_account_info(),
+            match_info.clone(),
+            ctx.remaining_accounts,
+            &match_id,
+            match_bump,
+            1,
+        )?;
+        close_token_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.host_nft_escrow,
+            &ctx.accounts.host_nft_mint,
+            ctx.accounts.host.to_account_info(),
+            match_info,
+            &match_id,
+            match_bump,
+        )?;
+
+        // Refund the host's fee, sweep any remainder to the treasury
+        let escrow_bump = ctx.bumps.escrow_vault;
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.host.to_account_info(),
+            &match_id,
+            escrow_bump,
+            flat_fee_lamports,
+        )?;
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            escrow_bump,
+            ctx.accounts.escrow_vault.lamports(),
+        )?;
+
+        emit!(MatchCancelled {
+            match_id,
+            refunded_to: ctx.accounts.host.key(),
+            amount: flat_fee_lamports,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a best-of-N series (N odd) between the host and a challenger.
+    /// Both stakes are escrowed once for the whole series.
+    pub fn create_series(
+        ctx: Context<CreateSeries>,
+        series_id: [u8; 32],
+        stake_amount: u64,
+        best_of: u8,
+    ) -> Result<()> {
+        require!(
+            stake_amount >= MIN_STAKE_LAMPORTS,
+            StakingError::StakeTooLow
+        );
+        require!(
+            stake_amount <= MAX_STAKE_LAMPORTS,
+            StakingError::StakeTooHigh
+        );
+        require!(
+            (1..=MAX_SERIES_GAMES).contains(&best_of) && best_of % 2 == 1,
+            StakingError::InvalidSeriesLength
+        );
+
+        let series = &mut ctx.accounts.series;
+        series.series_id = series_id;
+        series.host = ctx.accounts.host.key();
+        series.challenger = Pubkey::default();
+        series.stake_amount = stake_amount;
+        series.best_of = best_of;
+        series.games_played = 0;
+        series.host_wins = 0;
+        series.challenger_wins = 0;
+        series.status = MatchStatus::WaitingForChallenger;
+        series.winner = Pubkey::default();
+        series.fee_bps = PLATFORM_FEE_BPS as u16;
+        series.created_at = Clock::get()?.unix_timestamp;
+        series.bump = ctx.bumps.series;
+
+        // Transfer stake from host to the series escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.host.to_account_info(),
+                    to: ctx.accounts.series_escrow.to_account_info(),
+                },
+            ),
+            stake_amount,
+        )?;
+
+        emit!(SeriesCreated {
+            series_id,
+            host: ctx.accounts.host.key(),
+            stake_amount,
+            best_of,
+        });
+
+        Ok(())
+    }
+
+    /// Challenger joins a series by staking the same amount
+    pub fn join_series(ctx: Context<JoinSeries>) -> Result<()> {
+        let series = &mut ctx.accounts.series;
+
+        require!(
+            series.status == MatchStatus::WaitingForChallenger,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            ctx.accounts.challenger.key() != series.host,
+            StakingError::CannotPlaySelf
+        );
+
+        series.challenger = ctx.accounts.challenger.key();
+        series.status = MatchStatus::InProgress;
+
+        // Transfer matching stake from challenger to the series escrow
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.challenger.to_account_info(),
+                    to: ctx.accounts.series_escrow.to_account_info(),
+                },
+            ),
+            series.stake_amount,
+        )?;
+
+        emit!(SeriesStarted {
+            series_id: series.series_id,
+            host: series.host,
+            challenger: ctx.accounts.challenger.key(),
+            total_pot: series.stake_amount * 2,
+        });
+
+        Ok(())
+    }
+
+    /// Records one game of a series, reported by a participant like
+    /// claim_winner_reward (`None` for a drawn game). Once a player clinches
+    /// the series, or all games are played, the escrow is paid out: the
+    /// series leader takes the pot minus the platform fee, and a tied series
+    /// refunds both stakes.
+    pub fn record_series_game(
+        ctx: Context<RecordSeriesGame>,
+        winner: Option<Pubkey>,
+    ) -> Result<()> {
+        let series = &mut ctx.accounts.series;
+
+        require!(
+            series.status == MatchStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+
+        let caller = ctx.accounts.caller.key();
+        require!(
+            caller == series.host || caller == series.challenger,
+            StakingError::NotParticipant
+        );
+
+        match winner {
+            Some(winner) if winner == series.host => series.host_wins += 1,
+            Some(winner) if winner == series.challenger => series.challenger_wins += 1,
+            Some(_) => return err!(StakingError::InvalidWinner),
+            None => {}
+        }
+        series.games_played += 1;
+
+        emit!(SeriesGameRecorded {
+            series_id: series.series_id,
+            game: series.games_played,
+            winner: winner.unwrap_or_default(),
+            host_wins: series.host_wins,
+            challenger_wins: series.challenger_wins,
+        });
+
+        if !series.is_decided() {
+            return Ok(());
+        }
+
+        let series_id = series.series_id;
+        let escrow_bump = ctx.bumps.series_escrow;
+
+        let Some(series_winner) = series.leader() else {
+            // Tied at the end - refund both stakes
+            series.status = MatchStatus::Draw;
+            let stake_amount = series.stake_amount;
+
+            for player in [&ctx.accounts.host_account, &ctx.accounts.challenger_account] {
+                transfer_from_series_escrow(
+                    &ctx.accounts.system_program,
+                    &ctx.accounts.series_escrow,
+                    player.to_account_info(),
+                    &series_id,
+                    escrow_bump,
+                    stake_amount,
+                )?;
+            }
+
+            // Sweep any remainder to the treasury so the escrow is fully drained
+            transfer_from_series_escrow(
+                &ctx.accounts.system_program,
+                &ctx.accounts.series_escrow,
+                ctx.accounts.platform_treasury.to_account_info(),
+                &series_id,
+                escrow_bump,
+                ctx.accounts.series_escrow.lamports(),
+            )?;
+
+            emit!(SeriesSettled {
+                series_id,
+                winner: Pubkey::default(),
+                amount: stake_amount,
+                platform_fee: 0,
+            });
+
+            return Ok(());
+        };
+
+        series.winner = series_winner;
+        series.status = MatchStatus::Settled;
+
+        let total_pot = series.stake_amount * 2;
+        let platform_fee = platform_fee(total_pot, series.fee_bps as u64);
+
+        // Transfer platform fee to treasury
+        transfer_from_series_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.series_escrow,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &series_id,
+            escrow_bump,
+            platform_fee,
+        )?;
+
+        // Series winner sweeps the rest of the escrow
+        let winner_account = if series_winner == ctx.accounts.host_account.key() {
+            &ctx.accounts.host_account
+        } else {
+            &ctx.accounts.challenger_account
+        };
+        let winner_reward = ctx.accounts.series_escrow.lamports();
+        transfer_from_series_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.series_escrow,
+            winner_account.to_account_info(),
+            &series_id,
+            escrow_bump,
+            winner_reward,
+        )?;
+
+        emit!(SeriesSettled {
+            series_id,
+            winner: series_winner,
+            amount: winner_reward,
+            platform_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel a series before a challenger joins (refund host)
+    pub fn cancel_series(ctx: Context<CancelSeries>) -> Result<()> {
+        let series = &ctx.accounts.series;
+
+        require!(
+            series.status == MatchStatus::WaitingForChallenger,
+            StakingError::CannotCancelStartedMatch
+        );
+
+        let stake_amount = series.stake_amount;
+        let series_id = series.series_id;
+        let escrow_bump = ctx.bumps.series_escrow;
+
+        // Refund host using PDA signature
+        transfer_from_series_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.series_escrow,
+            ctx.accounts.host.to_account_info(),
+            &series_id,
+            escrow_bump,
+            stake_amount,
+        )?;
+
+        // Anything else in the escrow was never staked - sweep it to the treasury
+        transfer_from_series_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.series_escrow,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &series_id,
+            escrow_bump,
+            ctx.accounts.series_escrow.lamports(),
+        )?;
+
+        emit!(MatchCancelled {
+            match_id: series_id,
+            refunded_to: ctx.accounts.host.key(),
+            amount: stake_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Close a finished series and return its rent to the host, who paid it
+    pub fn close_series(ctx: Context<CloseSeries>) -> Result<()> {
+        let series = &ctx.accounts.series;
+
+        require!(
+            series.status == MatchStatus::Settled || series.status == MatchStatus::Draw,
+            StakingError::MatchNotFinished
+        );
+
+        let caller = ctx.accounts.caller.key();
+        require!(
+            caller == series.host || caller == series.challenger,
+            StakingError::NotParticipant
+        );
+
+        Ok(())
+    }
+
+    /// Creates a single-elimination tournament for `size` players (a power of
+    /// two). `schedule` splits the prize pool by placement: champion,
+    /// runner-up, then the semifinal losers in bracket order, and so on. If
+    /// there is no champion by `settle_by`, anyone can refund the entrants.
+    ///
+    /// Bracket games are played off-chain: the program announces the
+    /// pairings and takes organizer-reported results, it doesn't create
+    /// staked matches for them.
+    pub fn create_tournament(
+        ctx: Context<CreateTournament>,
+        tournament_id: [u8; 32],
+        entry_fee: u64,
+        size: u8,
+        settle_by: i64,
+    ) -> Result<()> {
+        require!(entry_fee >= MIN_STAKE_LAMPORTS, StakingError::StakeTooLow);
+        require!(entry_fee <= MAX_STAKE_LAMPORTS, StakingError::StakeTooHigh);
+        require!(
+            (2..=MAX_TOURNAMENT_SIZE).contains(&size) && size.is_power_of_two(),
+            StakingError::InvalidTournamentSize
+        );
+        require!(
+            ctx.accounts.schedule.shares_for(size as usize).len() <= size as usize,
+            StakingError::InvalidPayoutSchedule
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(settle_by > now, StakingError::InvalidSettleDeadline);
+
+        let tournament = &mut ctx.accounts.tournament;
+        tournament.tournament_id = tournament_id;
+        tournament.organizer = ctx.accounts.organizer.key();
+        tournament.entry_fee = entry_fee;
+        tournament.size = size;
+        tournament.schedule = ctx.accounts.schedule.key();
+        tournament.status = TournamentStatus::Registration;
+        tournament.round = 0;
+        tournament.players = Vec::new();
+        tournament.alive = Vec::new();
+        tournament.round_winners = Vec::new();
+        tournament.placements = Vec::new();
+        tournament.fee_bps = PLATFORM_FEE_BPS as u16;
+        tournament.created_at = now;
+        tournament.settle_by = settle_by;
+        tournament.bump = ctx.bumps.tournament;
+
+        emit!(TournamentCreated {
+            tournament_id,
+            organizer: ctx.accounts.organizer.key(),
+            entry_fee,
+            size,
+            schedule: ctx.accounts.schedule.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Register for a tournament by paying the entry fee into its escrow. The
+    /// bracket starts, seeded in registration order, once the last seat fills.
+    pub fn register_for_tournament(ctx: Context<RegisterForTournament>) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        let player = ctx.accounts.player.key();
+
+        require!(
+            tournament.status == TournamentStatus::Registration,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            !tournament.players.contains(&player),
+            StakingError::AlreadyRegistered
+        );
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.player.to_account_info(),
+                    to: ctx.accounts.tournament_escrow.to_account_info(),
+                },
+            ),
+            tournament.entry_fee,
+        )?;
+
+        tournament.players.push(player);
+
+        emit!(TournamentRegistered {
+            tournament_id: tournament.tournament_id,
+            player,
+            entrants: tournament.players.len() as u8,
+        });
+
+        if tournament.players.len() == tournament.size as usize {
+            tournament.status = TournamentStatus::InProgress;
+            tournament.alive = tournament.players.clone();
+            tournament.start_round();
+        }
+
+        Ok(())
+    }
+
+    /// Report the winner of bracket match `match_index` in the current round.
+    /// The organizer reports results; a player can also attest their own loss.
+    /// When the final is reported the prize pool is paid out - pass the
+    /// placement wallets (see `schedule`) as remaining accounts.
+    pub fn report_tournament_result<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReportTournamentResult<'info>>,
+        match_index: u8,
+        winner: Pubkey,
+    ) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+
+        require!(
+            tournament.status == TournamentStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+
+        let index = match_index as usize;
+        require!(
+            index < tournament.round_winners.len(),
+            StakingError::InvalidBracketMatch
+        );
+        require!(
+            tournament.round_winners[index] == Pubkey::default(),
+            StakingError::ResultAlreadyReported
+        );
+
+        let (player_a, player_b) = (tournament.alive[2 * index], tournament.alive[2 * index + 1]);
+        let loser = if winner == player_a {
+            player_b
+        } else if winner == player_b {
+            player_a
+        } else {
+            return err!(StakingError::InvalidWinner);
+        };
+
+        let reporter = ctx.accounts.reporter.key();
+        require!(
+            reporter == tournament.organizer || reporter == loser,
+            StakingError::NotOrganizer
+        );
+
+        tournament.round_winners[index] = winner;
+
+        emit!(TournamentResultReported {
+            tournament_id: tournament.tournament_id,
+            round: tournament.round,
+            match_index,
+            winner,
+            loser,
+        });
+
+        if tournament.round_winners.contains(&Pubkey::default()) {
+            return Ok(());
+        }
+
+        // Round complete - this round's losers rank above everyone out earlier
+        let winners = std::mem::take(&mut tournament.round_winners);
+        let losers: Vec<Pubkey> = tournament
+            .alive
+            .iter()
+            .filter(|player| !winners.contains(player))
+            .copied()
+            .collect();
+        tournament.placements.splice(0..0, losers);
+
+        if winners.len() > 1 {
+            tournament.alive = winners;
+            tournament.round += 1;
+            tournament.start_round();
+            return Ok(());
+        }
+
+        tournament.placements.insert(0, winners[0]);
+        tournament.alive = winners;
+        tournament.status = TournamentStatus::Completed;
+
+        // Pay the prize pool out by placement
+        let tournament_id = tournament.tournament_id;
+        let shares = ctx.accounts.schedule.shares_for(tournament.size as usize);
+        let escrow_seeds: &[&[u8]] = &[
+            b"tournament_escrow",
+            tournament_id.as_ref(),
+            &[ctx.bumps.tournament_escrow],
+        ];
+        let (prizes, platform_fee) = pay_prizes(
+            &ctx.accounts.system_program,
+            &ctx.accounts.tournament_escrow,
+            escrow_seeds,
+            ctx.accounts.platform_treasury.to_account_info(),
+            tournament.fee_bps,
+            tournament_id,
+            &tournament.placements,
+            &shares,
+            ctx.remaining_accounts,
+        )?;
+
+        emit!(TournamentCompleted {
+            tournament_id,
+            champion: tournament.placements[0],
+            prize_pool: prizes,
+            platform_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel a tournament that hasn't filled up, refunding every entry fee.
+    /// Pass the registered players' wallets as remaining accounts, in
+    /// registration order.
+    pub fn cancel_tournament<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelTournament<'info>>,
+    ) -> Result<()> {
+        let tournament = &ctx.accounts.tournament;
+
+        require!(
+            tournament.status == TournamentStatus::Registration,
+            StakingError::CannotCancelStartedMatch
+        );
+
+        let tournament_id = tournament.tournament_id;
+        refund_entrants(
+            &ctx.accounts.system_program,
+            &ctx.accounts.tournament_escrow,
+            &[
+                b"tournament_escrow",
+                tournament_id.as_ref(),
+                &[ctx.bumps.tournament_escrow],
+            ],
+            ctx.accounts.platform_treasury.to_account_info(),
+            &tournament.players,
+            ctx.remaining_accounts,
+            tournament.entry_fee,
+        )?;
+
+        emit!(TournamentCancelled {
+            tournament_id,
+            refunded_players: tournament.players.len() as u8,
+            entry_fee: tournament.entry_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Refund every entry fee of a tournament that has no champion by its
+    /// `settle_by` deadline. Anyone can call this; pass the registered
+    /// players' wallets as remaining accounts, in registration order.
+    pub fn expire_tournament<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExpireTournament<'info>>,
+    ) -> Result<()> {
+        let tournament = &ctx.accounts.tournament;
+
+        require!(
+            tournament.status != TournamentStatus::Completed,
+            StakingError::ResultAlreadyReported
+        );
+        require!(
+            Clock::get()?.unix_timestamp > tournament.settle_by,
+            StakingError::SettleDeadlineNotReached
+        );
+
+        let tournament_id = tournament.tournament_id;
+        refund_entrants(
+            &ctx.accounts.system_program,
+            &ctx.accounts.tournament_escrow,
+            &[
+                b"tournament_escrow",
+                tournament_id.as_ref(),
+                &[ctx.bumps.tournament_escrow],
+            ],
+            ctx.accounts.platform_treasury.to_account_info(),
+            &tournament.players,
+            ctx.remaining_accounts,
+            tournament.entry_fee,
+        )?;
+
+        emit!(TournamentCancelled {
+            tournament_id,
+            refunded_players: tournament.players.len() as u8,
+            entry_fee: tournament.entry_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a league. Round-robin leagues pair everyone once (the program
+    /// generates the pairings); Swiss leagues play `rounds` rounds paired by
+    /// the organizer from the points table. Entry fees form the season prize
+    /// pool, split by final standings per `schedule`. If the season hasn't
+    /// finished by `settle_by`, anyone can refund the entrants.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_league(
+        ctx: Context<CreateLeague>,
+        league_id: [u8; 32],
+        entry_fee: u64,
+        max_players: u8,
+        format: LeagueFormat,
+        rounds: u8,
+        points: LeaguePoints,
+        settle_by: i64,
+    ) -> Result<()> {
+        require!(entry_fee >= MIN_STAKE_LAMPORTS, StakingError::StakeTooLow);
+        require!(entry_fee <= MAX_STAKE_LAMPORTS, StakingError::StakeTooHigh);
+        require!(
+            (2..=MAX_LEAGUE_PLAYERS).contains(&max_players)
+                && (format == LeagueFormat::RoundRobin || rounds > 0),
+            StakingError::InvalidLeagueConfig
+        );
+        require!(
+            ctx.accounts.schedule.shares_for(max_players as usize).len() <= max_players as usize,
+            StakingError::InvalidPayoutSchedule
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(settle_by > now, StakingError::InvalidSettleDeadline);
+
+        let league = &mut ctx.accounts.league;
+        league.league_id = league_id;
+        league.organizer = ctx.accounts.organizer.key();
+        league.entry_fee = entry_fee;
+        league.max_players = max_players;
+        league.format = format;
+        league.rounds = rounds;
+        league.points_per_result = points;
+        league.schedule = ctx.accounts.schedule.key();
+        league.status = TournamentStatus::Registration;
+        league.round = 0;
+        league.players = Vec::new();
+        league.points = Vec::new();
+        league.pairings = Vec::new();
+        league.fee_bps = PLATFORM_FEE_BPS as u16;
+        league.created_at = now;
+        league.settle_by = settle_by;
+        league.bump = ctx.bumps.league;
+
+        emit!(LeagueCreated {
+            league_id,
+            organizer: ctx.accounts.organizer.key(),
+            entry_fee,
+            max_players,
+            format,
+            schedule: ctx.accounts.schedule.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Join a league by paying the entry fee into its escrow
+    pub fn join_league(ctx: Context<JoinLeague>) -> Result<()> {
+        let league = &mut ctx.accounts.league;
+        let player = ctx.accounts.player.key();
+
+        require!(
+            league.status == TournamentStatus::Registration,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            league.players.len() < league.max_players as usize,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            !league.players.contains(&player),
+            StakingError::AlreadyRegistered
+        );
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.player.to_account_info(),
+                    to: ctx.accounts.league_escrow.to_account_info(),
+                },
+            ),
+            league.entry_fee,
+        )?;
+
+        league.players.push(player);
+        league.points.push(0);
+
+        emit!(LeagueRegistered {
+            league_id: league.league_id,
+            player,
+            entrants: league.players.len() as u8,
+        });
+
+        Ok(())
+    }
+
+    /// Close registration and start the season. A round-robin league gets its
+    /// number of rounds and first pairings here.
+    pub fn start_league(ctx: Context<ManageLeague>) -> Result<()> {
+        let league = &mut ctx.accounts.league;
+
+        require!(
+            league.status == TournamentStatus::Registration,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            league.players.len() >= 2
+                && league.players.len()
+                    >= ctx.accounts.schedule.shares_for(league.players.len()).len(),
+            StakingError::NotEnoughPlayers
+        );
+
+        league.status = TournamentStatus::InProgress;
+        if league.format == LeagueFormat::RoundRobin {
+            // An odd field adds a bye seat, so everyone sits out once
+            let seats = league.players.len() + league.players.len() % 2;
+            league.rounds = (seats - 1) as u8;
+            league.pairings = league.round_robin_pairings();
+            league.announce_pairings();
+        }
+
+        Ok(())
+    }
+
+    /// Set the current round's pairings in a Swiss league, as indexes into
+    /// `players`. Each player appears at most once; anyone left out has a bye.
+    pub fn set_league_pairings(ctx: Context<ManageLeague>, pairs: Vec<[u8; 2]>) -> Result<()> {
+        let league = &mut ctx.accounts.league;
+
+        require!(
+            league.status == TournamentStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+        require!(
+            league.format == LeagueFormat::Swiss && league.pairings.is_empty(),
+            StakingError::InvalidPairings
+        );
+
+        let mut seen = vec![false; league.players.len()];
+        for pair in &pairs {
+            for index in pair {
+                let index = *index as usize;
+                require!(
+                    index < seen.len() && !seen[index],
+                    StakingError::InvalidPairings
+                );
+                seen[index] = true;
+            }
+        }
+        require!(!pairs.is_empty(), StakingError::InvalidPairings);
+
+        league.pairings = pairs
+            .iter()
+            .map(|pair| LeaguePairing {
+                player_a: pair[0],
+                player_b: pair[1],
+                result: LeagueResult::Pending,
+            })
+            .collect();
+        league.announce_pairings();
+
+        Ok(())
+    }
+
+    /// Report the result of pairing `pairing_index` in the current round and
+    /// update the points table. The organizer reports results; a player can
+    /// also attest their own loss. When the last round completes the prize
+    /// pool is paid out by standings - pass the wallets of the top
+    /// placements (see `League::standings`) as remaining accounts.
+    pub fn report_league_result<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReportLeagueResult<'info>>,
+        pairing_index: u8,
+        result: LeagueResult,
+    ) -> Result<()> {
+        let league = &mut ctx.accounts.league;
+
+        require!(
+            league.status == TournamentStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+
+        let index = pairing_index as usize;
+        require!(
+            index < league.pairings.len(),
+            StakingError::InvalidBracketMatch
+        );
+        let pairing = league.pairings[index];
+        require!(
+            pairing.result == LeagueResult::Pending,
+            StakingError::ResultAlreadyReported
+        );
+
+        let player_a = league.players[pairing.player_a as usize];
+        let player_b = league.players[pairing.player_b as usize];
+        let reporter = ctx.accounts.reporter.key();
+        let attested_loss = match result {
+            LeagueResult::PlayerAWins => reporter == player_b,
+            LeagueResult::PlayerBWins => reporter == player_a,
+            LeagueResult::Draw => false,
+            LeagueResult::Pending => return err!(StakingError::InvalidWinner),
+        };
+        require!(
+            reporter == league.organizer || attested_loss,
+            StakingError::NotOrganizer
+        );
+
+        let points = league.points_per_result;
+        let (points_a, points_b) = match result {
+            LeagueResult::PlayerAWins => (points.win, points.loss),
+            LeagueResult::PlayerBWins => (points.loss, points.win),
+            _ => (points.draw, points.draw),
+        };
+        league.points[pairing.player_a as usize] += points_a as u32;
+        league.points[pairing.player_b as usize] += points_b as u32;
+        league.pairings[index].result = result;
+
+        emit!(LeagueResultReported {
+            league_id: league.league_id,
+            round: league.round,
+            player_a,
+            player_b,
+            result,
+        });
+
+        if league
+            .pairings
+            .iter()
+            .any(|pairing| pairing.result == LeagueResult::Pending)
+        {
+            return Ok(());
+        }
+
+        // Round complete
+        league.round += 1;
+        if league.round < league.rounds {
+            league.pairings = match league.format {
+                LeagueFormat::RoundRobin => league.round_robin_pairings(),
+                LeagueFormat::Swiss => Vec::new(),
+            };
+            league.announce_pairings();
+            return Ok(());
+        }
+
+        league.pairings = Vec::new();
+        league.status = TournamentStatus::Completed;
+
+        let standings = league.standings();
+        let shares = ctx.accounts.schedule.shares_for(league.players.len());
+        let league_id = league.league_id;
+        let (prizes, platform_fee) = pay_prizes(
+            &ctx.accounts.system_program,
+            &ctx.accounts.league_escrow,
+            &[
+                b"league_escrow",
+                league_id.as_ref(),
+                &[ctx.bumps.league_escrow],
+            ],
+            ctx.accounts.platform_treasury.to_account_info(),
+            league.fee_bps,
+            league_id,
+            &standings,
+            &shares,
+            ctx.remaining_accounts,
+        )?;
+
+        emit!(LeagueCompleted {
+            league_id,
+            winner: standings[0],
+            prize_pool: prizes,
+            platform_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel a league before it starts, refunding every entry fee. Pass the
+    /// players' wallets as remaining accounts, in join order.
+    pub fn cancel_league<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelLeague<'info>>,
+    ) -> Result<()> {
+        let league = &ctx.accounts.league;
+
+        require!(
+            league.status == TournamentStatus::Registration,
+            StakingError::CannotCancelStartedMatch
+        );
+
+        let league_id = league.league_id;
+        refund_entrants(
+            &ctx.accounts.system_program,
+            &ctx.accounts.league_escrow,
+            &[
+                b"league_escrow",
+                league_id.as_ref(),
+                &[ctx.bumps.league_escrow],
+            ],
+            ctx.accounts.platform_treasury.to_account_info(),
+            &league.players,
+            ctx.remaining_accounts,
+            league.entry_fee,
+        )?;
+
+        emit!(LeagueCancelled {
+            league_id,
+            refunded_players: league.players.len() as u8,
+            entry_fee: league.entry_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Refund every entry fee of a league that hasn't finished by its
+    /// `settle_by` deadline. Anyone can call this; pass the players' wallets
+    /// as remaining accounts, in join order.
+    pub fn expire_league<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExpireLeague<'info>>,
+    ) -> Result<()> {
+        let league = &ctx.accounts.league;
+
+        require!(
+            league.status != TournamentStatus::Completed,
+            StakingError::ResultAlreadyReported
+        );
+        require!(
+            Clock::get()?.unix_timestamp > league.settle_by,
+            StakingError::SettleDeadlineNotReached
+        );
+
+        let league_id = league.league_id;
+        refund_entrants(
+            &ctx.accounts.system_program,
+            &ctx.accounts.league_escrow,
+            &[
+                b"league_escrow",
+                league_id.as_ref(),
+                &[ctx.bumps.league_escrow],
+            ],
+            ctx.accounts.platform_treasury.to_account_info(),
+            &league.players,
+            ctx.remaining_accounts,
+            league.entry_fee,
+        )?;
+
+        emit!(LeagueCancelled {
+            league_id,
+            refunded_players: league.players.len() as u8,
+            entry_fee: league.entry_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a reusable payout schedule. Either fixed `shares` per
+    /// placement (e.g. 60/30/10) summing to 10,000 bps, or, with `paid_bps`
+    /// set and no shares, the top `paid_bps` of entrants paid evenly (e.g.
+    /// 2,500 for the top 25%).
+    pub fn create_payout_schedule(
+        ctx: Context<CreatePayoutSchedule>,
+        schedule_id: [u8; 32],
+        shares: Vec<u16>,
+        paid_bps: u16,
+    ) -> Result<()> {
+        let valid = if paid_bps == 0 {
+            !shares.is_empty()
+                && shares.len() <= MAX_PAYOUT_PLACES
+                && shares.iter().map(|bps| *bps as u64).sum::<u64>() == 10_000
+        } else {
+            shares.is_empty() && paid_bps <= 10_000
+        };
+        require!(valid, StakingError::InvalidPayoutSchedule);
+
+        let schedule = &mut ctx.accounts.schedule;
+        schedule.schedule_id = schedule_id;
+        schedule.authority = ctx.accounts.authority.key();
+        schedule.shares = shares.clone();
+        schedule.paid_bps = paid_bps;
+        schedule.bump = ctx.bumps.schedule;
+
+        emit!(PayoutScheduleCreated {
+            schedule_id,
+            authority: ctx.accounts.authority.key(),
+            shares,
+            paid_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a pot that up to `max_entrants` players buy into. Once entries
+    /// are locked, `result_authority` posts the final placings and the pot is
+    /// paid out by `schedule`. If they haven't by `settle_by`, anyone can
+    /// refund the entrants.
+    pub fn create_pot(
+        ctx: Context<CreatePot>,
+        pot_id: [u8; 32],
+        buy_in: u64,
+        max_entrants: u8,
+        result_authority: Pubkey,
+        settle_by: i64,
+    ) -> Result<()> {
+        require!(buy_in >= MIN_STAKE_LAMPORTS, StakingError::StakeTooLow);
+        require!(buy_in <= MAX_STAKE_LAMPORTS, StakingError::StakeTooHigh);
+        require!(
+            (2..=MAX_POT_ENTRANTS).contains(&max_entrants),
+            StakingError::InvalidTournamentSize
+        );
+        require!(
+            ctx.accounts.schedule.shares.len() <= max_entrants as usize,
+            StakingError::InvalidPayoutSchedule
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(settle_by > now, StakingError::InvalidSettleDeadline);
+
+        let pot = &mut ctx.accounts.pot;
+        pot.pot_id = pot_id;
+        pot.creator = ctx.accounts.creator.key();
+        pot.result_authority = result_authority;
+        pot.schedule = ctx.accounts.schedule.key();
+        pot.buy_in = buy_in;
+        pot.max_entrants = max_entrants;
+        pot.status = TournamentStatus::Registration;
+        pot.entrants = Vec::new();
+        pot.placements = Vec::new();
+        pot.fee_bps = PLATFORM_FEE_BPS as u16;
+        pot.created_at = now;
+        pot.settle_by = settle_by;
+        pot.bump = ctx.bumps.pot;
+
+        emit!(PotCreated {
+            pot_id,
+            creator: ctx.accounts.creator.key(),
+            result_authority,
+            schedule: ctx.accounts.schedule.key(),
+            buy_in,
+            max_entrants,
+        });
+
+        Ok(())
+    }
+
+    /// Buy into a pot by paying the buy-in into its escrow
+    pub fn join_pot(ctx: Context<JoinPot>) -> Result<()> {
+        let pot = &mut ctx.accounts.pot;
+        let player = ctx.accounts.player.key();
+
+        require!(
+            pot.status == TournamentStatus::Registration,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            pot.entrants.len() < pot.max_entrants as usize,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            !pot.entrants.contains(&player),
+            StakingError::AlreadyRegistered
+        );
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.player.to_account_info(),
+                    to: ctx.accounts.pot_escrow.to_account_info(),
+                },
+            ),
+            pot.buy_in,
+        )?;
+
+        pot.entrants.push(player);
+
+        emit!(PotJoined {
+            pot_id: pot.pot_id,
+            player,
+            entrants: pot.entrants.len() as u8,
+        });
+
+        Ok(())
+    }
+
+    /// Close entries so play can begin. There must be enough entrants to fill
+    /// every paid placement.
+    pub fn lock_pot(ctx: Context<LockPot>) -> Result<()> {
+        let pot = &mut ctx.accounts.pot;
+
+        require!(
+            pot.status == TournamentStatus::Registration,
+            StakingError::MatchNotJoinable
+        );
+        require!(
+            pot.entrants.len() >= 2
+                && pot.entrants.len() >= ctx.accounts.schedule.shares_for(pot.entrants.len()).len(),
+            StakingError::NotEnoughPlayers
+        );
+
+        pot.status = TournamentStatus::InProgress;
+
+        Ok(())
+    }
+
+    /// Post the final placings, best first, and pay the pot out by its
+    /// schedule. Every paid placement must be listed; pass their wallets as
+    /// remaining accounts in the same order.
+    pub fn post_pot_results<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PostPotResults<'info>>,
+        placements: Vec<Pubkey>,
+    ) -> Result<()> {
+        let pot = &mut ctx.accounts.pot;
+
+        require!(
+            pot.status == TournamentStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+
+        let shares = ctx.accounts.schedule.shares_for(pot.entrants.len());
+        require!(
+            placements.len() >= shares.len() && placements.len() <= pot.entrants.len(),
+            StakingError::InvalidPlacements
+        );
+        for (i, player) in placements.iter().enumerate() {
+            require!(
+                pot.entrants.contains(player) && !placements[..i].contains(player),
+                StakingError::InvalidPlacements
+            );
+        }
+
+        pot.status = TournamentStatus::Completed;
+        pot.placements = placements;
+
+        let pot_id = pot.pot_id;
+        let (prizes, platform_fee) = pay_prizes(
+            &ctx.accounts.system_program,
+            &ctx.accounts.pot_escrow,
+            &[b"pot_escrow", pot_id.as_ref(), &[ctx.bumps.pot_escrow]],
+            ctx.accounts.platform_treasury.to_account_info(),
+            pot.fee_bps,
+            pot_id,
+            &pot.placements,
+            &shares,
+            ctx.remaining_accounts,
+        )?;
+
+        emit!(PotSettled {
+            pot_id,
+            winner: pot.placements[0],
+            places_paid: shares.len() as u8,
+            prize_pool: prizes,
+            platform_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel a pot before entries lock, refunding every buy-in. Pass the
+    /// entrants' wallets as remaining accounts, in join order.
+    pub fn cancel_pot<'info>(ctx: Context<'_, '_, 'info, 'info, CancelPot<'info>>) -> Result<()> {
+        let pot = &ctx.accounts.pot;
+
+        require!(
+            pot.status == TournamentStatus::Registration,
+            StakingError::CannotCancelStartedMatch
+        );
+
+        let pot_id = pot.pot_id;
+        refund_entrants(
+            &ctx.accounts.system_program,
+            &ctx.accounts.pot_escrow,
+            &[b"pot_escrow", pot_id.as_ref(), &[ctx.bumps.pot_escrow]],
+            ctx.accounts.platform_treasury.to_account_info(),
+            &pot.entrants,
+            ctx.remaining_accounts,
+            pot.buy_in,
+        )?;
+
+        emit!(PotCancelled {
+            pot_id,
+            refunded_players: pot.entrants.len() as u8,
+            buy_in: pot.buy_in,
+        });
+
+        Ok(())
+    }
+
+    /// Refund every buy-in of a pot whose placings weren't posted by its
+    /// `settle_by` deadline. Anyone can call this; pass the entrants' wallets
+    /// as remaining accounts, in join order.
+    pub fn expire_pot<'info>(ctx: Context<'_, '_, 'info, 'info, ExpirePot<'info>>) -> Result<()> {
+        let pot = &ctx.accounts.pot;
+
+        require!(
+            pot.status != TournamentStatus::Completed,
+            StakingError::ResultAlreadyReported
+        );
+        require!(
+            Clock::get()?.unix_timestamp > pot.settle_by,
+            StakingError::SettleDeadlineNotReached
+        );
+
+        let pot_id = pot.pot_id;
+        refund_entrants(
+            &ctx.accounts.system_program,
+            &ctx.accounts.pot_escrow,
+            &[b"pot_escrow", pot_id.as_ref(), &[ctx.bumps.pot_escrow]],
+            ctx.accounts.platform_treasury.to_account_info(),
+            &pot.entrants,
+            ctx.remaining_accounts,
+            pot.buy_in,
+        )?;
+
+        emit!(PotCancelled {
+            pot_id,
+            refunded_players: pot.entrants.len() as u8,
+            buy_in: pot.buy_in,
+        });
+
+        Ok(())
+    }
+
+    /// Opens a spectator betting pool on a match that is still waiting for
+    /// its challenger
+    pub fn open_bet_pool(ctx: Context<OpenBetPool>) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::WaitingForChallenger,
+            StakingError::BettingClosed
+        );
+        match_account.bet_pool_open = true;
+
+        let bet_pool = &mut ctx.accounts.bet_pool;
+        bet_pool.match_id = match_account.match_id;
+        bet_pool.opener = ctx.accounts.opener.key();
+        bet_pool.match_created_at = match_account.created_at;
+        bet_pool.host = match_account.host;
+        bet_pool.challenger = Pubkey::default();
+        bet_pool.host_total = 0;
+        bet_pool.challenger_total = 0;
+        bet_pool.draw_total = 0;
+        bet_pool.status = BetPoolStatus::Open;
+        bet_pool.winning_outcome = BetOutcome::HostWins;
+        bet_pool.winning_total = 0;
+        bet_pool.payout_pool = 0;
+        bet_pool.winning_unclaimed = 0;
+        bet_pool.open_bets = 0;
+        bet_pool.fee_bps = BET_POOL_FEE_BPS as u16;
+        bet_pool.bump = ctx.bumps.bet_pool;
+
+        emit!(BetPoolOpened {
+            match_id: match_account.match_id,
+            opener: ctx.accounts.opener.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Bet on a match's outcome. Betting closes once the match starts, and
+    /// the players (including an invited challenger) cannot bet.
+    pub fn place_bet(ctx: Context<PlaceBet>, outcome: BetOutcome, amount: u64) -> Result<()> {
+        let match_account = &ctx.accounts.match_account;
+        let bet_pool = &mut ctx.accounts.bet_pool;
+        let bettor = ctx.accounts.bettor.key();
+
+        require!(
+            bet_pool.status == BetPoolStatus::Open
+                && match_account.status == MatchStatus::WaitingForChallenger
+                && match_account.created_at == bet_pool.match_created_at,
+            StakingError::BettingClosed
+        );
+        require!(
+            bettor != match_account.host && bettor != match_account.invited_challenger,
+            StakingError::PlayerCannotBet
+        );
+        require!(amount >= MIN_BET_LAMPORTS, StakingError::StakeTooLow);
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.bettor.to_account_info(),
+                    to: ctx.accounts.bet_escrow.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        *bet_pool.total_mut(outcome) += amount;
+        bet_pool.open_bets += 1;
+
+        let bet = &mut ctx.accounts.bet;
+        bet.match_id = bet_pool.match_id;
+        bet.bettor = bettor;
+        bet.outcome = outcome;
+        bet.amount = amount;
+        bet.bump = ctx.bumps.bet;
+
+        emit!(BetPlaced {
+            match_id: bet_pool.match_id,
+            bettor,
+            outcome,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Settle a bet pool from its match's recorded result. Anyone can call
+    /// this once the match is decided. A decided match can't be closed before
+    /// its pool is settled, so a missing match was cancelled; then, or if
+    /// nobody backed the winning outcome, the pool is voided and every bet
+    /// refunded without a fee.
+    pub fn settle_bet_pool(ctx: Context<SettleBetPool>) -> Result<()> {
+        let bet_pool = &mut ctx.accounts.bet_pool;
+
+        require!(
+            bet_pool.status == BetPoolStatus::Open,
+            StakingError::ResultAlreadyReported
+        );
+
+        let info = ctx.accounts.match_account.to_account_info();
+        let outcome = if info.data_is_empty() {
+            None
+        } else {
+            let mut match_account = MatchAccount::load_any(&info)?;
+            if match_account.created_at != bet_pool.match_created_at {
+                // The match was cancelled and its id reused
+                None
+            } else {
+                let outcome = match match_account.status {
+                    MatchStatus::Draw => BetOutcome::Draw,
+                    MatchStatus::Settled | MatchStatus::Completed => {
+                        if match_account.winner == match_account.host {
+                            BetOutcome::HostWins
+                        } else {
+                            BetOutcome::ChallengerWins
+                        }
+                    }
+                    _ => return err!(StakingError::MatchNotInProgress),
+                };
+
+                // Let the match be closed again
+                bet_pool.challenger = match_account.challenger;
+                match_account.bet_pool_open = false;
+                match_account.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+                Some(outcome)
+            }
+        };
+
+        let total = bet_pool.host_total + bet_pool.challenger_total + bet_pool.draw_total;
+        match outcome.filter(|outcome| *bet_pool.total_mut(*outcome) > 0) {
+            Some(outcome) => {
+                let fee = platform_fee(total, bet_pool.fee_bps as u64);
+                let match_id = bet_pool.match_id;
+                transfer_from_vault(
+                    &ctx.accounts.system_program,
+                    &ctx.accounts.bet_escrow,
+                    ctx.accounts.platform_treasury.to_account_info(),
+                    &[b"bet_escrow", match_id.as_ref(), &[ctx.bumps.bet_escrow]],
+                    fee,
+                )?;
+
+                bet_pool.status = BetPoolStatus::Settled;
+                bet_pool.winning_outcome = outcome;
+                bet_pool.winning_total = *bet_pool.total_mut(outcome);
+                bet_pool.winning_unclaimed = bet_pool.winning_total;
+                bet_pool.payout_pool = total - fee;
+            }
+            None => bet_pool.status = BetPoolStatus::Void,
+        }
+
+        emit!(BetPoolSettled {
+            match_id: bet_pool.match_id,
+            voided: bet_pool.status == BetPoolStatus::Void,
+            winning_outcome: bet_pool.winning_outcome,
+            total_staked: total,
+            payout_pool: bet_pool.payout_pool,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out a bet once its pool is settled, closing the bet account to the
+    /// bettor. Winning bets share the pool pro-rata, the last one claimed
+    /// taking the rounding dust; a voided pool refunds the stake. A winning
+    /// bet placed by someone who went on to play the match is forfeited to
+    /// the treasury. Anyone can call this on the bettor's behalf.
+    pub fn claim_bet(ctx: Context<ClaimBet>) -> Result<()> {
+        let bet_pool = &mut ctx.accounts.bet_pool;
+        let bet = &ctx.accounts.bet;
+
+        let mut forfeited = false;
+        let amount = match bet_pool.status {
+            BetPoolStatus::Open => return err!(StakingError::BetPoolNotSettled),
+            BetPoolStatus::Void => bet.amount,
+            BetPoolStatus::Settled if bet.outcome == bet_pool.winning_outcome => {
+                forfeited = bet.bettor == bet_pool.host || bet.bettor == bet_pool.challenger;
+                bet_pool.winning_unclaimed -= bet.amount;
+                if bet_pool.winning_unclaimed == 0 {
+                    // Sweep, so the escrow isn't left holding less than rent
+                    ctx.accounts.bet_escrow.lamports()
+                } else {
+                    (bet_pool.payout_pool as u128 * bet.amount as u128
+                        / bet_pool.winning_total as u128) as u64
+                }
+            }
+            BetPoolStatus::Settled => 0,
+        };
+
+        let recipient = if forfeited {
+            ctx.accounts.platform_treasury.to_account_info()
+        } else {
+            ctx.accounts.bettor.to_account_info()
+        };
+        let match_id = bet_pool.match_id;
+        transfer_from_vault(
+            &ctx.accounts.system_program,
+            &ctx.accounts.bet_escrow,
+            recipient,
+            &[b"bet_escrow", match_id.as_ref(), &[ctx.bumps.bet_escrow]],
+            amount,
+        )?;
+        bet_pool.open_bets -= 1;
+
+        emit!(BetClaimed {
+            match_id,
+            bettor: bet.bettor,
+            amount: if forfeited { 0 } else { amount },
+        });
+
+        Ok(())
+    }
+
+    /// Close a settled bet pool once every bet is claimed. Anything still in
+    /// the escrow goes to the treasury; rent goes back to whoever opened the
+    /// pool.
+    pub fn close_bet_pool(ctx: Context<CloseBetPool>) -> Result<()> {
+        let bet_pool = &ctx.accounts.bet_pool;
+
+        require!(
+            bet_pool.status != BetPoolStatus::Open,
+            StakingError::BetPoolNotSettled
+        );
+        require!(bet_pool.open_bets == 0, StakingError::BetsOutstanding);
+
+        let match_id = bet_pool.match_id;
+        transfer_from_vault(
+            &ctx.accounts.system_program,
+            &ctx.accounts.bet_escrow,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &[b"bet_escrow", match_id.as_ref(), &[ctx.bumps.bet_escrow]],
+            ctx.accounts.bet_escrow.lamports(),
+        )
+    }
+
+    /// Add a bonus to a live SOL match's escrow, paid to the winner on top of
+    /// the stakes and free of the platform fee. On a draw the bonus is split
+    /// between the players, or returned to the sponsor if they chose
+    /// `SponsorDrawPolicy::Return`.
+    pub fn sponsor_match(
+        ctx: Context<SponsorMatch>,
+        amount: u64,
+        draw_policy: SponsorDrawPolicy,
+    ) -> Result<()> {
+        let match_account = &mut ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::InProgress,
+            StakingError::MatchNotInProgress
+        );
+        require!(amount >= MIN_SPONSOR_LAMPORTS, StakingError::StakeTooLow);
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.sponsor.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        match_account.sponsor_bonus += amount;
+        if draw_policy == SponsorDrawPolicy::Return {
+            match_account.sponsor_draw_refund += amount;
+            match_account.draw_refund_sponsors += 1;
+        }
+
+        let sponsorship = &mut ctx.accounts.sponsorship;
+        sponsorship.match_id = match_account.match_id;
+        sponsorship.sponsor = ctx.accounts.sponsor.key();
+        sponsorship.amount = amount;
+        sponsorship.draw_policy = draw_policy;
+        sponsorship.match_created_at = match_account.created_at;
+        sponsorship.bump = ctx.bumps.sponsorship;
+
+        emit!(MatchSponsored {
+            match_id: match_account.match_id,
+            sponsor: ctx.accounts.sponsor.key(),
+            amount,
+            draw_policy,
+            total_bonus: match_account.sponsor_bonus,
+        });
+
+        Ok(())
+    }
+
+    /// Close a Sponsorship record once its match is over, returning its rent
+    /// to the sponsor. A `SponsorDrawPolicy::Return` sponsor of a drawn match
+    /// gets their bonus back from escrow here too.
+    pub fn close_sponsorship(ctx: Context<CloseSponsorship>) -> Result<()> {
+        let sponsorship = &ctx.accounts.sponsorship;
+        let info = ctx.accounts.match_account.to_account_info();
+        if info.data_is_empty() {
+            return Ok(());
+        }
+
+        let mut match_account = MatchAccount::load_any(&info)?;
+        // A closed and reused match id is a different match
+        if match_account.created_at != sponsorship.match_created_at {
+            return Ok(());
+        }
+        require!(match_account.is_finished(), StakingError::MatchNotFinished);
+
+        if match_account.status == MatchStatus::Draw
+            && sponsorship.draw_policy == SponsorDrawPolicy::Return
+        {
+            transfer_from_escrow(
+                &ctx.accounts.system_program,
+                &ctx.accounts.escrow_vault,
+                ctx.accounts.sponsor.to_account_info(),
+                &sponsorship.match_id,
+                ctx.bumps.escrow_vault,
+                sponsorship.amount,
+            )?;
+
+            // Let the match be closed once every refund is claimed
+            match_account.sponsor_draw_refund -= sponsorship.amount;
+            match_account.draw_refund_sponsors -= 1;
+            match_account.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+            emit!(SponsorBonusPaid {
+                match_id: sponsorship.match_id,
+                recipient: sponsorship.sponsor,
+                amount: sponsorship.amount,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Creates the lobby index account for one stake bucket. Anyone can pay
+    /// for it.
+    pub fn init_lobby_bucket(ctx: Context<InitLobbyBucket>, bucket: u8) -> Result<()> {
+        require!(
+            bucket as usize <= LOBBY_BUCKET_BOUNDS.len(),
+            StakingError::InvalidLobbyBucket
+        );
+
+        let lobby = &mut ctx.accounts.lobby;
+        lobby.bucket = bucket;
+        lobby.entries = Vec::new();
+        lobby.bump = ctx.bumps.lobby;
+
+        Ok(())
+    }
+
+    /// Drop lobby entries for matches that were joined or cancelled without
+    /// delisting (e.g. listed before matches recorded their lobby bucket).
+    /// Anyone can call this; pass the listed matches' PDAs, in the same order
+    /// as `match_ids`, as remaining accounts. Entries still open are left
+    /// alone.
+    pub fn prune_lobby<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PruneLobby<'info>>,
+        match_ids: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        require!(
+            ctx.remaining_accounts.len() == match_ids.len(),
+            StakingError::InvalidMatchAccount
+        );
+
+        let lobby = &mut ctx.accounts.lobby;
+        for (match_id, info) in match_ids.iter().zip(ctx.remaining_accounts) {
+            let (address, _) = Pubkey::find_program_address(&[b"match", match_id], &crate::ID);
+            require_keys_eq!(info.key(), address, StakingError::InvalidMatchAccount);
+
+            let Some(index) = lobby
+                .entries
+                .iter()
+                .position(|entry| entry.match_id == *match_id)
+            else {
+                continue;
+            };
+            let still_open = !info.data_is_empty() && {
+                let match_account = MatchAccount::load_any(info)?;
+                match_account.status == MatchStatus::WaitingForChallenger
+                    && match_account.created_at == lobby.entries[index].created_at
+            };
+            if !still_open {
+                lobby.entries.remove(index);
+                emit!(MatchDelisted {
+                    match_id: *match_id,
+                    bucket: lobby.bucket,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates the matchmaking queue for one stake bucket (the same buckets as
+    /// the lobby). Anyone can pay for it.
+    pub fn init_matchmaking_queue(ctx: Context<InitMatchmakingQueue>, bucket: u8) -> Result<()> {
+        require!(
+            bucket as usize <= LOBBY_BUCKET_BOUNDS.len(),
+            StakingError::InvalidLobbyBucket
+        );
+
+        let queue = &mut ctx.accounts.queue;
+        queue.bucket = bucket;
+        queue.entries = Vec::new();
+        queue.bump = ctx.bumps.queue;
+
+        Ok(())
+    }
+
+    /// Wait for an opponent in a stake bucket's queue, escrowing the stake
+    /// until paired or the player leaves. The player is only paired at a
+    /// stake of at least `min_stake`.
+    pub fn enqueue(
+        ctx: Context<Enqueue>,
+        stake_amount: u64,
+        min_stake: u64,
+        time_control_base_seconds: u32,
+        time_control_increment_seconds: u32,
+    ) -> Result<()> {
+        let queue = &mut ctx.accounts.queue;
+        let player = ctx.accounts.player.key();
+
+        require!(
+            stake_amount >= MIN_STAKE_LAMPORTS,
+            StakingError::StakeTooLow
+        );
+        require!(
+            stake_amount <= MAX_STAKE_LAMPORTS,
+            StakingError::StakeTooHigh
+        );
+        require!(min_stake <= stake_amount, StakingError::InvalidMinStake);
+        require!(
+            queue.bucket == lobby_bucket(stake_amount),
+            StakingError::InvalidLobbyBucket
+        );
+        require!(
+            time_control_base_seconds <= MAX_TIME_CONTROL_BASE_SECONDS
+                && time_control_increment_seconds <= MAX_TIME_CONTROL_INCREMENT_SECONDS,
+            StakingError::InvalidTimeControl
+        );
+        require!(
+            queue.entries.len() < MAX_QUEUE_ENTRIES,
+            StakingError::QueueFull
+        );
+        require!(
+            queue.entries.iter().all(|entry| entry.player != player),
+            StakingError::AlreadyRegistered
+        );
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.player.to_account_info(),
+                    to: ctx.accounts.queue_escrow.to_account_info(),
+                },
+            ),
+            stake_amount,
+        )?;
+
+        let enqueued_at = Clock::get()?.unix_timestamp;
+        queue.entries.push(QueueEntry {
+            player,
+            stake_amount,
+            min_stake,
+            time_control_base_seconds,
+            time_control_increment_seconds,
+            enqueued_at,
+        });
+
+        emit!(PlayerQueued {
+            bucket: queue.bucket,
+            player,
+            stake_amount,
+            min_stake,
+            time_control_base_seconds,
+            time_control_increment_seconds,
+        });
+
+        Ok(())
+    }
+
+    /// Leave a matchmaking queue and get the escrowed stake back
+    pub fn leave_queue(ctx: Context<LeaveQueue>) -> Result<()> {
+        let queue = &mut ctx.accounts.queue;
+        let player = ctx.accounts.player.key();
+
+        let index = queue
+            .entries
+            .iter()
+            .position(|entry| entry.player == player)
+            .ok_or(StakingError::NotQueued)?;
+        let entry = queue.entries.remove(index);
+
+        transfer_from_vault(
+            &ctx.accounts.system_program,
+            &ctx.accounts.queue_escrow,
+            ctx.accounts.player.to_account_info(),
+            &[
+                b"queue_escrow",
+                queue.bucket.to_le_bytes().as_ref(),
+                &[ctx.bumps.queue_escrow],
+            ],
+            entry.stake_amount,
+        )?;
+
+        emit!(PlayerLeftQueue {
+            bucket: queue.bucket,
+            player,
+        });
+
+        Ok(())
+    }
+
+    /// Pair two queued players with the same time control into a new SOL
+    /// match that starts immediately. The earlier entrant hosts, both stake
+    /// the smaller of the two stakes, which must meet each player's
+    /// `min_stake` (the excess goes back to its owner), and `match_id` must be
+    /// `queue_match_id` of the pair.
+    ///
+    /// Either player can pick any compatible opponent. Anyone else can only
+    /// pair the oldest entry with the oldest entry compatible with it; they
+    /// advance the match rent and get it back when the match is closed.
+    pub fn pair(ctx: Context<Pair>, match_id: [u8; 32], index_a: u8, index_b: u8) -> Result<()> {
+        let queue = &mut ctx.accounts.queue;
+
+        let (first, second) = (index_a.min(index_b) as usize, index_a.max(index_b) as usize);
+        require!(
+            first != second && second < queue.entries.len(),
+            StakingError::IncompatibleQueueEntries
+        );
+        let host = queue.entries[first];
+        let challenger = queue.entries[second];
+        require!(
+            host.can_pair_with(&challenger),
+            StakingError::IncompatibleQueueEntries
+        );
+
+        let pairer = ctx.accounts.pairer.key();
+        if pairer != host.player && pairer != challenger.player {
+            let oldest_match = queue.entries[1..]
+                .iter()
+                .position(|entry| queue.entries[0].can_pair_with(entry))
+                .map(|index| index + 1);
+            require!(
+                first == 0 && oldest_match == Some(second),
+                StakingError::PairNotInQueueOrder
+            );
+        }
+        require!(
+            match_id == queue_match_id(&host, &challenger),
+            StakingError::InvalidMatchAccount
+        );
+        require_keys_eq!(
+            ctx.accounts.host.key(),
+            host.player,
+            StakingError::NotParticipant
+        );
+        require_keys_eq!(
+            ctx.accounts.challenger.key(),
+            challenger.player,
+            StakingError::NotParticipant
+        );
+
+        queue.entries.remove(second);
+        queue.entries.remove(first);
+
+        let stake_amount = host.stake_amount.min(challenger.stake_amount);
+
+        let match_account = &mut ctx.accounts.match_account;
+        match_account.set_inner(MatchAccount {
+            challenger: challenger.player,
+            status: MatchStatus::InProgress,
+            rent_payer: pairer,
+            metadata: MatchMetadata {
+                time_control_base_seconds: host.time_control_base_seconds,
+                time_control_increment_seconds: host.time_control_increment_seconds,
+                ..MatchMetadata::default()
+            },
+            ..MatchAccount::new(match_id, host.player, stake_amount, ctx.bumps.match_account)?
+        });
+
+        // Both stakes move to the match escrow; any excess goes back
+        let bucket = queue.bucket.to_le_bytes();
+        let queue_escrow_seeds: &[&[u8]] =
+            &[b"queue_escrow", bucket.as_ref(), &[ctx.bumps.queue_escrow]];
+        transfer_from_vault(
+            &ctx.accounts.system_program,
+            &ctx.accounts.queue_escrow,
+            ctx.accounts.escrow_vault.to_account_info(),
+            queue_escrow_seeds,
+            stake_amount * 2,
+        )?;
+        transfer_from_vault(
+            &ctx.accounts.system_program,
+            &ctx.accounts.queue_escrow,
+            ctx.accounts.host.to_account_info(),
+            queue_escrow_seeds,
+            host.stake_amount - stake_amount,
+        )?;
+        transfer_from_vault(
+            &ctx.accounts.system_program,
+            &ctx.accounts.queue_escrow,
+            ctx.accounts.challenger.to_account_info(),
+            queue_escrow_seeds,
+            challenger.stake_amount - stake_amount,
+        )?;
+
+        emit!(QueuePaired {
+            match_id,
+            bucket: queue.bucket,
+            host: host.player,
+            challenger: challenger.player,
+            stake_amount,
+        });
+        emit!(MatchStarted {
+            match_id,
+            host: host.player,
+            challenger: challenger.player,
+            total_pot: match_account.total_pot()?,
+        });
+
+        Ok(())
+    }
+
+    /// Upgrade a match account written with an older layout to the current
+    /// one, growing it in place. Anyone can pay for the extra rent.
+    pub fn migrate_match(ctx: Context<MigrateMatch>) -> Result<()> {
+        let info = ctx.accounts.match_account.to_account_info();
+        let mut match_account = MatchAccount::load_any(&info)?;
+
+        let from_version = match_account.version;
+        require!(
+            from_version < MATCH_ACCOUNT_VERSION,
+            StakingError::AlreadyMigrated
+        );
+
+        // Only accounts at their canonical PDA can be migrated
+        let expected = Pubkey::create_program_address(
+            &[
+                b"match".as_ref(),
+                match_account.match_id.as_ref(),
+                &[match_account.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| StakingError::InvalidMatchAccount)?;
+        require_keys_eq!(info.key(), expected, StakingError::InvalidMatchAccount);
+
+        // Top up rent for the larger layout, then grow the account
+        let new_len = 8 + MatchAccount::INIT_SPACE;
+        let required = Rent::get()?.minimum_balance(new_len);
+        let shortfall = required.saturating_sub(info.lamports());
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: info.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        info.resize(new_len)?;
+
+        match_account.version = MATCH_ACCOUNT_VERSION;
+        match_account.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        emit!(MatchMigrated {
+            match_id: match_account.match_id,
+            from_version,
+            to_version: MATCH_ACCOUNT_VERSION,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out a match left in `Completed` by the retired declare_winner
+    /// instruction. Permissionless while legacy compatibility is enabled in the
+    /// config; legacy-layout accounts go through migrate_match first.
+    pub fn finalize_legacy_match(ctx: Context<FinalizeLegacyMatch>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        require!(
+            config.legacy_compat && Clock::get()?.unix_timestamp <= config.legacy_compat_until,
+            StakingError::LegacyCompatDisabled
+        );
+
+        let match_account = &ctx.accounts.match_account;
+
+        require!(
+            match_account.status == MatchStatus::Completed,
+            StakingError::MatchNotCompleted
+        );
+        require!(
+            ctx.accounts.winner.key() == match_account.winner,
+            StakingError::NotWinner
+        );
+
+        let total_pot = match_account.total_pot()?;
+        let platform_fee = platform_fee(total_pot, match_account.fee_bps as u64);
+        let match_id = match_account.match_id;
+        let escrow_bump = ctx.bumps.escrow_vault;
+
+        // Transfer platform fee to treasury using PDA signature
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            escrow_bump,
+            platform_fee,
+        )?;
+
+        // Winner sweeps the rest of the vault using PDA signature
+        let winner_reward = ctx.accounts.escrow_vault.lamports();
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.winner.to_account_info(),
+            &match_id,
+            escrow_bump,
+            winner_reward,
+        )?;
+
+        emit!(RewardClaimed {
+            match_id,
+            winner: ctx.accounts.winner.key(),
+            amount: winner_reward,
+            platform_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Turn legacy compatibility (finalize_legacy_match) on or off. It lapses
+    /// at `legacy_compat_until` either way.
+    pub fn set_legacy_compat(ctx: Context<UpdateConfig>, enabled: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.legacy_compat = enabled;
+
+        emit!(LegacyCompatSet { enabled });
+
+        Ok(())
+    }
+
+    /// Point USD matches at a SOL/USD price feed account (see PriceFeed)
+    pub fn set_price_feed(ctx: Context<UpdateConfig>, price_feed: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.price_feed = price_feed;
+
+        emit!(PriceFeedSet { price_feed });
+
+        Ok(())
+    }
+
+    /// One-time setup of the program config. Only the program's upgrade
+    /// authority can call this, and it becomes the config admin.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, treasury: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.treasury = treasury;
+        // Matches stuck in Completed may exist until an admin finalizes them all
+        config.legacy_compat = true;
+        config.price_feed = Pubkey::default();
+        config.bump = ctx.bumps.config;
+        config.legacy_compat_until = Clock::get()?.unix_timestamp + LEGACY_COMPAT_WINDOW_SECONDS;
+        config.pending_admin = Pubkey::default();
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            treasury,
+        });
+
+        Ok(())
+    }
+
+    /// Move the platform treasury, and propose `new_admin` for the admin
+    /// role. They take over once they sign accept_admin; pass the current
+    /// admin to keep it (and withdraw any pending proposal).
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        new_admin: Pubkey,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.treasury = treasury;
+        config.pending_admin = if new_admin == config.admin {
+            Pubkey::default()
+        } else {
+            new_admin
+        };
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            treasury,
+        });
+        if config.pending_admin != Pubkey::default() {
+            emit!(AdminProposed {
+                pending_admin: new_admin
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Take over the admin role proposed by update_config
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.new_admin.key();
+        config.pending_admin = Pubkey::default();
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            treasury: config.treasury,
+        });
+
+        Ok(())
+    }
+
+    /// Allowlist a stake mint with its own stake range and platform fee
+    pub fn add_allowed_mint(
+        ctx: Context<AddAllowedMint>,
+        min_stake: u64,
+        max_stake: u64,
+        fee_bps: u16,
+    ) -> Result<()> {
+        validate_mint_limits(min_stake, max_stake, fee_bps)?;
+        validate_stake_mint(&ctx.accounts.mint.to_account_info())?;
+
+        let mint_config = &mut ctx.accounts.mint_config;
+        mint_config.mint = ctx.accounts.mint.key();
+        mint_config.min_stake = min_stake;
+        mint_config.max_stake = max_stake;
+        mint_config.fee_bps = fee_bps;
+        mint_config.enabled = true;
+        mint_config.bump = ctx.bumps.mint_config;
+
+        emit!(MintConfigUpdated {
+            mint: mint_config.mint,
+            min_stake,
+            max_stake,
+            fee_bps,
+            enabled: true,
+        });
+
+        Ok(())
+    }
+
+    /// Change an allowed mint's limits or pause new matches with it. Matches
+    /// already created keep the fee they were created with.
+    pub fn update_allowed_mint(
+        ctx: Context<UpdateAllowedMint>,
+        min_stake: u64,
+        max_stake: u64,
+        fee_bps: u16,
+        enabled: bool,
+    ) -> Result<()> {
+        validate_mint_limits(min_stake, max_stake, fee_bps)?;
+
+        let mint_config = &mut ctx.accounts.mint_config;
+        mint_config.min_stake = min_stake;
+        mint_config.max_stake = max_stake;
+        mint_config.fee_bps = fee_bps;
+        mint_config.enabled = enabled;
+
+        emit!(MintConfigUpdated {
+            mint: mint_config.mint,
+            min_stake,
+            max_stake,
+            fee_bps,
+            enabled,
+        });
+
+        Ok(())
+    }
+
+    /// Drop a mint from the allowlist, returning the config's rent to the admin
+    pub fn remove_allowed_mint(ctx: Context<RemoveAllowedMint>) -> Result<()> {
+        emit!(MintRemoved {
+            mint: ctx.accounts.mint_config.mint,
+        });
+
+        Ok(())
+    }
+
+    /// Sweep lamports left in an escrow vault whose MatchAccount no longer
+    /// exists (e.g. SOL sent straight to the escrow PDA after settlement)
+    pub fn sweep_orphaned_escrow(
+        ctx: Context<SweepOrphanedEscrow>,
+        match_id: [u8; 32],
+    ) -> Result<()> {
+        let match_info = ctx.accounts.match_account.to_account_info();
+        require!(
+            match_info.lamports() == 0 && match_info.data_is_empty(),
+            StakingError::MatchStillOpen
+        );
+
+        let amount = ctx.accounts.escrow_vault.lamports();
+        transfer_from_escrow(
+            &ctx.accounts.system_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.platform_treasury.to_account_info(),
+            &match_id,
+            ctx.bumps.escrow_vault,
+            amount,
+        )?;
+
+        emit!(EscrowSwept {
+            match_id,
+            amount,
+            treasury: ctx.accounts.platform_treasury.key(),
+        });
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Pays `amount` lamports out of a match's escrow vault, signing with its PDA seeds
+pub fn transfer_from_escrow<'info>(
+    system_program: &Program<'info, System>,
+    escrow_vault: &SystemAccount<'info>,
+    to: AccountInfo<'info>,
+    match_id: &[u8; 32],
+    escrow_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    transfer_from_vault(
+        system_program,
+        escrow_vault,
+        to,
+        &[b"escrow".as_ref(), match_id.as_ref(), &[escrow_bump]],
+        amount,
+    )
+}
+
+/// Moves SOL out of a series escrow using its PDA signature
+pub fn transfer_from_series_escrow<'info>(
+    system_program: &Program<'info, System>,
+    series_escrow: &SystemAccount<'info>,
+    to: AccountInfo<'info>,
+    series_id: &[u8; 32],
+    escrow_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    transfer_from_vault(
+        system_program,
+        series_escrow,
+        to,
+        &[
+            b"series_escrow".as_ref(),
+            series_id.as_ref(),
+            &[escrow_bump],
+        ],
+        amount,
+    )
+}
+
+/// Moves SOL out of any system-owned PDA vault, signing with `vault_seeds`
+/// (bump included). Skips zero amounts.
+pub fn transfer_from_vault<'info>(
+    system_program: &Program<'info, System>,
+    vault: &SystemAccount<'info>,
+    to: AccountInfo<'info>,
+    vault_seeds: &[&[u8]],
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    system_program::transfer(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: vault.to_account_info(),
+                to,
+            },
+            &[vault_seeds],
+        ),
+        amount,
+    )
+}
+
+/// Pays a prize pool out of a PDA vault by placement: the platform fee to the
+/// treasury, then each placement its `prize_bps` share of the rest. First
+/// place is paid last and takes the rounding remainder, along with any share
+/// too small to open an empty recipient's account. `recipients` must be the
+/// wallets of `placements`, best first, one per prize share. Returns the
+/// prizes paid and the platform fee.
+#[allow(clippy::too_many_arguments)]
+pub fn pay_prizes<'info>(
+    system_program: &Program<'info, System>,
+    vault: &SystemAccount<'info>,
+    vault_seeds: &[&[u8]],
+    treasury: AccountInfo<'info>,
+    fee_bps: u16,
+    pool_id: [u8; 32],
+    placements: &[Pubkey],
+    prize_bps: &[u16],
+    recipients: &[AccountInfo<'info>],
+) -> Result<(u64, u64)> {
+    require!(
+        recipients.len() == prize_bps.len() && placements.len() >= prize_bps.len(),
+        StakingError::WrongPrizeRecipient
+    );
+
+    let prize_pool = vault.lamports();
+    let platform_fee = platform_fee(prize_pool, fee_bps as u64);
+    transfer_from_vault(system_program, vault, treasury, vault_seeds, platform_fee)?;
+
+    let prizes = prize_pool - platform_fee;
+    let rent_exempt_minimum = Rent::get()?.minimum_balance(0);
+    for place in (0..recipients.len()).rev() {
+        require_keys_eq!(
+            recipients[place].key(),
+            placements[place],
+            StakingError::WrongPrizeRecipient
+        );
+        let amount = if place == 0 {
+            vault.lamports()
+        } else {
+            let share = (prizes as u128 * prize_bps[place] as u128 / 10_000) as u64;
+            // Left in the vault for first place rather than failing the payout
+            if recipients[place].lamports() == 0 && share < rent_exempt_minimum {
+                0
+            } else {
+                share
+            }
+        };
+        transfer_from_vault(
+            system_program,
+            vault,
+            recipients[place].clone(),
+            vault_seeds,
+            amount,
+        )?;
+
+        emit!(PrizePaid {
+            pool_id,
+            placement: place as u8 + 1,
+            player: recipients[place].key(),
+            amount,
+        });
+    }
+
+    Ok((prizes, platform_fee))
+}
+
+/// Refunds `amount` to each of `players` out of a PDA vault, then sweeps
+/// whatever is left to the treasury. `wallets` must be the players' wallets
+/// in the same order.
+pub fn refund_entrants<'info>(
+    system_program: &Program<'info, System>,
+    vault: &SystemAccount<'info>,
+    vault_seeds: &[&[u8]],
+    treasury: AccountInfo<'info>,
+    players: &[Pubkey],
+    wallets: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    require!(
+        wallets.len() == players.len(),
+        StakingError::WrongPrizeRecipient
+    );
+
+    for (player, wallet) in players.iter().zip(wallets) {
+        require_keys_eq!(wallet.key(), *player, StakingError::WrongPrizeRecipient);
+        transfer_from_vault(system_program, vault, wallet.clone(), vault_seeds, amount)?;
+    }
+
+    // Anything else in the vault was never paid in - sweep it to the treasury
+    transfer_from_vault(
+        system_program,
+        vault,
+        treasury,
+        vault_seeds,
+        vault.lamports(),
+    )
+}
+
+/// The player a signer acts for: the session's owner when signing with that
+/// session's live key, otherwise the signer itself
+pub fn acting_player(signer: &Pubkey, session: Option<&SessionAuthority>) -> Result<Pubkey> {
+    match session {
+        Some(session) => {
+            require_keys_eq!(
+                session.session_key,
+                *signer,
+                StakingError::SessionKeyMismatch
+            );
+            require!(
+                Clock::get()?.unix_timestamp < session.expires_at,
+                StakingError::SessionExpired
+            );
+            Ok(session.owner)
+        }
+        None => Ok(*signer),
+    }
+}
+
+/// Pays a match's relayer back what it advanced, capped at what's left in
+/// escrow. Returns the amount paid.
+pub fn reimburse_relayer<'info>(
+    system_program: &Program<'info, System>,
+    escrow_vault: &SystemAccount<'info>,
+    relayer: Option<&UncheckedAccount<'info>>,
+    match_account: &MatchAccount,
+    escrow_bump: u8,
+) -> Result<u64> {
+    if match_account.relayer_advanced == 0 {
+        return Ok(0);
+    }
+    let relayer = relayer.ok_or(StakingError::RelayerMismatch)?;
+
+    let amount = match_account.relayer_advanced.min(escrow_vault.lamports());
+    transfer_from_escrow(
+        system_program,
+        escrow_vault,
+        relayer.to_account_info(),
+        &match_account.match_id,
+        escrow_bump,
+        amount,
+    )?;
+
+    emit!(RelayerReimbursed {
+        match_id: match_account.match_id,
+        relayer: relayer.key(),
+        amount,
+    });
+
+    Ok(amount)
+}
+
+/// Verifies a merkle proof that `player` is in the tree with `root`. Leaves are
+/// `hash(player)` and each parent hashes its two children in sorted order.
+pub fn verify_invite_proof(player: &Pubkey, proof: &[[u8; 32]], root: &[u8; 32]) -> bool {
+    let mut node = hashv(&[player.as_ref()]).to_bytes();
+    for sibling in proof {
+        node = if node <= *sibling {
+            hashv(&[node.as_ref(), sibling.as_ref()]).to_bytes()
+        } else {
+            hashv(&[sibling.as_ref(), node.as_ref()]).to_bytes()
+        };
+    }
+    node == *root
+}
+
+/// Lobby bucket for matches a challenger joins with `stake` lamports
+pub fn lobby_bucket(stake: u64) -> u8 {
+    LOBBY_BUCKET_BOUNDS
+        .iter()
+        .filter(|bound| stake >= **bound)
+        .count() as u8
+}
+
+/// Lists an open match in `lobby`, if one was passed, and records the bucket
+/// on the match. It must be the bucket for the challenger's stake.
+pub fn list_in_lobby(
+    lobby: Option<&mut Account<LobbyBucket>>,
+    match_account: &mut MatchAccount,
+) -> Result<()> {
+    let Some(lobby) = lobby else {
+        return Ok(());
+    };
+    require!(
+        lobby.bucket == lobby_bucket(match_account.challenger_stake),
+        StakingError::InvalidLobbyBucket
+    );
+    require!(
+        lobby.entries.len() < MAX_LOBBY_ENTRIES,
+        StakingError::LobbyFull
+    );
+
+    lobby.entries.push(LobbyEntry {
+        match_id: match_account.match_id,
+        host: match_account.host,
+        stake_amount: match_account.challenger_stake,
+        metadata: match_account.metadata,
+        created_at: match_account.created_at,
+    });
+
+    match_account.listed_bucket = Some(lobby.bucket);
+
+    emit!(MatchListed {
+        match_id: match_account.match_id,
+        bucket: lobby.bucket,
+    });
+    Ok(())
+}
+
+/// Removes a match from the lobby bucket it is listed in. `lobby` must be that
+/// bucket if the match is listed, and is ignored otherwise.
+pub fn delist_from_lobby(
+    lobby: Option<&mut Account<LobbyBucket>>,
+    match_account: &mut MatchAccount,
+) -> Result<()> {
+    let Some(bucket) = match_account.listed_bucket else {
+        return Ok(());
+    };
+    let lobby = lobby.ok_or(StakingError::LobbyBucketRequired)?;
+    require!(lobby.bucket == bucket, StakingError::InvalidLobbyBucket);
+    match_account.listed_bucket = None;
+
+    let listed = lobby.entries.len();
+    lobby
+        .entries
+        .retain(|entry| entry.match_id != match_account.match_id);
+    if lobby.entries.len() < listed {
+        emit!(MatchDelisted {
+            match_id: match_account.match_id,
+            bucket: lobby.bucket,
+        });
+    }
+    Ok(())
+}
+
+/// Match id `pair` uses for two queue entries, so clients can derive the
+/// match PDA before pairing
+pub fn queue_match_id(host: &QueueEntry, challenger: &QueueEntry) -> [u8; 32] {
+    hashv(&[
+        b"queue_match".as_ref(),
+        host.player.as_ref(),
+        challenger.player.as_ref(),
+        &host.enqueued_at.to_le_bytes(),
+    ])
+    .to_bytes()
+}
+
+/// Moves a BackingPool's raised stake into the match escrow, leaving the markup
+/// in the pool until the match starts or is cancelled. Like a PlayerVault, the
+/// pool is owned by this program and is debited directly.
+pub fn place_backed_stake<'info>(
+    backing_pool: &mut Account<'info, BackingPool>,
+    escrow_vault: &AccountInfo<'info>,
+    match_created_at: i64,
+) -> Result<()> {
+    backing_pool.sub_lamports(backing_pool.total_backed)?;
+    escrow_vault.add_lamports(backing_pool.total_backed)?;
+    backing_pool.status = BackingStatus::Staked;
+    backing_pool.match_created_at = match_created_at;
+    Ok(())
+}
+
+/// Releases a host-backed match's markup to the host once a challenger joins.
+/// Every join path for a host-backed match must pass the host's BackingPool.
+pub fn release_host_markup(
+    match_account: &MatchAccount,
+    host_backing_pool: Option<&mut Account<BackingPool>>,
+) -> Result<()> {
+    if !match_account.host_backed {
+        return Ok(());
+    }
+    let backing_pool = host_backing_pool.ok_or(StakingError::HostBackingPoolRequired)?;
+    require!(
+        backing_pool.status == BackingStatus::Staked
+            && backing_pool.match_created_at == match_account.created_at,
+        StakingError::HostBackingPoolRequired
+    );
+    backing_pool.markup_released = true;
+    Ok(())
+}
+
+/// Moves lamports out of a PlayerVault. The vault is owned by this program, so
+/// its lamports are debited directly rather than through the system program.
+pub fn debit_player_vault<'info>(
+    player_vault: &Account<'info, PlayerVault>,
+    to: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    require!(
+        amount <= PlayerVault::available_balance(&player_vault.to_account_info())?,
+        StakingError::InsufficientVaultBalance
+    );
+    player_vault.sub_lamports(amount)?;
+    to.add_lamports(amount)?;
+    Ok(())
+}
+
+/// Platform cut of `total_pot` at `fee_bps`, computed in u128 so large token
+/// amounts can't overflow
+pub fn platform_fee(total_pot: u64, fee_bps: u64) -> u64 {
+    ((total_pot as u128 * fee_bps as u128) / 10_000) as u64
+}
+
+/// Rejects Token-2022 mints whose extensions would let tokens leave or get
+/// stuck in escrow without the match PDA's say-so
+pub fn validate_stake_mint(mint: &AccountInfo) -> Result<()> {
+    let mint_data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+
+    for extension in mint.get_extension_types()? {
+        match extension {
+            // A permanent delegate can pull tokens straight out of the escrow
+            ExtensionType::PermanentDelegate | ExtensionType::NonTransferable => {
+                return err!(StakingError::UnsupportedMintExtension);
+            }
+            // A frozen-by-default escrow could never pay out
+            ExtensionType::DefaultAccountState => {
+                let default_state = mint.get_extension::<DefaultAccountState>()?;
+                require!(
+                    default_state.state != AccountState::Frozen as u8,
+                    StakingError::UnsupportedMintExtension
+                );
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Sanity checks for an allowlisted mint's stake range and fee
+pub fn validate_mint_limits(min_stake: u64, max_stake: u64, fee_bps: u16) -> Result<()> {
+    require!(
+        min_stake > 0 && min_stake <= max_stake,
+        StakingError::InvalidStakeLimits
+    );
+    require!(
+        fee_bps <= pokechess_staking::MAX_MINT_FEE_BPS,
+        StakingError::FeeTooHigh
+    );
+    Ok(())
+}
+
+/// Checks that a wagered mint is a plain 1-of-1 NFT, and that it belongs to
+/// `collection` (verified). Without a collection the mint must be unable to
+/// mint more: no mint authority, or its Metaplex master edition.
+pub fn verify_wager_nft(
+    mint: &InterfaceAccount<Mint>,
+    metadata: Option<&MetadataAccount>,
+    collection: Pubkey,
+) -> Result<()> {
+    require!(
+        mint.decimals == 0 && mint.supply == 1,
+        StakingError::InvalidNft
+    );
+    validate_stake_mint(&mint.to_account_info())?;
+
+    // A transfer fee would take the NFT itself
+    let mint_info = mint.to_account_info();
+    let mint_data = mint_info.try_borrow_data()?;
+    require!(
+        StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?
+            .get_extension::<TransferFeeConfig>()
+            .is_err(),
+        StakingError::UnsupportedMintExtension
+    );
+
+    if collection != Pubkey::default() {
+        let metadata = metadata.ok_or(StakingError::NftNotInCollection)?;
+        let in_collection = metadata
+            .collection
+            .as_ref()
+            .is_some_and(|c| c.verified && c.key == collection);
+        require!(in_collection, StakingError::NftNotInCollection);
+    } else {
+        let master_edition =
+            metadata::mpl_token_metadata::accounts::MasterEdition::find_pda(&mint.key()).0;
+        require!(
+            mint.mint_authority.is_none() || mint.mint_authority == COption::Some(master_edition),
+            StakingError::InvalidNft
+        );
+    }
+
+    Ok(())
+}
+
+/// Amount to send so that `net_amount` arrives after the mint's current
+/// transfer fee (just `net_amount` for mints without one)
+pub fn gross_for_net_amount(mint: &AccountInfo, net_amount: u64) -> Result<u64> {
+    let mint_data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+
+    let fee = match mint.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => fee_config
+            .get_epoch_fee(Clock::get()?.epoch)
+            .calculate_inverse_fee(net_amount)
+            .ok_or(StakingError::MathOverflow)?,
+        Err(_) => 0,
+    };
+
+    net_amount
+        .checked_add(fee)
+        .ok_or_else(|| StakingError::MathOverflow.into())
+}
+
+/// Moves stake tokens with `transfer_checked`, forwarding any extra accounts a
+/// Token-2022 transfer hook needs (passed in as remaining accounts)
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_stake_tokens<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    spl_token_2022::onchain::invoke_transfer_checked(
+        token_program.key,
+        from,
+        mint.to_account_info(),
+        to,
+        authority,
+        hook_accounts,
+        amount,
+        mint.decimals,
+        signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+/// Pays `amount` out of a match's token escrow, signing as the match PDA
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_from_token_escrow<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    token_escrow: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    match_account: AccountInfo<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    match_id: &[u8; 32],
+    match_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let match_seeds = &[b"match".as_ref(), match_id.as_ref(), &[match_bump]];
+
+    transfer_stake_tokens(
+        token_program,
+        token_escrow.to_account_info(),
+        mint,
+        to,
+        match_account,
+        hook_accounts,
+        amount,
+        &[&match_seeds[..]],
+    )
+}
+
+/// Closes an emptied token escrow, returning its rent to `destination`.
+/// Withheld transfer fees are harvested to the mint first, as Token-2022
+/// refuses to close an account still holding them.
+pub fn close_token_escrow<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    token_escrow: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    destination: AccountInfo<'info>,
+    match_account: AccountInfo<'info>,
+    match_id: &[u8; 32],
+    match_bump: u8,
+) -> Result<()> {
+    let has_transfer_fee = {
+        let mint_info = mint.to_account_info();
+        let mint_data = mint_info.try_borrow_data()?;
+        StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?
+            .get_extension::<TransferFeeConfig>()
+            .is_ok()
+    };
+    if has_transfer_fee {
+        token_interface::harvest_withheld_tokens_to_mint(
+            CpiContext::new(
+                token_program.to_account_info(),
+                token_interface::HarvestWithheldTokensToMint {
+                    token_program_id: token_program.to_account_info(),
+                    mint: mint.to_account_info(),
+                },
+            ),
+            vec![token_escrow.to_account_info()],
+        )?;
+    }
+
+    let match_seeds = &[b"match".as_ref(), match_id.as_ref(), &[match_bump]];
+    let signer_seeds = &[&match_seeds[..]];
+
+    token_interface::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token_interface::CloseAccount {
+            account: token_escrow.to_account_info(),
+            destination,
+            authority: match_account,
+        },
+        signer_seeds,
+    ))
+}
+
+// ============================================================================
+// Accounts
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32], stake_amount: u64)]
+pub struct CreateMatch<'info> {
+    #[account(
+        init,
+        payer = host,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", match_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    /// Lobby bucket for the stake, if the match is (to be) listed
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Option<Account<'info, LobbyBucket>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+
+    /// Lobby bucket for the stake, if the match is (to be) listed
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Option<Account<'info, LobbyBucket>>,
+
+    /// Host's BackingPool, required if the match is host-backed
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            match_account.match_id.as_ref(),
+            match_account.host.as_ref()
+        ],
+        bump = host_backing_pool.bump
+    )]
+    pub host_backing_pool: Option<Account<'info, BackingPool>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// New combined instruction: declare winner + distribute rewards in ONE tx
+/// Winner does NOT need to sign - only the caller (participant) signs
+#[derive(Accounts)]
+pub struct ClaimWinnerReward<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding the staked SOL
+    pub escrow_vault: SystemAccount<'info>,
+
+    /// The caller (must be host or challenger) - this is the ONLY signer needed
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: The winner's wallet, or their PlayerVault or BackingPool if they
+    /// staked from one - does NOT need to sign, just receives SOL (checked in the handler)
+    #[account(mut)]
+    pub winner_account: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury for fees - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    /// Session of the player `caller` signs for, if it's a session key
+    #[account(
+        seeds = [b"session", session.owner.as_ref()],
+        bump = session.bump
+    )]
+    pub session: Option<Account<'info, SessionAuthority>>,
+
+    /// CHECK: Relayer that advanced rent and fees for this match, if any
+    #[account(mut, address = match_account.relayer @ StakingError::RelayerMismatch)]
+    pub relayer: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Legacy matches close to the winner, as the retired claim_reward did
+#[derive(Accounts)]
+pub struct FinalizeLegacyMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        close = winner
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds SOL for distribution
+    pub escrow_vault: SystemAccount<'info>,
+
+    /// CHECK: Recorded winner - does NOT need to sign, checked in the handler
+    #[account(mut)]
+    pub winner: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury for fees - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        close = host,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds SOL for refund
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    /// CHECK: Host wallet, PlayerVault or BackingPool for the refund - validated by address constraint
+    #[account(mut, address = match_account.payout_address(match_account.host))]
+    pub host_refund: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the vault
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    /// CHECK: Relayer that advanced rent and fees for this match, if any
+    #[account(mut, address = match_account.relayer @ StakingError::RelayerMismatch)]
+    pub relayer: Option<UncheckedAccount<'info>>,
+
+    /// Lobby bucket for the stake, if the match is (to be) listed
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Option<Account<'info, LobbyBucket>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMatchInvite<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    pub host: Signer<'info>,
+
+    /// Lobby bucket for the stake, if the match is (to be) listed
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Option<Account<'info, LobbyBucket>>,
+}
+
+#[derive(Accounts)]
+pub struct OfferDraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    pub caller: Signer<'info>,
+
+    /// Session of the player `caller` signs for, if it's a session key
+    #[account(
+        seeds = [b"session", session.owner.as_ref()],
+        bump = session.bump
+    )]
+    pub session: Option<Account<'info, SessionAuthority>>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptDraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds SOL for refund
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: Host wallet, PlayerVault or BackingPool for refund - validated by address constraint
+    #[account(mut, address = match_account.payout_address(match_account.host))]
+    pub host_account: UncheckedAccount<'info>,
+
+    /// CHECK: Challenger wallet, PlayerVault or BackingPool for refund - validated by address constraint
+    #[account(mut, address = match_account.payout_address(match_account.challenger))]
+    pub challenger_account: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the vault
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    /// Session of the player `caller` signs for, if it's a session key
+    #[account(
+        seeds = [b"session", session.owner.as_ref()],
+        bump = session.bump
+    )]
+    pub session: Option<Account<'info, SessionAuthority>>,
+
+    /// CHECK: Relayer that advanced rent and fees for this match, if any
+    #[account(mut, address = match_account.relayer @ StakingError::RelayerMismatch)]
+    pub relayer: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(rematch_id: [u8; 32])]
+pub struct CreateRematch<'info> {
+    #[account(
+        seeds = [b"match", previous_match.match_id.as_ref()],
+        bump = previous_match.bump,
+        constraint = previous_match.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub previous_match: Account<'info, MatchAccount>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", rematch_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", rematch_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptRematch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRematch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        close = proposer
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds SOL for refund
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the vault
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        close = rent_payer
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    /// CHECK: Receives the rent back; must be whoever paid it
+    #[account(mut, address = match_account.rent_payer @ StakingError::RentPayerMismatch)]
+    pub rent_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32], stake_amount: u64)]
+pub struct CreateTokenMatch<'info> {
+    #[account(
+        init,
+        payer = host,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", match_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(mint::token_program = token_program)]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+
+    /// Allowlist entry for the stake mint - missing means the mint isn't accepted
+    #[account(
+        seeds = [b"mint_config", stake_mint.key().as_ref()],
+        bump = mint_config.bump
+    )]
+    pub mint_config: Account<'info, MintConfig>,
+
+    /// Token escrow owned by the match PDA
+    #[account(
+        init,
+        payer = host,
+        seeds = [b"token_escrow", match_id.as_ref()],
+        bump,
+        token::mint = stake_mint,
+        token::authority = match_account,
+        token::token_program = token_program
+    )]
+    pub token_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = host,
+        token::token_program = token_program
+    )]
+    pub host_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinTokenMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Token @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        address = match_account.stake_mint,
+        mint::token_program = token_program
+    )]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"token_escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    pub token_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = challenger,
+        token::token_program = token_program
+    )]
+    pub challenger_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub challenger: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(winner: Pubkey)]
+pub struct ClaimTokenWinnerReward<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Token @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    /// Writable so withheld transfer fees can be harvested before closing
+    #[account(
+        mut,
+        address = match_account.stake_mint,
+        mint::token_program = token_program
+    )]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"token_escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    pub token_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    /// The caller (must be host or challenger) - receives the escrow's rent
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// Winner's token account - the winner does NOT need to sign
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = winner,
+        token::token_program = token_program
+    )]
+    pub winner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = config.treasury,
+        token::token_program = token_program
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Session of the player `caller` signs for, if it's a session key
+    #[account(
+        seeds = [b"session", session.owner.as_ref()],
+        bump = session.bump
+    )]
+    pub session: Option<Account<'info, SessionAuthority>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptTokenDraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Token @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    /// Writable so withheld transfer fees can be harvested before closing
+    #[account(
+        mut,
+        address = match_account.stake_mint,
+        mint::token_program = token_program
+    )]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"token_escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    pub token_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = match_account.host,
+        token::token_program = token_program
+    )]
+    pub host_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = match_account.challenger,
+        token::token_program = token_program
+    )]
+    pub challenger_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = config.treasury,
+        token::token_program = token_program
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Session of the player `caller` signs for, if it's a session key
+    #[account(
+        seeds = [b"session", session.owner.as_ref()],
+        bump = session.bump
+    )]
+    pub session: Option<Account<'info, SessionAuthority>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTokenMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        close = host,
+        constraint = match_account.kind == MatchKind::Token @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    /// Writable so withheld transfer fees can be harvested before closing
+    #[account(
+        mut,
+        address = match_account.stake_mint,
+        mint::token_program = token_program
+    )]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"token_escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    pub token_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = host,
+        token::token_program = token_program
+    )]
+    pub host_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        token::mint = stake_mint,
+        token::authority = config.treasury,
+        token::token_program = token_program
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32])]
+pub struct CreateMatchRelayed<'info> {
+    #[account(
+        init,
+        payer = relayer,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", match_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    /// Fee payer advancing the match account rent and transaction fees
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinMatchRelayed<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+
+    /// Fee payer advancing the transaction fee
+    pub relayer: Signer<'info>,
+
+    /// Host's BackingPool, required if the match is host-backed
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            match_account.match_id.as_ref(),
+            match_account.host.as_ref()
+        ],
+        bump = host_backing_pool.bump
+    )]
+    pub host_backing_pool: Option<Account<'info, BackingPool>>,
+
+    /// Lobby bucket for the stake, if the match is (to be) listed
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Option<Account<'info, LobbyBucket>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32])]
+pub struct CreateMatchFromVault<'info> {
+    #[account(
+        init,
+        payer = host,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", match_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"player_vault", host.key().as_ref()],
+        bump = player_vault.bump
+    )]
+    pub player_vault: Account<'info, PlayerVault>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinMatchFromVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"player_vault", player_vault.owner.as_ref()],
+        bump = player_vault.bump
+    )]
+    pub player_vault: Account<'info, PlayerVault>,
+
+    /// The vault owner, or their session key
+    pub challenger: Signer<'info>,
+
+    /// Session of the player `challenger` signs for, if it's a session key
+    #[account(
+        mut,
+        seeds = [b"session", session.owner.as_ref()],
+        bump = session.bump
+    )]
+    pub session: Option<Account<'info, SessionAuthority>>,
+
+    /// Host's BackingPool, required if the match is host-backed
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            match_account.match_id.as_ref(),
+            match_account.host.as_ref()
+        ],
+        bump = host_backing_pool.bump
+    )]
+    pub host_backing_pool: Option<Account<'info, BackingPool>>,
+
+    /// Lobby bucket for the stake, if the match is (to be) listed
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Option<Account<'info, LobbyBucket>>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32])]
+pub struct OpenBackingPool<'info> {
+    #[account(
+        init,
+        payer = player,
+        space = 8 + BackingPool::INIT_SPACE,
+        seeds = [b"backing_pool", match_id.as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub backing_pool: Account<'info, BackingPool>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BackPlayer<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            backing_pool.match_id.as_ref(),
+            backing_pool.player.as_ref()
+        ],
+        bump = backing_pool.bump
+    )]
+    pub backing_pool: Account<'info, BackingPool>,
+
+    #[account(
+        init_if_needed,
+        payer = backer,
+        space = 8 + Backing::INIT_SPACE,
+        seeds = [
+            b"backing",
+            backing_pool.match_id.as_ref(),
+            backing_pool.player.as_ref(),
+            backer.key().as_ref()
+        ],
+        bump
+    )]
+    pub backing: Account<'info, Backing>,
+
+    #[account(mut)]
+    pub backer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBackingPool<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            backing_pool.match_id.as_ref(),
+            player.key().as_ref()
+        ],
+        bump = backing_pool.bump,
+        has_one = player
+    )]
+    pub backing_pool: Account<'info, BackingPool>,
+
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32])]
+pub struct CreateBackedMatch<'info> {
+    #[account(
+        init,
+        payer = host,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", match_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"backing_pool", match_id.as_ref(), host.key().as_ref()],
+        bump = backing_pool.bump
+    )]
+    pub backing_pool: Account<'info, BackingPool>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinBackedMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            match_account.match_id.as_ref(),
+            challenger.key().as_ref()
+        ],
+        bump = backing_pool.bump
+    )]
+    pub backing_pool: Account<'info, BackingPool>,
+
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+
+    /// Host's BackingPool, required if the match is host-backed
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            match_account.match_id.as_ref(),
+            match_account.host.as_ref()
+        ],
+        bump = host_backing_pool.bump
+    )]
+    pub host_backing_pool: Option<Account<'info, BackingPool>>,
+
+    /// Lobby bucket for the stake, if the match is (to be) listed
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Option<Account<'info, LobbyBucket>>,
+}
+
+#[derive(Accounts)]
+pub struct SettleBackingPool<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            backing_pool.match_id.as_ref(),
+            backing_pool.player.as_ref()
+        ],
+        bump = backing_pool.bump
+    )]
+    pub backing_pool: Account<'info, BackingPool>,
+
+    /// CHECK: The pool's match PDA, read with MatchAccount::load_any. It may
+    /// already be closed.
+    #[account(
+        seeds = [b"match", backing_pool.match_id.as_ref()],
+        bump
+    )]
+    pub match_account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimBacking<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            backing_pool.match_id.as_ref(),
+            backing_pool.player.as_ref()
+        ],
+        bump = backing_pool.bump
+    )]
+    pub backing_pool: Account<'info, BackingPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"backing",
+            backing_pool.match_id.as_ref(),
+            backing_pool.player.as_ref(),
+            backer.key().as_ref()
+        ],
+        bump = backing.bump,
+        has_one = backer,
+        close = backer
+    )]
+    pub backing: Account<'info, Backing>,
+
+    /// CHECK: The backer, receives their share and the backing account's rent
+    #[account(mut)]
+    pub backer: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBackingPool<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"backing_pool",
+            backing_pool.match_id.as_ref(),
+            player.key().as_ref()
+        ],
+        bump = backing_pool.bump,
+        has_one = player,
+        close = player
+    )]
+    pub backing_pool: Account<'info, BackingPool>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSession<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + SessionAuthority::INIT_SPACE,
+        seeds = [b"session", owner.key().as_ref()],
+        bump
+    )]
+    pub session: Account<'info, SessionAuthority>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSession<'info> {
+    #[account(
+        mut,
+        seeds = [b"session", owner.key().as_ref()],
+        bump = session.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub session: Account<'info, SessionAuthority>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + PlayerVault::INIT_SPACE,
+        seeds = [b"player_vault", owner.key().as_ref()],
+        bump
+    )]
+    pub player_vault: Account<'info, PlayerVault>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"player_vault", owner.key().as_ref()],
+        bump = player_vault.bump,
+        has_one = owner
+    )]
+    pub player_vault: Account<'info, PlayerVault>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32])]
+pub struct CreateUsdMatch<'info> {
+    #[account(
+        init,
+        payer = host,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", match_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.price_feed != Pubkey::default() @ StakingError::PriceFeedNotSet
+    )]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Price feed account, parsed by PriceFeed::load - must match the config
+    #[account(address = config.price_feed)]
+    pub price_feed: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinUsdMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind,
+        constraint = match_account.stake_usd_cents > 0 @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.price_feed != Pubkey::default() @ StakingError::PriceFeedNotSet
+    )]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Price feed account, parsed by PriceFeed::load - must match the config
+    #[account(address = config.price_feed)]
+    pub price_feed: UncheckedAccount<'info>,
+
+    /// Lobby bucket for the stake, if the match is (to be) listed
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Option<Account<'info, LobbyBucket>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32])]
+pub struct CreateNftMatch<'info> {
+    #[account(
+        init,
+        payer = host,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", match_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(mint::token_program = token_program)]
+    pub host_nft_mint: InterfaceAccount<'info, Mint>,
+
+    /// Metaplex metadata of the host's NFT - required when the match has a collection
+    #[account(
+        seeds = [b"metadata", metadata::ID.as_ref(), host_nft_mint.key().as_ref()],
+        seeds::program = metadata::ID,
+        bump
+    )]
+    pub host_nft_metadata: Option<Account<'info, MetadataAccount>>,
+
+    /// NFT escrow owned by the match PDA
+    #[account(
+        init,
+        payer = host,
+        seeds = [b"nft_escrow", match_id.as_ref(), host_nft_mint.key().as_ref()],
+        bump,
+        token::mint = host_nft_mint,
+        token::authority = match_account,
+        token::token_program = token_program
+    )]
+    pub host_nft_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = host_nft_mint,
+        token::authority = host,
+        token::token_program = token_program
+    )]
+    pub host_nft_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives the flat fee
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinNftMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Nft @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(mint::token_program = token_program)]
+    pub challenger_nft_mint: InterfaceAccount<'info, Mint>,
+
+    /// Metaplex metadata of the challenger's NFT - required when the match has a collection
+    #[account(
+        seeds = [b"metadata", metadata::ID.as_ref(), challenger_nft_mint.key().as_ref()],
+        seeds::program = metadata::ID,
+        bump
+    )]
+    pub challenger_nft_metadata: Option<Account<'info, MetadataAccount>>,
+
+    /// NFT escrow owned by the match PDA
+    #[account(
+        init,
+        payer = challenger,
+        seeds = [
+            b"nft_escrow",
+            match_account.match_id.as_ref(),
+            challenger_nft_mint.key().as_ref()
+        ],
+        bump,
+        token::mint = challenger_nft_mint,
+        token::authority = match_account,
+        token::token_program = token_program
+    )]
+    pub challenger_nft_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = challenger_nft_mint,
+        token::authority = challenger,
+        token::token_program = token_program
+    )]
+    pub challenger_nft_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives the flat fee
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(winner: Pubkey)]
+pub struct ClaimNftWinnerReward<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Nft @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(address = match_account.host_nft_mint)]
+    pub host_nft_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = match_account.challenger_nft_mint)]
+    pub challenger_nft_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nft_escrow",
+            match_account.match_id.as_ref(),
+            match_account.host_nft_mint.as_ref()
+        ],
+        bump
+    )]
+    pub host_nft_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nft_escrow",
+            match_account.match_id.as_ref(),
+            match_account.challenger_nft_mint.as_ref()
+        ],
+        bump
+    )]
+    pub challenger_nft_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding the flat fees
+    pub escrow_vault: SystemAccount<'info>,
+
+    /// The caller (must be host or challenger) - receives the escrows' rent
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: The winner's wallet - does NOT need to sign, receives any SOL dust
+    #[account(mut, address = winner @ StakingError::InvalidWinner)]
+    pub winner_account: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = host_nft_mint,
+        token::authority = winner,
+        token::token_program = token_program
+    )]
+    pub winner_host_nft_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = challenger_nft_mint,
+        token::authority = winner,
+        token::token_program = token_program
+    )]
+    pub winner_challenger_nft_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury for fees - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    /// Session of the player `caller` signs for, if it's a session key
+    #[account(
+        seeds = [b"session", session.owner.as_ref()],
+        bump = session.bump
+    )]
+    pub session: Option<Account<'info, SessionAuthority>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptNftDraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Nft @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(address = match_account.host_nft_mint)]
+    pub host_nft_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = match_account.challenger_nft_mint)]
+    pub challenger_nft_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nft_escrow",
+            match_account.match_id.as_ref(),
+            match_account.host_nft_mint.as_ref()
+        ],
+        bump
+    )]
+    pub host_nft_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nft_escrow",
+            match_account.match_id.as_ref(),
+            match_account.challenger_nft_mint.as_ref()
+        ],
+        bump
+    )]
+    pub challenger_nft_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding the flat fees
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: Host account for the fee refund - validated by address constraint
+    #[account(mut, address = match_account.host)]
+    pub host_account: SystemAccount<'info>,
+
+    /// CHECK: Challenger account for the fee refund - validated by address constraint
+    #[account(mut, address = match_account.challenger)]
+    pub challenger_account: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = host_nft_mint,
+        token::authority = match_account.host,
+        token::token_program = token_program
+    )]
+    pub host_nft_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = challenger_nft_mint,
+        token::authority = match_account.challenger,
+        token::token_program = token_program
+    )]
+    pub challenger_nft_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the vault
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    /// Session of the player `caller` signs for, if it's a session key
+    #[account(
+        seeds = [b"session", session.owner.as_ref()],
+        bump = session.bump
+    )]
+    pub session: Option<Account<'info, SessionAuthority>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelNftMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        close = host,
+        constraint = match_account.kind == MatchKind::Nft @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(address = match_account.host_nft_mint)]
+    pub host_nft_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nft_escrow",
+            match_account.match_id.as_ref(),
+            match_account.host_nft_mint.as_ref()
+        ],
+        bump
+    )]
+    pub host_nft_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = host_nft_mint,
+        token::authority = host,
+        token::token_program = token_program
+    )]
+    pub host_nft_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding the flat fee
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the vault
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(series_id: [u8; 32])]
+pub struct CreateSeries<'info> {
+    #[account(
+        init,
+        payer = host,
+        space = 8 + Series::INIT_SPACE,
+        seeds = [b"series", series_id.as_ref()],
+        bump
+    )]
+    pub series: Account<'info, Series>,
+
+    #[account(
+        mut,
+        seeds = [b"series_escrow", series_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub series_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinSeries<'info> {
+    #[account(
+        mut,
+        seeds = [b"series", series.series_id.as_ref()],
+        bump = series.bump
+    )]
+    pub series: Account<'info, Series>,
+
+    #[account(
+        mut,
+        seeds = [b"series_escrow", series.series_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
+    pub series_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RecordSeriesGame<'info> {
+    #[account(
+        mut,
+        seeds = [b"series", series.series_id.as_ref()],
+        bump = series.bump
+    )]
+    pub series: Account<'info, Series>,
+
+    #[account(
+        mut,
+        seeds = [b"series_escrow", series.series_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding both series stakes
+    pub series_escrow: SystemAccount<'info>,
+
+    pub caller: Signer<'info>,
+
+    /// CHECK: Host account for payouts - validated by address constraint
+    #[account(mut, address = series.host)]
+    pub host_account: SystemAccount<'info>,
+
+    /// CHECK: Challenger account for payouts - validated by address constraint
+    #[account(mut, address = series.challenger)]
+    pub challenger_account: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury for fees - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSeries<'info> {
+    #[account(
+        mut,
+        seeds = [b"series", series.series_id.as_ref()],
+        bump = series.bump,
+        has_one = host @ StakingError::NotHost,
+        close = host
+    )]
+    pub series: Account<'info, Series>,
+
+    #[account(
+        mut,
+        seeds = [b"series_escrow", series.series_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds SOL for refund
+    pub series_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub host: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the escrow
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSeries<'info> {
+    #[account(
+        mut,
+        seeds = [b"series", series.series_id.as_ref()],
+        bump = series.bump,
+        close = host
+    )]
+    pub series: Account<'info, Series>,
+
+    pub caller: Signer<'info>,
+
+    /// CHECK: Receives the rent back; must be the host, who paid it
+    #[account(mut, address = series.host @ StakingError::RentPayerMismatch)]
+    pub host: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: [u8; 32])]
+pub struct CreateTournament<'info> {
+    #[account(
+        init,
+        payer = organizer,
+        space = 8 + Tournament::INIT_SPACE,
+        seeds = [b"tournament", tournament_id.as_ref()],
+        bump
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    #[account(mut)]
+    pub organizer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterForTournament<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.as_ref()],
+        bump = tournament.bump
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(
+        mut,
+        seeds = [b"tournament_escrow", tournament.tournament_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives entry fees
+    pub tournament_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReportTournamentResult<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.as_ref()],
+        bump = tournament.bump,
+        has_one = schedule
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    #[account(
+        mut,
+        seeds = [b"tournament_escrow", tournament.tournament_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding the prize pool
+    pub tournament_escrow: SystemAccount<'info>,
+
+    /// Organizer, or the losing player attesting the result
+    pub reporter: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury for fees - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTournament<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.as_ref()],
+        bump = tournament.bump,
+        has_one = organizer @ StakingError::NotOrganizer,
+        close = organizer
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(
+        mut,
+        seeds = [b"tournament_escrow", tournament.tournament_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds entry fees for refund
+    pub tournament_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub organizer: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the escrow
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireTournament<'info> {
+    #[account(
+        mut,
+        seeds = [b"tournament", tournament.tournament_id.as_ref()],
+        bump = tournament.bump,
+        has_one = organizer @ StakingError::NotOrganizer,
+        close = organizer
+    )]
+    pub tournament: Account<'info, Tournament>,
+
+    #[account(
+        mut,
+        seeds = [b"tournament_escrow", tournament.tournament_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds entry fees for refund
+    pub tournament_escrow: SystemAccount<'info>,
+
+    /// CHECK: Organizer, receives the tournament's rent
+    #[account(mut)]
+    pub organizer: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the escrow
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(league_id: [u8; 32])]
+pub struct CreateLeague<'info> {
+    #[account(
+        init,
+        payer = organizer,
+        space = 8 + League::INIT_SPACE,
+        seeds = [b"league", league_id.as_ref()],
+        bump
+    )]
+    pub league: Account<'info, League>,
+
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    #[account(mut)]
+    pub organizer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinLeague<'info> {
+    #[account(
+        mut,
+        seeds = [b"league", league.league_id.as_ref()],
+        bump = league.bump
+    )]
+    pub league: Account<'info, League>,
+
+    #[account(
+        mut,
+        seeds = [b"league_escrow", league.league_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives entry fees
+    pub league_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageLeague<'info> {
+    #[account(
+        mut,
+        seeds = [b"league", league.league_id.as_ref()],
+        bump = league.bump,
+        has_one = organizer @ StakingError::NotOrganizer,
+        has_one = schedule
+    )]
+    pub league: Account<'info, League>,
+
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    pub organizer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReportLeagueResult<'info> {
+    #[account(
+        mut,
+        seeds = [b"league", league.league_id.as_ref()],
+        bump = league.bump,
+        has_one = schedule
+    )]
+    pub league: Account<'info, League>,
+
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    #[account(
+        mut,
+        seeds = [b"league_escrow", league.league_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding the season prize pool
+    pub league_escrow: SystemAccount<'info>,
+
+    /// Organizer, or the losing player attesting the result
+    pub reporter: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury for fees - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLeague<'info> {
+    #[account(
+        mut,
+        seeds = [b"league", league.league_id.as_ref()],
+        bump = league.bump,
+        has_one = organizer @ StakingError::NotOrganizer,
+        close = organizer
+    )]
+    pub league: Account<'info, League>,
+
+    #[account(
+        mut,
+        seeds = [b"league_escrow", league.league_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds entry fees for refund
+    pub league_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub organizer: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the escrow
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireLeague<'info> {
+    #[account(
+        mut,
+        seeds = [b"league", league.league_id.as_ref()],
+        bump = league.bump,
+        has_one = organizer @ StakingError::NotOrganizer,
+        close = organizer
+    )]
+    pub league: Account<'info, League>,
+
+    #[account(
+        mut,
+        seeds = [b"league_escrow", league.league_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds entry fees for refund
+    pub league_escrow: SystemAccount<'info>,
+
+    /// CHECK: Organizer, receives the league's rent
+    #[account(mut)]
+    pub organizer: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the escrow
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(schedule_id: [u8; 32])]
+pub struct CreatePayoutSchedule<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PayoutSchedule::INIT_SPACE,
+        seeds = [b"payout_schedule", schedule_id.as_ref()],
+        bump
+    )]
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(pot_id: [u8; 32])]
+pub struct CreatePot<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + Pot::INIT_SPACE,
+        seeds = [b"pot", pot_id.as_ref()],
+        bump
+    )]
+    pub pot: Account<'info, Pot>,
+
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinPot<'info> {
+    #[account(
+        mut,
+        seeds = [b"pot", pot.pot_id.as_ref()],
+        bump = pot.bump
+    )]
+    pub pot: Account<'info, Pot>,
+
+    #[account(
+        mut,
+        seeds = [b"pot_escrow", pot.pot_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives buy-ins
+    pub pot_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LockPot<'info> {
+    #[account(
+        mut,
+        seeds = [b"pot", pot.pot_id.as_ref()],
+        bump = pot.bump,
+        has_one = creator @ StakingError::NotOrganizer,
+        has_one = schedule
+    )]
+    pub pot: Account<'info, Pot>,
+
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PostPotResults<'info> {
+    #[account(
+        mut,
+        seeds = [b"pot", pot.pot_id.as_ref()],
+        bump = pot.bump,
+        has_one = result_authority @ StakingError::NotResultAuthority,
+        has_one = schedule
+    )]
+    pub pot: Account<'info, Pot>,
+
+    #[account(
+        mut,
+        seeds = [b"pot_escrow", pot.pot_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding the pot
+    pub pot_escrow: SystemAccount<'info>,
+
+    pub schedule: Account<'info, PayoutSchedule>,
+
+    pub result_authority: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury for fees - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPot<'info> {
+    #[account(
+        mut,
+        seeds = [b"pot", pot.pot_id.as_ref()],
+        bump = pot.bump,
+        has_one = creator @ StakingError::NotOrganizer,
+        close = creator
+    )]
+    pub pot: Account<'info, Pot>,
+
+    #[account(
+        mut,
+        seeds = [b"pot_escrow", pot.pot_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds buy-ins for refund
+    pub pot_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the escrow
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpirePot<'info> {
+    #[account(
+        mut,
+        seeds = [b"pot", pot.pot_id.as_ref()],
+        bump = pot.bump,
+        has_one = creator @ StakingError::NotOrganizer,
+        close = creator
+    )]
+    pub pot: Account<'info, Pot>,
+
+    #[account(
+        mut,
+        seeds = [b"pot_escrow", pot.pot_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds buy-ins for refund
+    pub pot_escrow: SystemAccount<'info>,
+
+    /// CHECK: Creator, receives the pot's rent
+    #[account(mut)]
+    pub creator: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the escrow
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenBetPool<'info> {
+    #[account(
+        init,
+        payer = opener,
+        space = 8 + BetPool::INIT_SPACE,
+        seeds = [b"bet_pool", match_account.match_id.as_ref()],
+        bump
+    )]
+    pub bet_pool: Account<'info, BetPool>,
+
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(mut)]
+    pub opener: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBet<'info> {
+    #[account(
+        mut,
+        seeds = [b"bet_pool", bet_pool.match_id.as_ref()],
+        bump = bet_pool.bump
+    )]
+    pub bet_pool: Account<'info, BetPool>,
+
+    #[account(
+        seeds = [b"match", bet_pool.match_id.as_ref()],
+        bump = match_account.bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        init,
+        payer = bettor,
+        space = 8 + Bet::INIT_SPACE,
+        seeds = [b"bet", bet_pool.match_id.as_ref(), bettor.key().as_ref()],
+        bump
+    )]
+    pub bet: Account<'info, Bet>,
+
+    #[account(
+        mut,
+        seeds = [b"bet_escrow", bet_pool.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds spectator bets
+    pub bet_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub bettor: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleBetPool<'info> {
+    #[account(
+        mut,
+        seeds = [b"bet_pool", bet_pool.match_id.as_ref()],
+        bump = bet_pool.bump
+    )]
+    pub bet_pool: Account<'info, BetPool>,
+
+    /// CHECK: The pool's match PDA, read with MatchAccount::load_any. It may
+    /// already be cancelled and closed, which voids the pool.
+    #[account(
+        mut,
+        seeds = [b"match", bet_pool.match_id.as_ref()],
+        bump
+    )]
+    pub match_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"bet_escrow", bet_pool.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding spectator bets
+    pub bet_escrow: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury for fees - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimBet<'info> {
+    #[account(
+        mut,
+        seeds = [b"bet_pool", bet_pool.match_id.as_ref()],
+        bump = bet_pool.bump
+    )]
+    pub bet_pool: Account<'info, BetPool>,
+
+    #[account(
+        mut,
+        seeds = [b"bet", bet_pool.match_id.as_ref(), bettor.key().as_ref()],
+        bump = bet.bump,
+        has_one = bettor,
+        close = bettor
+    )]
+    pub bet: Account<'info, Bet>,
+
+    #[account(
+        mut,
+        seeds = [b"bet_escrow", bet_pool.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding spectator bets
+    pub bet_escrow: SystemAccount<'info>,
+
+    /// CHECK: The bettor, receives the payout and the bet account's rent
+    #[account(mut)]
+    pub bettor: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives forfeited winnings
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBetPool<'info> {
+    #[account(
+        mut,
+        seeds = [b"bet_pool", bet_pool.match_id.as_ref()],
+        bump = bet_pool.bump,
+        has_one = opener,
+        close = opener
+    )]
+    pub bet_pool: Account<'info, BetPool>,
+
+    #[account(
+        mut,
+        seeds = [b"bet_escrow", bet_pool.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: PDA escrow vault holding spectator bets
+    pub bet_escrow: SystemAccount<'info>,
+
+    /// CHECK: Whoever opened the pool, receives its rent
+    #[account(mut)]
+    pub opener: SystemAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: Platform treasury, receives anything left in the escrow
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SponsorMatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"match", match_account.match_id.as_ref()],
+        bump = match_account.bump,
+        constraint = match_account.kind == MatchKind::Sol @ StakingError::WrongMatchKind
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_account.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives the bonus
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(
+        init,
+        payer = sponsor,
+        space = 8 + Sponsorship::INIT_SPACE,
+        seeds = [
+            b"sponsorship",
+            match_account.match_id.as_ref(),
+            sponsor.key().as_ref()
+        ],
+        bump
+    )]
+    pub sponsorship: Account<'info, Sponsorship>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSponsorship<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sponsorship",
+            sponsorship.match_id.as_ref(),
+            sponsor.key().as_ref()
+        ],
+        bump = sponsorship.bump,
+        has_one = sponsor,
+        close = sponsor
+    )]
+    pub sponsorship: Account<'info, Sponsorship>,
+
+    /// CHECK: The sponsored match PDA, read with MatchAccount::load_any. It
+    /// may already be closed.
+    #[account(
+        mut,
+        seeds = [b"match", sponsorship.match_id.as_ref()],
+        bump
+    )]
+    pub match_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", sponsorship.match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds the draw refund
+    pub escrow_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(bucket: u8)]
+pub struct InitLobbyBucket<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + LobbyBucket::INIT_SPACE,
+        seeds = [b"lobby", bucket.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub lobby: Account<'info, LobbyBucket>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PruneLobby<'info> {
+    #[account(
+        mut,
+        seeds = [b"lobby", lobby.bucket.to_le_bytes().as_ref()],
+        bump = lobby.bump
+    )]
+    pub lobby: Account<'info, LobbyBucket>,
+}
+
+#[derive(Accounts)]
+#[instruction(bucket: u8)]
+pub struct InitMatchmakingQueue<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + MatchmakingQueue::INIT_SPACE,
+        seeds = [b"queue", bucket.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub queue: Account<'info, MatchmakingQueue>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Enqueue<'info> {
+    #[account(
+        mut,
+        seeds = [b"queue", queue.bucket.to_le_bytes().as_ref()],
+        bump = queue.bump
+    )]
+    pub queue: Account<'info, MatchmakingQueue>,
+
+    #[account(
+        mut,
+        seeds = [b"queue_escrow", queue.bucket.to_le_bytes().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds queued stakes
+    pub queue_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LeaveQueue<'info> {
+    #[account(
+        mut,
+        seeds = [b"queue", queue.bucket.to_le_bytes().as_ref()],
+        bump = queue.bump
+    )]
+    pub queue: Account<'info, MatchmakingQueue>,
+
+    #[account(
+        mut,
+        seeds = [b"queue_escrow", queue.bucket.to_le_bytes().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds queued stakes
+    pub queue_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32])]
+pub struct Pair<'info> {
+    #[account(
+        mut,
+        seeds = [b"queue", queue.bucket.to_le_bytes().as_ref()],
+        bump = queue.bump
+    )]
+    pub queue: Account<'info, MatchmakingQueue>,
+
+    #[account(
+        mut,
+        seeds = [b"queue_escrow", queue.bucket.to_le_bytes().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds queued stakes
+    pub queue_escrow: SystemAccount<'info>,
+
+    #[account(
+        init,
+        payer = pairer,
+        space = 8 + MatchAccount::INIT_SPACE,
+        seeds = [b"match", match_id.as_ref()],
+        bump
+    )]
+    pub match_account: Account<'info, MatchAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - receives both stakes
+    pub escrow_vault: SystemAccount<'info>,
+
+    /// CHECK: The earlier entrant's wallet, receives any excess stake (checked in the handler)
+    #[account(mut)]
+    pub host: UncheckedAccount<'info>,
+
+    /// CHECK: The later entrant's wallet, receives any excess stake (checked in the handler)
+    #[account(mut)]
+    pub challenger: UncheckedAccount<'info>,
+
+    /// Either player, or anyone pairing in queue order; advances the match rent
+    #[account(mut)]
+    pub pairer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateMatch<'info> {
+    #[account(mut, owner = crate::ID)]
+    /// CHECK: May still be in the legacy layout - decoded with MatchAccount::load_any
+    pub match_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Config::INIT_SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::PokechessStaking>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ StakingError::NotAdmin
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ StakingError::NotAdmin
+    )]
+    pub config: Account<'info, Config>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.pending_admin == new_admin.key() @ StakingError::NotPendingAdmin
+    )]
+    pub config: Account<'info, Config>,
+
+    pub new_admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddAllowedMint<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ StakingError::NotAdmin
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + MintConfig::INIT_SPACE,
+        seeds = [b"mint_config", mint.key().as_ref()],
+        bump
+    )]
+    pub mint_config: Account<'info, MintConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAllowedMint<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ StakingError::NotAdmin
+    )]
+    pub config: Account<'info, Config>,
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"mint_config", mint_config.mint.as_ref()],
+        bump = mint_config.bump
+    )]
+    pub mint_config: Account<'info, MintConfig>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveAllowedMint<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ StakingError::NotAdmin
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"mint_config", mint_config.mint.as_ref()],
+        bump = mint_config.bump,
+        close = admin
+    )]
+    pub mint_config: Account<'info, MintConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: [u8; 32])]
+pub struct SweepOrphanedEscrow<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ StakingError::NotAdmin
+    )]
+    pub config: Account<'info, Config>,
+
+    pub admin: Signer<'info>,
+
+    #[account(seeds = [b"match", match_id.as_ref()], bump)]
+    /// CHECK: Must be closed (no lamports, no data) - checked in the handler
+    pub match_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", match_id.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as escrow vault - holds the orphaned SOL
+    pub escrow_vault: SystemAccount<'info>,
+
+    /// CHECK: Platform treasury - must match the config
+    #[account(mut, address = config.treasury)]
+    pub platform_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// ============================================================================
+// State
+// ============================================================================
+
+/// Program-wide settings, a singleton PDA owned by the admin
+#[account]
+#[derive(InitSpace)]
+pub struct Config {
+    /// Admin allowed to change the config and sweep orphaned escrows
+    pub admin: Pubkey,
+
+    /// Wallet receiving platform fees and escrow remainders
+    pub treasury: Pubkey,
+
+    /// Whether matches left in `Completed` by the retired declare_winner can
+    /// still be finalized
+    pub legacy_compat: bool,
+
+    /// SOL/USD price feed used by USD matches (default until set_price_feed)
+    pub price_feed: Pubkey,
+
+    /// PDA bump seed
+    pub bump: u8,
+
+    /// Unix timestamp after which legacy_compat no longer applies
+    pub legacy_compat_until: i64,
+
+    /// Admin proposed by update_config, until they accept (default if none)
+    pub pending_admin: Pubkey,
+}
+
+/// Allowlist entry for a token stake mint, one PDA per mint
+#[account]
+#[derive(InitSpace)]
+pub struct MintConfig {
+    /// Mint this entry applies to
+    pub mint: Pubkey,
+
+    /// Minimum stake per player, in the mint's base units
+    pub min_stake: u64,
+
+    /// Maximum stake per player, in the mint's base units
+    pub max_stake: u64,
+
+    /// Platform fee for matches in this mint, in basis points
+    pub fee_bps: u16,
+
+    /// Whether new matches may be created with this mint
+    pub enabled: bool,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+/// Per-player SOL balance that matches can be staked from and paid back into.
+/// Everything above the account's rent-exempt minimum is the player's balance.
+#[account]
+#[derive(InitSpace)]
+pub struct PlayerVault {
+    /// Wallet that owns the balance and can withdraw it
+    pub owner: Pubkey,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl PlayerVault {
+    pub fn address(owner: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[b"player_vault", owner.as_ref()], &crate::ID).0
+    }
+
+    /// Lamports the owner of the vault account `info` can spend or withdraw
+    pub fn available_balance(info: &AccountInfo) -> Result<u64> {
+        let rent_exempt = Rent::get()?.minimum_balance(info.data_len());
+        Ok(info.lamports().saturating_sub(rent_exempt))
+    }
+}
+
+/// Backers' funds for one player's stake in one match. Like a PlayerVault,
+/// the pool account itself holds the lamports: backers' payments until the
+/// stake is placed, then whatever the match pays that side.
+#[account]
+#[derive(InitSpace)]
+pub struct BackingPool {
+    /// Match the stake is for
+    pub match_id: [u8; 32],
+
+    /// Player being backed
+    pub player: Pubkey,
+
+    /// Stake to raise in lamports
+    pub target: u64,
+
+    /// Markup backers pay on top of their share, in basis points
+    pub markup_bps: u16,
+
+    /// Stake raised so far
+    pub total_backed: u64,
+
+    /// Markup collected so far. Held in the pool; the player gets it when the
+    /// pool closes if the match started, the backers otherwise.
+    pub total_markup: u64,
+
+    /// Whether the match started, so `total_markup` belongs to the player
+    pub markup_released: bool,
+
+    /// Lamports the match paid back to the pool (set at settlement)
+    pub returned: u64,
+
+    /// Current pool status
+    pub status: BackingStatus,
+
+    /// The match's `created_at`, so a reused match id is not mistaken for it
+    pub match_created_at: i64,
+
+    /// Backing accounts not yet claimed
+    pub open_backings: u32,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl BackingPool {
+    pub fn address(match_id: &[u8; 32], player: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"backing_pool", match_id.as_ref(), player.as_ref()],
+            &crate::ID,
+        )
+        .0
+    }
+}
+
+/// One backer's share of a BackingPool
+#[account]
+#[derive(InitSpace)]
+pub struct Backing {
+    /// Match the stake is for
+    pub match_id: [u8; 32],
+
+    /// Player being backed
+    pub player: Pubkey,
+
+    /// Who is backing
+    pub backer: Pubkey,
+
+    /// Stake contributed in lamports
+    pub amount: u64,
+
+    /// Markup paid on top, refunded if the pool is cancelled or the match
+    /// never starts
+    pub markup_paid: u64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum BackingStatus {
+    /// Taking backers
+    #[default]
+    Open,
+    /// Stake placed in the match
+    Staked,
+    /// Match over; backers can claim their share of `returned`
+    Settled,
+    /// Called off before staking; backers are refunded in full
+    Cancelled,
+}
+
+/// Ephemeral key a player authorized to sign in-game instructions for them,
+/// one PDA per player
+#[account]
+#[derive(InitSpace)]
+pub struct SessionAuthority {
+    /// Player the session acts for
+    pub owner: Pubkey,
+
+    /// Key allowed to sign on the owner's behalf
+    pub session_key: Pubkey,
+
+    /// Unix timestamp the session stops working at
+    pub expires_at: i64,
+
+    /// Largest stake the session key may commit to a single match
+    pub max_stake_per_match: u64,
+
+    /// Total lamports the session key may stake over its lifetime
+    pub spend_cap: u64,
+
+    /// Lamports staked through this session so far
+    pub spent: u64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl SessionAuthority {
+    /// Counts a stake against the session's limits
+    pub fn record_stake(&mut self, amount: u64) -> Result<()> {
+        require!(
+            amount <= self.max_stake_per_match,
+            StakingError::SessionStakeTooHigh
+        );
+        let spent = self
+            .spent
+            .checked_add(amount)
+            .ok_or(StakingError::MathOverflow)?;
+        require!(
+            spent <= self.spend_cap,
+            StakingError::SessionSpendCapExceeded
+        );
+        self.spent = spent;
+        Ok(())
+    }
+}
+
+/// Escrowed match between a host and a challenger.
+///
+/// Layout is append-only: the original (version 0) fields come first, then the
+/// `version` byte, then everything added since. New fields must go at the end
+/// and treat all-zero bytes as a sensible default, so older accounts can be
+/// read by zero-padding them and upgraded in place with migrate_match.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct MatchAccount {
+    /// Unique match identifier (can be room code hash)
+    pub match_id: [u8; 32],
+
+    /// Host player (white, creates the match)
+    pub host: Pubkey,
+
+    /// Challenger player (black, joins the match)
+    pub challenger: Pubkey,
+
+    /// Stake amount per player in lamports
+    pub stake_amount: u64,
+
+    /// Current match status
+    pub status: MatchStatus,
+
+    /// Winner's public key (set after match ends)
+    pub winner: Pubkey,
+
+    /// Unix timestamp when match was created
+    pub created_at: i64,
+
+    /// PDA bump seed
+    pub bump: u8,
+
+    /// Layout version (0 for accounts created before versioning)
+    pub version: u8,
+
+    /// Participant with an open draw offer (default if none)
+    pub draw_offered_by: Pubkey,
+
+    /// Unix timestamp after which the draw offer can no longer be accepted
+    pub draw_offer_expires_at: i64,
+
+    /// Reason given with the draw offer, kept as the recorded result on accept
+    pub draw_reason: DrawReason,
+
+    /// Match this one is a rematch of (zeroed for regular matches)
+    pub rematch_of: [u8; 32],
+
+    /// Player who proposed the rematch and staked first
+    pub rematch_proposer: Pubkey,
+
+    /// What the players staked
+    pub kind: MatchKind,
+
+    /// SPL mint of the stake for token matches (default for SOL)
+    pub stake_mint: Pubkey,
+
+    /// Platform fee in basis points, fixed when the match was created
+    pub fee_bps: u16,
+
+    /// NFT the host wagered (NFT matches only)
+    pub host_nft_mint: Pubkey,
+
+    /// NFT the challenger wagered (NFT matches only)
+    pub challenger_nft_mint: Pubkey,
+
+    /// Verified collection both NFTs must belong to (default if unrestricted)
+    pub nft_collection: Pubkey,
+
+    /// Flat SOL fee each player paid instead of a bps cut (NFT matches only)
+    pub flat_fee_lamports: u64,
+
+    /// USD value of the stake in cents; 0 for matches staked in plain lamports
+    pub stake_usd_cents: u64,
+
+    /// Host staked from their PlayerVault, so payouts to them go back there
+    pub host_uses_vault: bool,
+
+    /// Challenger staked from their PlayerVault, so payouts to them go back there
+    pub challenger_uses_vault: bool,
+
+    /// Fee payer that advanced rent and fees for the players (default if none)
+    pub relayer: Pubkey,
+
+    /// Transaction fees owed to the relayer, paid from escrow at settlement.
+    /// The rent it advanced is returned by close_match instead.
+    pub relayer_advanced: u64,
+
+    /// Challenger's stake; `stake_amount` is the host's. Equal unless the
+    /// match was created with odds.
+    pub challenger_stake: u64,
+
+    /// Only this player may join (default if not restricted to one player)
+    pub invited_challenger: Pubkey,
+
+    /// Merkle root of pubkeys allowed to join (all zero if no group)
+    pub invite_root: [u8; 32],
+
+    /// Host's stake was raised from backers, so payouts to them go to their BackingPool
+    pub host_backed: bool,
+
+    /// Challenger's stake was raised from backers, so payouts to them go to their BackingPool
+    pub challenger_backed: bool,
+
+    /// Sponsor bonus lamports in escrow on top of the stakes, paid to the winner
+    pub sponsor_bonus: u64,
+
+    /// Part of `sponsor_bonus` returned to its sponsors on a draw (the rest is split)
+    pub sponsor_draw_refund: u64,
+
+    /// Number of sponsors owed a refund on a draw, less those who claimed it
+    pub draw_refund_sponsors: u8,
+
+    /// Time control, variant, rated flag and color choice, for the lobby
+    pub metadata: MatchMetadata,
+
+    /// Who paid the account's rent, refunded by close_match
+    pub rent_payer: Pubkey,
+
+    /// Unix timestamp when the match was settled or drawn (0 until then)
+    pub finished_at: i64,
+
+    /// A spectator BetPool on this match still needs settle_bet_pool, so the
+    /// match can't be closed yet
+    pub bet_pool_open: bool,
+
+    /// Lobby bucket the match is listed in, if any. Joining, cancelling or
+    /// restricting the match must pass this bucket to delist it.
+    pub listed_bucket: Option<u8>,
+}
+
+// The account traits are written out rather than generated by `#[account]` so
+// that every `Account<MatchAccount>` can load a legacy account. Writing one
+// back still needs migrate_match to grow it first.
+impl Discriminator for MatchAccount {
+    /// `sha256("account:MatchAccount")[..8]`, as `#[account]` derives it
+    const DISCRIMINATOR: &'static [u8] = &[235, 36, 243, 39, 81, 16, 144, 87];
+}
+
+impl Owner for MatchAccount {
+    fn owner() -> Pubkey {
+        crate::ID
+    }
+}
+
+impl AccountSerialize for MatchAccount {
+    fn try_serialize<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
+        writer
+            .write_all(Self::DISCRIMINATOR)
+            .map_err(|_| ErrorCode::AccountDidNotSerialize)?;
+        AnchorSerialize::serialize(self, writer).map_err(|_| ErrorCode::AccountDidNotSerialize)?;
+        Ok(())
+    }
+}
+
+impl AccountDeserialize for MatchAccount {
+    fn try_deserialize(buf: &mut &[u8]) -> Result<Self> {
+        require!(
+            buf.len() >= Self::DISCRIMINATOR.len(),
+            ErrorCode::AccountDiscriminatorNotFound
+        );
+        require!(
+            buf[..8] == *Self::DISCRIMINATOR,
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+        Self::try_deserialize_unchecked(buf)
+    }
+
+    fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+        require!(
+            buf.len() >= Self::LEGACY_LEN,
+            ErrorCode::AccountDidNotDeserialize
+        );
+
+        // Zero-pad anything shorter than the current layout
+        let data = &buf[8..];
+        let decoded = if data.len() >= Self::INIT_SPACE {
+            Self::deserialize(&mut &data[..])
+        } else {
+            let mut padded = data.to_vec();
+            padded.resize(Self::INIT_SPACE, 0);
+            Self::deserialize(&mut padded.as_slice())
+        };
+        let mut account = decoded.map_err(|_| ErrorCode::AccountDidNotDeserialize)?;
+
+        // Fields whose zero value isn't the right default for legacy matches
+        if account.version == 0 {
+            account.fee_bps = PLATFORM_FEE_BPS as u16;
+            account.challenger_stake = account.stake_amount;
+            account.rent_payer = account.host;
+        }
+
+        Ok(account)
+    }
+}
+
+/// Game settings the lobby filters on. All zero (the default) for matches
+/// created without them.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub struct MatchMetadata {
+    /// Starting clock per player in seconds (0 if unspecified)
+    pub time_control_base_seconds: u32,
+
+    /// Seconds added to the clock per move
+    pub time_control_increment_seconds: u32,
+
+    /// Pokemon chess rule set (0 for standard rules)
+    pub variant: u16,
+
+    /// Whether the result counts toward ratings
+    pub rated: bool,
+
+    /// Color the host wants to play
+    pub host_color: ColorPreference,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum ColorPreference {
+    #[default]
+    Random,
+    White,
+    Black,
+}
+
+/// Index of open matches whose challenger stake falls in one bucket of
+/// LOBBY_BUCKET_BOUNDS, so clients can list games without scanning every
+/// program account
+#[account]
+#[derive(InitSpace)]
+pub struct LobbyBucket {
+    /// Bucket index (see `lobby_bucket`)
+    pub bucket: u8,
+
+    /// Open matches, oldest first
+    #[max_len(32)]
+    pub entries: Vec<LobbyEntry>,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+/// One open match in a LobbyBucket
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct LobbyEntry {
+    pub match_id: [u8; 32],
+    pub host: Pubkey,
+    /// Stake the challenger pays to join
+    pub stake_amount: u64,
+    pub metadata: MatchMetadata,
+    /// The match's `created_at`, so a reused match id is not mistaken for it
+    pub created_at: i64,
+}
+
+/// Players waiting to be paired in one stake bucket (the lobby buckets).
+/// Their stakes are held in `queue_escrow`.
+#[account]
+#[derive(InitSpace)]
+pub struct MatchmakingQueue {
+    /// Bucket index (see `lobby_bucket`)
+    pub bucket: u8,
+
+    /// Waiting players, oldest first
+    #[max_len(32)]
+    pub entries: Vec<QueueEntry>,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+/// One player waiting in a MatchmakingQueue
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct QueueEntry {
+    pub player: Pubkey,
+    /// Stake escrowed, in lamports
+    pub stake_amount: u64,
+    /// Smallest stake the player accepts being paired at, in lamports
+    pub min_stake: u64,
+    /// Preferred starting clock in seconds
+    pub time_control_base_seconds: u32,
+    /// Preferred per-move increment in seconds
+    pub time_control_increment_seconds: u32,
+    pub enqueued_at: i64,
+}
+
+impl QueueEntry {
+    /// Whether two entries can be paired: same time control, and the smaller
+    /// of the two stakes is one both players accept
+    pub fn can_pair_with(&self, other: &QueueEntry) -> bool {
+        let stake_amount = self.stake_amount.min(other.stake_amount);
+        self.time_control_base_seconds == other.time_control_base_seconds
+            && self.time_control_increment_seconds == other.time_control_increment_seconds
+            && stake_amount >= self.min_stake
+            && stake_amount >= other.min_stake
+    }
+}
+
+/// SOL/USD price as stored in the configured price feed account: the Borsh
+/// encoding of this struct at offset 0, no discriminator. The USD price of
+/// one SOL is `price * 10^expo`, give or take `conf` in the same units.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PriceFeed {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+impl PriceFeed {
+    /// Reads the feed, rejecting stale, non-positive or too uncertain prices
+    pub fn load(info: &AccountInfo) -> Result<PriceFeed> {
+        let data = info.try_borrow_data()?;
+        let feed = PriceFeed::deserialize(&mut &data[..])
+            .map_err(|_| StakingError::InvalidPriceFeed)?;
+
+        require!(feed.price > 0, StakingError::InvalidPriceFeed);
+        require!(
+            Clock::get()?.unix_timestamp - feed.publish_time
+                <= pokechess_staking::PRICE_MAX_AGE_SECONDS,
+            StakingError::StalePrice
+        );
+        require!(
+            feed.conf as u128 * 10_000
+                <= feed.price as u128 * pokechess_staking::PRICE_MAX_CONF_BPS as u128,
+            StakingError::PriceTooUncertain
+        );
+
+        Ok(feed)
+    }
+
+    /// Converts a USD amount in cents to lamports at this price
+    pub fn usd_cents_to_lamports(&self, usd_cents: u64) -> Result<u64> {
+        // lamports = cents / 100 * 10^9 / (price * 10^expo)
+        let (scale_num, scale_den) = if self.expo <= 0 {
+            (7 + self.expo.unsigned_abs(), 0)
+        } else {
+            (7, self.expo as u32)
+        };
+        let numerator = 10u128
+            .checked_pow(scale_num)
+            .and_then(|scale| scale.checked_mul(usd_cents as u128))
+            .ok_or(StakingError::MathOverflow)?;
+        let denominator = 10u128
+            .checked_pow(scale_den)
+            .and_then(|scale| scale.checked_mul(self.price as u128))
+            .ok_or(StakingError::MathOverflow)?;
+
+        u64::try_from(numerator / denominator).map_err(|_| StakingError::MathOverflow.into())
+    }
+}
+
+impl MatchAccount {
+    /// Account size (discriminator included) of the original, unversioned layout
+    pub const LEGACY_LEN: usize = 8 + 32 + 32 + 32 + 8 + 1 + 32 + 8 + 1;
+
+    /// Deserializes a match account that isn't held as an `Account`, e.g. one
+    /// that may already be closed. Legacy accounts read as version 0.
+    pub fn load_any(info: &AccountInfo) -> Result<MatchAccount> {
+        Self::try_deserialize(&mut &info.try_borrow_data()?[..])
+    }
+
+    /// An open SOL match waiting for a challenger at an even stake and paid for
+    /// by the host, with every other field at its default. Create paths override what differs.
+    pub fn new(match_id: [u8; 32], host: Pubkey, stake_amount: u64, bump: u8) -> Result<Self> {
+        Ok(Self {
+            match_id,
+            host,
+            challenger: Pubkey::default(),
+            stake_amount,
+            status: MatchStatus::WaitingForChallenger,
+            winner: Pubkey::default(),
+            created_at: Clock::get()?.unix_timestamp,
+            bump,
+            version: MATCH_ACCOUNT_VERSION,
+            draw_offered_by: Pubkey::default(),
+            draw_offer_expires_at: 0,
+            draw_reason: DrawReason::default(),
+            rematch_of: [0u8; 32],
+            rematch_proposer: Pubkey::default(),
+            kind: MatchKind::Sol,
+            stake_mint: Pubkey::default(),
+            fee_bps: PLATFORM_FEE_BPS as u16,
+            host_nft_mint: Pubkey::default(),
+            challenger_nft_mint: Pubkey::default(),
+            nft_collection: Pubkey::default(),
+            flat_fee_lamports: 0,
+            stake_usd_cents: 0,
+            host_uses_vault: false,
+            challenger_uses_vault: false,
+            relayer: Pubkey::default(),
+            relayer_advanced: 0,
+            challenger_stake: stake_amount,
+            invited_challenger: Pubkey::default(),
+            invite_root: [0u8; 32],
+            host_backed: false,
+            challenger_backed: false,
+            sponsor_bonus: 0,
+            sponsor_draw_refund: 0,
+            draw_refund_sponsors: 0,
+            metadata: MatchMetadata::default(),
+            rent_payer: host,
+            finished_at: 0,
+            bet_pool_open: false,
+            listed_bucket: None,
+        })
+    }
+
+    /// Records the final result and when it was reached
+    pub fn finish(&mut self, status: MatchStatus) -> Result<()> {
+        self.status = status;
+        self.finished_at = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+
+    /// Whether the game has been decided and paid out
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status, MatchStatus::Settled | MatchStatus::Draw)
+    }
+
+    /// Checks `challenger` may join: anyone can unless the match names an
+    /// invited challenger or an invite group, in which case they must be that
+    /// player or prove membership of the group
+    pub fn check_invite(&self, challenger: &Pubkey, proof: &[[u8; 32]]) -> Result<()> {
+        let invited = self.invited_challenger != Pubkey::default();
+        let has_group = self.invite_root != [0u8; 32];
+        if !invited && !has_group {
+            return Ok(());
+        }
+
+        let allowed = (invited && *challenger == self.invited_challenger)
+            || (has_group && verify_invite_proof(challenger, proof, &self.invite_root));
+        require!(allowed, StakingError::NotInvited);
+        Ok(())
+    }
+
+    /// Both stakes together
+    pub fn total_pot(&self) -> Result<u64> {
+        self.stake_amount
+            .checked_add(self.challenger_stake)
+            .ok_or(StakingError::MathOverflow.into())
+    }
+
+    /// What `player` stakes in this match, by seat
+    pub fn stake_of(&self, player: Pubkey) -> u64 {
+        if player == self.host {
+            self.stake_amount
+        } else {
+            self.challenger_stake
+        }
+    }
+
+    /// Where `player`'s winnings and refunds go: their backing pool, vault or wallet
+    pub fn payout_address(&self, player: Pubkey) -> Pubkey {
+        let uses_vault = (player == self.host && self.host_uses_vault)
+            || (player == self.challenger && self.challenger_uses_vault);
+        let backed = (player == self.host && self.host_backed)
+            || (player == self.challenger && self.challenger_backed);
+        if backed {
+            BackingPool::address(&self.match_id, &player)
+        } else if uses_vault {
+            PlayerVault::address(&player)
+        } else {
+            player
+        }
+    }
+
+    /// Deterministic id of this match's rematch, so neither player needs a new room code
+    pub fn rematch_id(&self) -> [u8; 32] {
+        hashv(&[b"rematch".as_ref(), self.match_id.as_ref()]).to_bytes()
+    }
+}
+
+/// Best-of-N series between two players, escrowed once in `series_escrow`.
+/// Reuses MatchStatus for its lifecycle.
+#[account]
+#[derive(InitSpace)]
+pub struct Series {
+    /// Unique series identifier
+    pub series_id: [u8; 32],
+
+    /// Host player (creates the series)
+    pub host: Pubkey,
+
+    /// Challenger player (joins the series)
+    pub challenger: Pubkey,
+
+    /// Stake amount per player in lamports, for the whole series
+    pub stake_amount: u64,
+
+    /// Number of games in the series
+    pub best_of: u8,
+
+    /// Games recorded so far, drawn games included
+    pub games_played: u8,
+
+    /// Games won by the host
+    pub host_wins: u8,
+
+    /// Games won by the challenger
+    pub challenger_wins: u8,
+
+    /// Current series status
+    pub status: MatchStatus,
+
+    /// Series winner (default until settled, and for a drawn series)
+    pub winner: Pubkey,
+
+    /// Platform fee in basis points, fixed when the series was created
+    pub fee_bps: u16,
+
+    /// Timestamp when the series was created
+    pub created_at: i64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl Series {
+    /// Player with more game wins, if either
+    pub fn leader(&self) -> Option<Pubkey> {
+        match self.host_wins.cmp(&self.challenger_wins) {
+            std::cmp::Ordering::Greater => Some(self.host),
+            std::cmp::Ordering::Less => Some(self.challenger),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    /// A player has clinched a majority of games, or every game is played
+    pub fn is_decided(&self) -> bool {
+        let needed = self.best_of / 2 + 1;
+        self.host_wins >= needed
+            || self.challenger_wins >= needed
+            || self.games_played >= self.best_of
+    }
+}
+
+/// Single-elimination tournament with entry fees held in `tournament_escrow`.
+/// Pairings come from the seeding and the reported results; no MatchAccount
+/// is created for bracket games.
+#[account]
+#[derive(InitSpace)]
+pub struct Tournament {
+    /// Unique tournament identifier
+    pub tournament_id: [u8; 32],
+
+    /// Creator, who reports results and can cancel before it starts
+    pub organizer: Pubkey,
+
+    /// Entry fee per player in lamports
+    pub entry_fee: u64,
+
+    /// Number of players in the bracket (a power of two)
+    pub size: u8,
+
+    /// PayoutSchedule the prize pool is paid out by
+    pub schedule: Pubkey,
+
+    /// Current tournament status
+    pub status: TournamentStatus,
+
+    /// Current round, 0 being the first
+    pub round: u8,
+
+    /// Registered players, in registration (seeding) order
+    #[max_len(16)]
+    pub players: Vec<Pubkey>,
+
+    /// Players left in the current round, in bracket order. Match `i` of the
+    /// round is `alive[2i]` against `alive[2i + 1]`.
+    #[max_len(16)]
+    pub alive: Vec<Pubkey>,
+
+    /// Reported winner of each match this round (default until reported)
+    #[max_len(8)]
+    pub round_winners: Vec<Pubkey>,
+
+    /// Eliminated players, best first; the champion is prepended at the end
+    #[max_len(16)]
+    pub placements: Vec<Pubkey>,
+
+    /// Platform fee in basis points, fixed when the tournament was created
+    pub fee_bps: u16,
+
+    /// Timestamp when the tournament was created
+    pub created_at: i64,
+
+    /// Deadline for the final, after which anyone can refund the entry fees
+    pub settle_by: i64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl Tournament {
+    /// Opens the current round for results and announces its pairings
+    pub fn start_round(&mut self) {
+        let matches = self.alive.len() / 2;
+        self.round_winners = vec![Pubkey::default(); matches];
+
+        for (match_index, pair) in self.alive.chunks(2).enumerate() {
+            emit!(TournamentMatchReady {
+                tournament_id: self.tournament_id,
+                round: self.round,
+                match_index: match_index as u8,
+                player_a: pair[0],
+                player_b: pair[1],
+            });
+        }
+    }
+}
+
+/// Round-robin or Swiss league with a points table and a season prize pool
+/// held in `league_escrow`. Reuses TournamentStatus for its lifecycle.
+#[account]
+#[derive(InitSpace)]
+pub struct League {
+    /// Unique league identifier
+    pub league_id: [u8; 32],
+
+    /// Creator, who starts the season, pairs Swiss rounds and reports results
+    pub organizer: Pubkey,
+
+    /// Entry fee per player in lamports
+    pub entry_fee: u64,
+
+    /// Most players that can join
+    pub max_players: u8,
+
+    /// How rounds are paired
+    pub format: LeagueFormat,
+
+    /// Rounds in the season (set at start for round-robin)
+    pub rounds: u8,
+
+    /// Points awarded per result
+    pub points_per_result: LeaguePoints,
+
+    /// PayoutSchedule the season prize pool is paid out by
+    pub schedule: Pubkey,
+
+    /// Current league status
+    pub status: TournamentStatus,
+
+    /// Current round, 0 being the first
+    pub round: u8,
+
+    /// Players, in join order
+    #[max_len(16)]
+    pub players: Vec<Pubkey>,
+
+    /// Points table, parallel to `players`
+    #[max_len(16)]
+    pub points: Vec<u32>,
+
+    /// Current round's pairings (empty while a Swiss round awaits pairing)
+    #[max_len(8)]
+    pub pairings: Vec<LeaguePairing>,
+
+    /// Platform fee in basis points, fixed when the league was created
+    pub fee_bps: u16,
+
+    /// Timestamp when the league was created
+    pub created_at: i64,
+
+    /// Deadline for the season to finish, after which anyone can refund it
+    pub settle_by: i64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl League {
+    /// Pairings for the current round by the circle method. With an odd
+    /// number of players the extra seat is a bye and its pairing is skipped.
+    pub fn round_robin_pairings(&self) -> Vec<LeaguePairing> {
+        let players = self.players.len();
+        let seats = players + players % 2;
+        let seat = |k: usize| {
+            if k == 0 {
+                0
+            } else {
+                (k - 1 + self.round as usize) % (seats - 1) + 1
+            }
+        };
+
+        (0..seats / 2)
+            .map(|i| (seat(i), seat(seats - 1 - i)))
+            .filter(|(a, b)| *a < players && *b < players)
+            .map(|(a, b)| LeaguePairing {
+                player_a: a as u8,
+                player_b: b as u8,
+                result: LeagueResult::Pending,
+            })
+            .collect()
+    }
+
+    /// Emits a LeaguePairingReady for each pairing of the current round
+    pub fn announce_pairings(&self) {
+        for (match_index, pairing) in self.pairings.iter().enumerate() {
+            emit!(LeaguePairingReady {
+                league_id: self.league_id,
+                round: self.round,
+                match_index: match_index as u8,
+                player_a: self.players[pairing.player_a as usize],
+                player_b: self.players[pairing.player_b as usize],
+            });
+        }
+    }
+
+    /// Players ranked by points, ties broken by join order
+    pub fn standings(&self) -> Vec<Pubkey> {
+        let mut order: Vec<usize> = (0..self.players.len()).collect();
+        order.sort_by(|a, b| self.points[*b].cmp(&self.points[*a]).then(a.cmp(b)));
+        order.into_iter().map(|i| self.players[i]).collect()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum LeagueFormat {
+    /// Everyone plays everyone once, paired by the program
+    #[default]
+    RoundRobin,
+    /// A fixed number of rounds, paired by the organizer each round
+    Swiss,
+}
+
+/// Points for a win, draw and loss
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub struct LeaguePoints {
+    pub win: u8,
+    pub draw: u8,
+    pub loss: u8,
+}
+
+/// One game in a league round, players given as indexes into `League::players`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct LeaguePairing {
+    pub player_a: u8,
+    pub player_b: u8,
+    pub result: LeagueResult,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum LeagueResult {
+    #[default]
+    Pending,
+    PlayerAWins,
+    PlayerBWins,
+    Draw,
+}
+
+/// How a prize pool is split by placement. Reusable across pots, tournaments
+/// and leagues.
+#[account]
+#[derive(InitSpace)]
+pub struct PayoutSchedule {
+    /// Unique schedule identifier
+    pub schedule_id: [u8; 32],
+
+    /// Creator of the schedule
+    pub authority: Pubkey,
+
+    /// Fixed share per placement in basis points, best first, summing to
+    /// 10,000 (empty when `paid_bps` is used)
+    #[max_len(16)]
+    pub shares: Vec<u16>,
+
+    /// Fraction of entrants paid, in basis points, split evenly (0 when
+    /// `shares` is used)
+    pub paid_bps: u16,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl PayoutSchedule {
+    /// Shares per placement for a field of `entrants`. A percentage schedule
+    /// pays at least one place; basis points that do not divide evenly go to
+    /// first place, as do the lamports lost to rounding at payout.
+    pub fn shares_for(&self, entrants: usize) -> Vec<u16> {
+        if self.paid_bps == 0 {
+            return self.shares.clone();
+        }
+
+        let places = (entrants * self.paid_bps as usize / 10_000).clamp(1, MAX_PAYOUT_PLACES);
+        let share = (10_000 / places) as u16;
+        let mut shares = vec![share; places];
+        shares[0] += 10_000 - share * places as u16;
+        shares
+    }
+}
+
+/// Multi-entrant pot: players buy into `pot_escrow` and the result authority
+/// posts final placings, paid out by a PayoutSchedule. Reuses
+/// TournamentStatus for its lifecycle.
+#[account]
+#[derive(InitSpace)]
+pub struct Pot {
+    /// Unique pot identifier
+    pub pot_id: [u8; 32],
+
+    /// Creator, who locks entries and can cancel before then
+    pub creator: Pubkey,
+
+    /// Who posts the final placings
+    pub result_authority: Pubkey,
+
+    /// PayoutSchedule the pot is paid out by
+    pub schedule: Pubkey,
+
+    /// Buy-in per entrant in lamports
+    pub buy_in: u64,
+
+    /// Most entrants allowed
+    pub max_entrants: u8,
+
+    /// Current pot status
+    pub status: TournamentStatus,
+
+    /// Entrants, in join order
+    #[max_len(32)]
+    pub entrants: Vec<Pubkey>,
+
+    /// Final placings, best first (empty until posted)
+    #[max_len(32)]
+    pub placements: Vec<Pubkey>,
+
+    /// Platform fee in basis points, fixed when the pot was created
+    pub fee_bps: u16,
+
+    /// Timestamp when the pot was created
+    pub created_at: i64,
+
+    /// Deadline for the placings, after which anyone can refund the buy-ins
+    pub settle_by: i64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+/// Parimutuel spectator betting on one match. Bets are held in `bet_escrow`
+/// and winners split the whole pool, less the bet pool fee, pro-rata.
+#[account]
+#[derive(InitSpace)]
+pub struct BetPool {
+    /// Match the pool bets on
+    pub match_id: [u8; 32],
+
+    /// Who opened the pool and paid its rent
+    pub opener: Pubkey,
+
+    /// The match's `created_at`, so a reused match id is not mistaken for it
+    pub match_created_at: i64,
+
+    /// The match host, who cannot win bets
+    pub host: Pubkey,
+
+    /// The match challenger, who cannot win bets (set at settlement)
+    pub challenger: Pubkey,
+
+    /// Lamports bet on the host winning
+    pub host_total: u64,
+
+    /// Lamports bet on the challenger winning
+    pub challenger_total: u64,
+
+    /// Lamports bet on a draw
+    pub draw_total: u64,
+
+    /// Current pool status
+    pub status: BetPoolStatus,
+
+    /// Outcome that won (meaningful once settled)
+    pub winning_outcome: BetOutcome,
+
+    /// Lamports bet on the winning outcome
+    pub winning_total: u64,
+
+    /// Lamports shared by winning bets, after the fee
+    pub payout_pool: u64,
+
+    /// Lamports bet on the winning outcome whose bets are not yet claimed
+    pub winning_unclaimed: u64,
+
+    /// Bets not yet claimed
+    pub open_bets: u32,
+
+    /// Fee in basis points, fixed when the pool was opened
+    pub fee_bps: u16,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl BetPool {
+    pub fn total_mut(&mut self, outcome: BetOutcome) -> &mut u64 {
+        match outcome {
+            BetOutcome::HostWins => &mut self.host_total,
+            BetOutcome::ChallengerWins => &mut self.challenger_total,
+            BetOutcome::Draw => &mut self.draw_total,
+        }
+    }
+}
+
+/// One spectator's bet in a BetPool
+#[account]
+#[derive(InitSpace)]
+pub struct Bet {
+    /// Match the bet is on
+    pub match_id: [u8; 32],
+
+    /// Who placed the bet
+    pub bettor: Pubkey,
+
+    /// Outcome backed
+    pub outcome: BetOutcome,
+
+    /// Lamports staked
+    pub amount: u64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum BetOutcome {
+    #[default]
+    HostWins,
+    ChallengerWins,
+    Draw,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum BetPoolStatus {
+    /// Taking bets, or waiting for the match result
+    #[default]
+    Open,
+    /// Result recorded; winning bets can be claimed
+    Settled,
+    /// Match cancelled or nobody backed the winner; every bet is refunded
+    Void,
+}
+
+/// A sponsor's bonus on one match. The lamports sit in the match escrow; this
+/// records who added them and what happens to them on a draw.
+#[account]
+#[derive(InitSpace)]
+pub struct Sponsorship {
+    /// Match sponsored
+    pub match_id: [u8; 32],
+
+    /// Who added the bonus
+    pub sponsor: Pubkey,
+
+    /// Bonus in lamports
+    pub amount: u64,
+
+    /// What happens to the bonus on a draw
+    pub draw_policy: SponsorDrawPolicy,
+
+    /// The match's `created_at`, so a reused match id is not mistaken for it
+    pub match_created_at: i64,
+
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum SponsorDrawPolicy {
+    /// Split the bonus evenly between both players
+    #[default]
+    Split,
+    /// Return the bonus to the sponsor
+    Return,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum TournamentStatus {
+    #[default]
+    Registration,
+    InProgress,
+    Completed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum MatchStatus {
+    #[default]
+    WaitingForChallenger,
+    InProgress,
+    /// Winner declared by the retired declare_winner, not yet paid out
+    Completed,
+    Cancelled,
+    Draw,
+    /// Winner paid out; the account is kept for rematches until close_match
+    Settled,
+    /// Rematch created by one player, waiting for the other to stake
+    WaitingForRematch,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum MatchKind {
+    /// Native SOL held in the `escrow` PDA
+    #[default]
+    Sol,
+    /// SPL or Token-2022 tokens held in the `token_escrow` account owned by the match PDA
+    Token,
+    /// One NFT per player held in `nft_escrow` accounts, flat SOL fee in `escrow`
+    Nft,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum DrawReason {
+    #[default]
+    Agreement,
+    Stalemate,
+    Repetition,
+    InsufficientMaterial,
+}
+
+// ============================================================================
+// Events
+// ============================================================================
+
+#[event]
+pub struct MatchCreated {
+    pub match_id: [u8; 32],
+    pub host: Pubkey,
+    pub stake_amount: u64,
+    pub metadata: MatchMetadata,
+}
+
+#[event]
+pub struct OddsMatchCreated {
+    pub match_id: [u8; 32],
+    pub host: Pubkey,
+    pub host_stake: u64,
+    pub challenger_stake: u64,
+}
+
+#[event]
+pub struct TokenMatchCreated {
+    pub match_id: [u8; 32],
+    pub host: Pubkey,
+    pub stake_mint: Pubkey,
+    pub stake_amount: u64,
+}
+
+#[event]
+pub struct UsdMatchCreated {
+    pub match_id: [u8; 32],
+    pub host: Pubkey,
+    pub stake_usd_cents: u64,
+    pub stake_amount: u64,
+    pub price: i64,
+    pub expo: i32,
+}
+
+#[event]
+pub struct NftMatchCreated {
+    pub match_id: [u8; 32],
+    pub host: Pubkey,
+    pub host_nft_mint: Pubkey,
+    pub collection: Pubkey,
+    pub flat_fee_lamports: u64,
+}
+
+#[event]
+pub struct MatchInviteUpdated {
+    pub match_id: [u8; 32],
+    pub invited_challenger: Pubkey,
+    pub invite_root: [u8; 32],
+}
+
+#[event]
+pub struct MatchListed {
+    pub match_id: [u8; 32],
+    pub bucket: u8,
+}
+
+#[event]
+pub struct MatchDelisted {
+    pub match_id: [u8; 32],
+    pub bucket: u8,
+}
+
+#[event]
+pub struct PlayerQueued {
+    pub bucket: u8,
+    pub player: Pubkey,
+    pub stake_amount: u64,
+    pub min_stake: u64,
+    pub time_control_base_seconds: u32,
+    pub time_control_increment_seconds: u32,
+}
+
+#[event]
+pub struct PlayerLeftQueue {
+    pub bucket: u8,
+    pub player: Pubkey,
+}
+
+#[event]
+pub struct QueuePaired {
+    pub match_id: [u8; 32],
+    pub bucket: u8,
+    pub host: Pubkey,
+    pub challenger: Pubkey,
+    pub stake_amount: u64,
+}
+
+#[event]
+pub struct MatchStarted {
+    pub match_id: [u8; 32],
+    pub host: Pubkey,
+    pub challenger: Pubkey,
+    pub total_pot: u64,
+}
+
+#[event]
+pub struct RematchCreated {
+    pub match_id: [u8; 32],
+    pub rematch_of: [u8; 32],
+    pub host: Pubkey,
+    pub challenger: Pubkey,
+    pub proposed_by: Pubkey,
+    pub stake_amount: u64,
+    pub challenger_stake: u64,
+}
+
+#[event]
+pub struct RewardClaimed {
+    pub match_id: [u8; 32],
+    pub winner: Pubkey,
+    pub amount: u64,
+    pub platform_fee: u64,
+}
+
+#[event]
+pub struct NftRewardClaimed {
+    pub match_id: [u8; 32],
+    pub winner: Pubkey,
+    pub host_nft_mint: Pubkey,
+    pub challenger_nft_mint: Pubkey,
+    pub platform_fee: u64,
+}
+
+#[event]
+pub struct MatchSponsored {
+    pub match_id: [u8; 32],
+    pub sponsor: Pubkey,
+    pub amount: u64,
+    pub draw_policy: SponsorDrawPolicy,
+    pub total_bonus: u64,
+}
+
+#[event]
+pub struct SponsorBonusPaid {
+    pub match_id: [u8; 32],
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MatchCancelled {
+    pub match_id: [u8; 32],
+    pub refunded_to: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SeriesCreated {
+    pub series_id: [u8; 32],
+    pub host: Pubkey,
+    pub stake_amount: u64,
+    pub best_of: u8,
+}
+
+#[event]
+pub struct SeriesStarted {
+    pub series_id: [u8; 32],
+    pub host: Pubkey,
+    pub challenger: Pubkey,
+    pub total_pot: u64,
+}
+
+#[event]
+pub struct SeriesGameRecorded {
+    pub series_id: [u8; 32],
+    pub game: u8,
+    /// Default for a drawn game
+    pub winner: Pubkey,
+    pub host_wins: u8,
+    pub challenger_wins: u8,
+}
+
+#[event]
+pub struct SeriesSettled {
+    pub series_id: [u8; 32],
+    /// Default for a drawn series, where `amount` is each player's refund
+    pub winner: Pubkey,
+    pub amount: u64,
+    pub platform_fee: u64,
+}
+
+#[event]
+pub struct TournamentCreated {
+    pub tournament_id: [u8; 32],
+    pub organizer: Pubkey,
+    pub entry_fee: u64,
+    pub size: u8,
+    pub schedule: Pubkey,
+}
+
+#[event]
+pub struct TournamentRegistered {
+    pub tournament_id: [u8; 32],
+    pub player: Pubkey,
+    pub entrants: u8,
+}
+
+#[event]
+pub struct TournamentMatchReady {
+    pub tournament_id: [u8; 32],
+    pub round: u8,
+    pub match_index: u8,
+    pub player_a: Pubkey,
+    pub player_b: Pubkey,
+}
+
+#[event]
+pub struct TournamentResultReported {
+    pub tournament_id: [u8; 32],
+    pub round: u8,
+    pub match_index: u8,
+    pub winner: Pubkey,
+    pub loser: Pubkey,
+}
+
+#[event]
+pub struct TournamentCompleted {
+    pub tournament_id: [u8; 32],
+    pub champion: Pubkey,
+    pub prize_pool: u64,
+    pub platform_fee: u64,
+}
+
+#[event]
+pub struct TournamentCancelled {
+    pub tournament_id: [u8; 32],
+    pub refunded_players: u8,
+    pub entry_fee: u64,
+}
+
+#[event]
+pub struct LeagueCreated {
+    pub league_id: [u8; 32],
+    pub organizer: Pubkey,
+    pub entry_fee: u64,
+    pub max_players: u8,
+    pub format: LeagueFormat,
+    pub schedule: Pubkey,
+}
+
+#[event]
+pub struct LeagueRegistered {
+    pub league_id: [u8; 32],
+    pub player: Pubkey,
+    pub entrants: u8,
+}
+
+#[event]
+pub struct LeaguePairingReady {
+    pub league_id: [u8; 32],
+    pub round: u8,
+    pub match_index: u8,
+    pub player_a: Pubkey,
+    pub player_b: Pubkey,
+}
+
+#[event]
+pub struct LeagueResultReported {
+    pub league_id: [u8; 32],
+    pub round: u8,
+    pub player_a: Pubkey,
+    pub player_b: Pubkey,
+    pub result: LeagueResult,
+}
+
+#[event]
+pub struct LeagueCompleted {
+    pub league_id: [u8; 32],
+    pub winner: Pubkey,
+    pub prize_pool: u64,
+    pub platform_fee: u64,
+}
+
+#[event]
+pub struct LeagueCancelled {
+    pub league_id: [u8; 32],
+    pub refunded_players: u8,
+    pub entry_fee: u64,
+}
+
+#[event]
+pub struct PayoutScheduleCreated {
+    pub schedule_id: [u8; 32],
+    pub authority: Pubkey,
+    pub shares: Vec<u16>,
+    pub paid_bps: u16,
+}
+
+#[event]
+pub struct PotCreated {
+    pub pot_id: [u8; 32],
+    pub creator: Pubkey,
+    pub result_authority: Pubkey,
+    pub schedule: Pubkey,
+    pub buy_in: u64,
+    pub max_entrants: u8,
+}
+
+#[event]
+pub struct PotJoined {
+    pub pot_id: [u8; 32],
+    pub player: Pubkey,
+    pub entrants: u8,
+}
+
+#[event]
+pub struct PotSettled {
+    pub pot_id: [u8; 32],
+    pub winner: Pubkey,
+    pub places_paid: u8,
+    pub prize_pool: u64,
+    pub platform_fee: u64,
+}
+
+#[event]
+pub struct PotCancelled {
+    pub pot_id: [u8; 32],
+    pub refunded_players: u8,
+    pub buy_in: u64,
+}
+
+#[event]
+pub struct BetPoolOpened {
+    pub match_id: [u8; 32],
+    pub opener: Pubkey,
+}
+
+#[event]
+pub struct BetPlaced {
+    pub match_id: [u8; 32],
+    pub bettor: Pubkey,
+    pub outcome: BetOutcome,
+    pub amount: u64,
+}
+
+#[event]
+pub struct BetPoolSettled {
+    pub match_id: [u8; 32],
+    pub voided: bool,
+    pub winning_outcome: BetOutcome,
+    pub total_staked: u64,
+    pub payout_pool: u64,
+}
+
+#[event]
+pub struct BetClaimed {
+    pub match_id: [u8; 32],
+    pub bettor: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PrizePaid {
+    /// Tournament (or other prize pool) the prize came from
+    pub pool_id: [u8; 32],
+    pub placement: u8,
+    pub player: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MatchMigrated {
+    pub match_id: [u8; 32],
+    pub from_version: u8,
+    pub to_version: u8,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+}
+
+#[event]
+pub struct MintConfigUpdated {
+    pub mint: Pubkey,
+    pub min_stake: u64,
+    pub max_stake: u64,
+    pub fee_bps: u16,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct MintRemoved {
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct AdminProposed {
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct LegacyCompatSet {
+    pub enabled: bool,
+}
+
+#[event]
+pub struct PriceFeedSet {
+    pub price_feed: Pubkey,
+}
+
+#[event]
+pub struct VaultDeposited {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub balance: u64,
+}
+
+#[event]
+pub struct VaultWithdrawn {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub balance: u64,
+}
+
+#[event]
+pub struct BackingPoolOpened {
+    pub match_id: [u8; 32],
+    pub player: Pubkey,
+    pub target: u64,
+    pub markup_bps: u16,
+}
+
+#[event]
+pub struct PlayerBacked {
+    pub match_id: [u8; 32],
+    pub player: Pubkey,
+    pub backer: Pubkey,
+    pub amount: u64,
+    pub markup: u64,
+}
+
+#[event]
+pub struct BackingPoolSettled {
+    pub match_id: [u8; 32],
+    pub player: Pubkey,
+    pub total_backed: u64,
+    pub returned: u64,
+}
+
+#[event]
+pub struct BackingClaimed {
+    pub match_id: [u8; 32],
+    pub player: Pubkey,
+    pub backer: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SessionCreated {
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    pub expires_at: i64,
+    pub max_stake_per_match: u64,
+    pub spend_cap: u64,
+}
+
+#[event]
+pub struct SessionRevoked {
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+}
+
+#[event]
+pub struct RelayerReimbursed {
+    pub match_id: [u8; 32],
+    pub relayer: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct EscrowSwept {
+    pub match_id: [u8; 32],
+    pub amount: u64,
+    pub treasury: Pubkey,
+}
+
+#[event]
+pub struct DrawOffered {
+    pub match_id: [u8; 32],
+    pub offered_by: Pubkey,
+    pub reason: DrawReason,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct MatchDraw {
+    pub match_id: [u8; 32],
+    pub refund_amount: u64,
+    pub challenger_refund_amount: u64,
+    pub reason: DrawReason,
+}
+
+// ============================================================================
+// Errors
 // ============================================================================
-// Accounts
-// ============================================================================
 
-#[derive(Accounts)]
-#[instruction(match_id: [u8; 32], stake_amount: u64)]
-pub struct CreateMatch<'info> {
-    #[account(
-        init,
-        payer = host,
-        space = 8 + MatchAccount::INIT_SPACE,
-        seeds = [b"match", match_id.as_ref()],
-        bump
-    )]
-    pub match_account: Account<'info, MatchAccount>,
+#[error_code]
+pub enum StakingError {
+    #[msg("Stake amount is below minimum (0.01 SOL)")]
+    StakeTooLow,
+
+    #[msg("Stake amount exceeds maximum (10 SOL)")]
+    StakeTooHigh,
+
+    #[msg("Match is not joinable")]
+    MatchNotJoinable,
+
+    #[msg("Cannot play against yourself")]
+    CannotPlaySelf,
+
+    #[msg("Match is not in progress")]
+    MatchNotInProgress,
+
+    #[msg("Invalid winner address")]
+    InvalidWinner,
+
+    #[msg("Caller is not a match participant")]
+    NotParticipant,
+
+    #[msg("Match is not completed")]
+    MatchNotCompleted,
+
+    #[msg("Caller is not the winner")]
+    NotWinner,
+
+    #[msg("Cannot cancel a match that has started")]
+    CannotCancelStartedMatch,
+
+    #[msg("Caller is not the host")]
+    NotHost,
+
+    #[msg("Opponent has a pending draw offer")]
+    DrawOfferPending,
+
+    #[msg("No draw offer to accept")]
+    NoDrawOffer,
+
+    #[msg("Cannot accept your own draw offer")]
+    CannotAcceptOwnDrawOffer,
+
+    #[msg("Draw offer has expired")]
+    DrawOfferExpired,
+
+    #[msg("Match is not finished")]
+    MatchNotFinished,
+
+    #[msg("Rematch id does not match the previous match")]
+    InvalidRematchId,
+
+    #[msg("Player has already staked")]
+    AlreadyStaked,
+
+    #[msg("Caller is not the rematch proposer")]
+    NotRematchProposer,
+
+    #[msg("Caller is not the config admin")]
+    NotAdmin,
+
+    #[msg("Match account still exists")]
+    MatchStillOpen,
+
+    #[msg("Match account is already on the current layout")]
+    AlreadyMigrated,
+
+    #[msg("Account is not a canonical match PDA")]
+    InvalidMatchAccount,
+
+    #[msg("Legacy compatibility is disabled")]
+    LegacyCompatDisabled,
+
+    #[msg("Instruction does not support this match's stake type")]
+    WrongMatchKind,
+
+    #[msg("Mint has an extension that can't be safely escrowed")]
+    UnsupportedMintExtension,
+
+    #[msg("Escrow received less than the matched stake")]
+    TransferFeeMismatch,
+
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+
+    #[msg("Mint is not accepted for stakes")]
+    MintNotAllowed,
+
+    #[msg("Invalid stake limits")]
+    InvalidStakeLimits,
+
+    #[msg("Fee exceeds the maximum allowed")]
+    FeeTooHigh,
+
+    #[msg("Mint is not a 1-of-1 NFT")]
+    InvalidNft,
+
+    #[msg("NFT is not in the match's verified collection")]
+    NftNotInCollection,
+
+    #[msg("No price feed configured")]
+    PriceFeedNotSet,
+
+    #[msg("Price feed account is malformed or has a non-positive price")]
+    InvalidPriceFeed,
+
+    #[msg("Price feed is stale")]
+    StalePrice,
+
+    #[msg("Price confidence interval is too wide")]
+    PriceTooUncertain,
+
+    #[msg("Price moved too far since the match was created")]
+    PriceMoved,
+
+    #[msg("Not enough SOL in the player vault")]
+    InsufficientVaultBalance,
+
+    #[msg("Player vault does not belong to the joining player")]
+    WrongPlayerVault,
+
+    #[msg("Signer is not this session's key")]
+    SessionKeyMismatch,
+
+    #[msg("Session has expired")]
+    SessionExpired,
+
+    #[msg("Stake exceeds the session's per-match limit")]
+    SessionStakeTooHigh,
+
+    #[msg("Stake exceeds the session's spend cap")]
+    SessionSpendCapExceeded,
+
+    #[msg("Relayer fee exceeds the maximum allowed")]
+    RelayerFeeTooHigh,
+
+    #[msg("Relayer cannot be a player in the match")]
+    RelayerIsPlayer,
+
+    #[msg("Relayer does not match the one recorded for this match")]
+    RelayerMismatch,
+
+    #[msg("Challenger is not invited to this match")]
+    NotInvited,
+
+    #[msg("Series length must be an odd number of games up to the maximum")]
+    InvalidSeriesLength,
+
+    #[msg("Tournament size must be a power of two up to the maximum")]
+    InvalidTournamentSize,
+
+    #[msg("Prize shares must cover at most the paid placements and sum to 10,000 bps")]
+    InvalidPrizeSplit,
+
+    #[msg("Player is already registered")]
+    AlreadyRegistered,
+
+    #[msg("Only the organizer can do this")]
+    NotOrganizer,
+
+    #[msg("No such match in the current round")]
+    InvalidBracketMatch,
+
+    #[msg("Result already reported for this match")]
+    ResultAlreadyReported,
+
+    #[msg("Payout accounts do not match the expected players")]
+    WrongPrizeRecipient,
+
+    #[msg("Invalid league settings")]
+    InvalidLeagueConfig,
+
+    #[msg("Not enough players to start")]
+    NotEnoughPlayers,
+
+    #[msg("Pairings are invalid or not expected now")]
+    InvalidPairings,
+
+    #[msg("Payout shares must sum to 10,000 basis points")]
+    InvalidPayoutSchedule,
+
+    #[msg("Only the result authority can post results")]
+    NotResultAuthority,
+
+    #[msg("Placings must be distinct entrants covering every paid place")]
+    InvalidPlacements,
+
+    #[msg("Betting on this match has closed")]
+    BettingClosed,
+
+    #[msg("Players cannot bet on their own match")]
+    PlayerCannotBet,
+
+    #[msg("Bet pool has not been settled yet")]
+    BetPoolNotSettled,
+
+    #[msg("Bets in this pool are still unclaimed")]
+    BetsOutstanding,
+
+    #[msg("Markup exceeds the maximum allowed")]
+    MarkupTooHigh,
+
+    #[msg("Backing pool is not taking backers")]
+    BackingClosed,
+
+    #[msg("Backing pool has not raised its full stake")]
+    BackingNotFunded,
+
+    #[msg("Backing pool has not been settled yet")]
+    BackingNotSettled,
+
+    #[msg("Backers have not all claimed yet")]
+    BackersOutstanding,
+
+    #[msg("Time control exceeds the maximum allowed")]
+    InvalidTimeControl,
+
+    #[msg("Lobby bucket does not match the stake")]
+    InvalidLobbyBucket,
+
+    #[msg("Lobby bucket is full")]
+    LobbyFull,
+
+    #[msg("Matchmaking queue is full")]
+    QueueFull,
+
+    #[msg("Player is not in this queue")]
+    NotQueued,
+
+    #[msg("Queue entries cannot be paired")]
+    IncompatibleQueueEntries,
+
+    #[msg("Match can still be rematched and cannot be closed yet")]
+    RematchWindowOpen,
+
+    #[msg("Rent payer does not match the match account")]
+    RentPayerMismatch,
+
+    #[msg("Session lasts longer than allowed")]
+    SessionTooLong,
+
+    #[msg("Invite groups are only supported on plain SOL matches")]
+    InviteGroupNotSupported,
+
+    #[msg("Result deadline must be in the future")]
+    InvalidSettleDeadline,
+
+    #[msg("Result deadline has not passed yet")]
+    SettleDeadlineNotReached,
+
+    #[msg("A host-backed match must be joined with the host's staked BackingPool")]
+    HostBackingPoolRequired,
+
+    #[msg("Sponsors have draw refunds left to claim")]
+    SponsorRefundsOutstanding,
+
+    #[msg("The match is listed in the lobby; pass its lobby bucket")]
+    LobbyBucketRequired,
+
+    #[msg("Minimum acceptable stake is above the stake")]
+    InvalidMinStake,
+
+    #[msg("Only the players can pair entries out of queue order")]
+    PairNotInQueueOrder,
+
+    #[msg("Signer is not the proposed admin")]
+    NotPendingAdmin,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::instruction::Instruction;
+    use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+    use anchor_lang::solana_program::program_error::ProgramError;
+    use anchor_lang::solana_program::program_stubs::{self, SyscallStubs};
+    use anchor_lang::{InstructionData, ToAccountMetas};
+    use spl_token_2022::extension::permanent_delegate::PermanentDelegate;
+    use spl_token_2022::extension::transfer_fee::TransferFee;
+    use spl_token_2022::extension::{BaseStateWithExtensionsMut, StateWithExtensionsMut};
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::sync::Once;
+
+    /// Largest account the test runtime can hold
+    const DATA_CAPACITY: usize = 10 * 1024;
+
+    thread_local! {
+        static NOW: Cell<i64> = const { Cell::new(1_700_000_000) };
+    }
+
+    /// Off-chain stand-ins for the syscalls instructions make: Clock, Rent and
+    /// the system program's transfer, create, allocate and assign
+    struct Stubs;
+
+    impl SyscallStubs for Stubs {
+        fn sol_log(&self, _message: &str) {}
+
+        fn sol_log_data(&self, _fields: &[&[u8]]) {}
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                unix_timestamp: NOW.with(Cell::get),
+                ..Clock::default()
+            };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            0
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            0
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> std::result::Result<(), ProgramError> {
+            assert_eq!(
+                instruction.program_id,
+                system_program::ID,
+                "unsupported CPI"
+            );
+            let find = |index: usize| {
+                let key = instruction.accounts[index].pubkey;
+                account_infos.iter().find(|info| *info.key == key).unwrap()
+            };
+            let data = &instruction.data;
+            let u64_at = |at: usize| u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+            let move_lamports = |from: &AccountInfo,
+                                 to: &AccountInfo,
+                                 amount: u64|
+             -> std::result::Result<(), ProgramError> {
+                **from.try_borrow_mut_lamports()? = from
+                    .lamports()
+                    .checked_sub(amount)
+                    .ok_or(ProgramError::InsufficientFunds)?;
+                **to.try_borrow_mut_lamports()? += amount;
+                Ok(())
+            };
+            match u32::from_le_bytes(data[..4].try_into().unwrap()) {
+                // CreateAccount { lamports, space, owner }
+                0 => {
+                    let owner = Pubkey::try_from(&data[20..52]).unwrap();
+                    move_lamports(find(0), find(1), u64_at(4))?;
+                    find(1).resize(u64_at(12) as usize)?;
+                    find(1).assign(&owner);
+                }
+                // Assign { owner }
+                1 => find(0).assign(&Pubkey::try_from(&data[4..36]).unwrap()),
+                // Transfer { lamports }
+                2 => move_lamports(find(0), find(1), u64_at(4))?,
+                // Allocate { space }
+                8 => find(0).resize(u64_at(4) as usize)?,
+                other => panic!("unsupported system instruction {other}"),
+            }
+            Ok(())
+        }
+    }
+
+    /// The runtime reads an account's original length 4 bytes before its key
+    #[repr(C)]
+    struct KeyCell {
+        original_data_len: u32,
+        key: Pubkey,
+    }
+
+    struct Slot {
+        key: Box<KeyCell>,
+        lamports: u64,
+        owner: Pubkey,
+        /// 8-byte length prefix, then the data, as the runtime lays it out
+        data: Vec<u64>,
+        len: usize,
+        executable: bool,
+    }
+
+    /// Accounts for driving instructions through `entry`, with the runtime's
+    /// lamport-conservation and rent checks after each one
+    struct Bank {
+        slots: BTreeMap<Pubkey, Slot>,
+    }
+
+    impl Bank {
+        fn new() -> Self {
+            static STUBS: Once = Once::new();
+            STUBS.call_once(|| {
+                program_stubs::set_syscall_stubs(Box::new(Stubs));
+            });
+
+            let mut bank = Bank {
+                slots: BTreeMap::new(),
+            };
+            for program in [system_program::ID, crate::ID] {
+                bank.slot(&program).executable = true;
+            }
+            bank
+        }
+
+        fn slot(&mut self, key: &Pubkey) -> &mut Slot {
+            self.slots.entry(*key).or_insert_with(|| Slot {
+                key: Box::new(KeyCell {
+                    original_data_len: 0,
+                    key: *key,
+                }),
+                lamports: 0,
+                owner: system_program::ID,
+                data: vec![0; 1 + DATA_CAPACITY / 8],
+                len: 0,
+                executable: false,
+            })
+        }
+
+        /// A wallet holding `lamports`
+        fn wallet(&mut self, lamports: u64) -> Pubkey {
+            let key = Pubkey::new_unique();
+            self.slot(&key).lamports = lamports;
+            key
+        }
+
+        fn lamports(&self, key: &Pubkey) -> u64 {
+            self.slots.get(key).map_or(0, |slot| slot.lamports)
+        }
 
-    #[account(
-        mut,
-        seeds = [b"escrow", match_id.as_ref()],
-        bump
-    )]
-    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
-    pub escrow_vault: SystemAccount<'info>,
+        fn data(&self, key: &Pubkey) -> &[u8] {
+            let slot = &self.slots[key];
+            let bytes = unsafe {
+                std::slice::from_raw_parts(slot.data.as_ptr() as *const u8, 8 + slot.len)
+            };
+            &bytes[8..]
+        }
 
-    #[account(mut)]
-    pub host: Signer<'info>,
+        /// Writes a program account directly, rent-exempt, as if created earlier
+        fn put<T: AccountSerialize>(&mut self, key: &Pubkey, account: &T) {
+            let mut bytes = Vec::new();
+            account.try_serialize(&mut bytes).unwrap();
+            self.put_raw(key, &bytes);
+        }
 
-    pub system_program: Program<'info, System>,
-}
+        fn put_raw(&mut self, key: &Pubkey, bytes: &[u8]) {
+            let slot = self.slot(key);
+            slot.owner = crate::ID;
+            slot.len = bytes.len();
+            slot.lamports = Rent::default().minimum_balance(bytes.len());
+            let data = unsafe {
+                std::slice::from_raw_parts_mut(slot.data.as_mut_ptr() as *mut u8, 8 + bytes.len())
+            };
+            data[8..].copy_from_slice(bytes);
+        }
 
-#[derive(Accounts)]
-pub struct JoinMatch<'info> {
-    #[account(
-        mut,
-        seeds = [b"match", match_account.match_id.as_ref()],
-        bump = match_account.bump
-    )]
-    pub match_account: Account<'info, MatchAccount>,
+        fn get<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+            T::try_deserialize(&mut self.data(key)).unwrap()
+        }
 
-    #[account(
-        mut,
-        seeds = [b"escrow", match_account.match_id.as_ref()],
-        bump
-    )]
-    /// CHECK: This is a PDA used as escrow vault - receives SOL transfers
-    pub escrow_vault: SystemAccount<'info>,
+        /// Runs one instruction, rolling nothing back on failure: tests only
+        /// go on after instructions that succeed
+        fn run(
+            &mut self,
+            accounts: impl ToAccountMetas,
+            args: impl InstructionData,
+        ) -> std::result::Result<(), ProgramError> {
+            let metas = accounts.to_account_metas(None);
+            for meta in &metas {
+                self.slot(&meta.pubkey);
+            }
+            let before: BTreeMap<Pubkey, u64> = self
+                .slots
+                .iter()
+                .map(|(key, slot)| (*key, slot.lamports))
+                .collect();
 
-    #[account(mut)]
-    pub challenger: Signer<'info>,
+            let (result, lens) = {
+                let mut infos = BTreeMap::new();
+                for (key, slot) in self.slots.iter_mut() {
+                    let Some(meta) = metas.iter().find(|meta| meta.pubkey == *key) else {
+                        continue;
+                    };
+                    let writable = metas.iter().any(|m| m.pubkey == *key && m.is_writable);
+                    let signer = metas.iter().any(|m| m.pubkey == *key && m.is_signer);
+                    slot.key.original_data_len = slot.len as u32;
+                    let len = slot.len;
+                    let bytes = unsafe {
+                        std::slice::from_raw_parts_mut(
+                            slot.data.as_mut_ptr() as *mut u8,
+                            8 + DATA_CAPACITY,
+                        )
+                    };
+                    bytes[..8].copy_from_slice(&(len as u64).to_le_bytes());
+                    infos.insert(
+                        meta.pubkey,
+                        AccountInfo::new(
+                            &slot.key.key,
+                            signer,
+                            writable,
+                            &mut slot.lamports,
+                            &mut bytes[8..8 + len],
+                            &slot.owner,
+                            slot.executable,
+                            0,
+                        ),
+                    );
+                }
+                let ordered: Vec<AccountInfo> = metas
+                    .iter()
+                    .map(|meta| infos[&meta.pubkey].clone())
+                    .collect();
+                let result = crate::entry(&crate::ID, &ordered, &args.data());
+                let lens: Vec<(Pubkey, usize)> = infos
+                    .iter()
+                    .map(|(key, info)| (*key, info.data_len()))
+                    .collect();
+                (result, lens)
+            };
+            for (key, len) in lens {
+                self.slot(&key).len = len;
+            }
+            result?;
 
-    pub system_program: Program<'info, System>,
-}
+            let total = |lamports: &BTreeMap<Pubkey, u64>| lamports.values().sum::<u64>();
+            let after: BTreeMap<Pubkey, u64> = self
+                .slots
+                .iter()
+                .map(|(key, slot)| (*key, slot.lamports))
+                .collect();
+            assert_eq!(total(&before), total(&after), "lamports not conserved");
+            for (key, slot) in &self.slots {
+                let rent_paying =
+                    slot.lamports > 0 && slot.lamports < Rent::default().minimum_balance(slot.len);
+                assert!(
+                    !rent_paying || before[key] == slot.lamports,
+                    "{key} left below the rent-exempt minimum"
+                );
+            }
+            Ok(())
+        }
+    }
 
-/// New combined instruction: declare winner + distribute rewards in ONE tx
-/// Winner does NOT need to sign - only the caller (participant) signs
-#[derive(Accounts)]
-pub struct ClaimWinnerReward<'info> {
-    #[account(
-        mut,
-        seeds = [b"match", match_account.match_id.as_ref()],
-        bump = match_account.bump,
-        close = caller
-    )]
-    pub match_account: Account<'info, MatchAccount>,
+    /// Instruction accounts followed by writable remaining accounts
+    struct WithRemaining<A>(A, Vec<Pubkey>);
+
+    impl<A: ToAccountMetas> ToAccountMetas for WithRemaining<A> {
+        fn to_account_metas(&self, is_signer: Option<bool>) -> Vec<AccountMeta> {
+            let mut metas = self.0.to_account_metas(is_signer);
+            metas.extend(self.1.iter().map(|key| AccountMeta::new(*key, false)));
+            metas
+        }
+    }
+
+    fn warp(seconds: i64) {
+        NOW.with(|now| now.set(now.get() + seconds));
+    }
+
+    fn staking_error(error: StakingError) -> ProgramError {
+        anchor_lang::error::Error::from(error).into()
+    }
+
+    fn pda(seeds: &[&[u8]]) -> Pubkey {
+        Pubkey::find_program_address(seeds, &crate::ID).0
+    }
+
+    /// A bank with the config PDA and its treasury in place
+    fn bank_with_config() -> (Bank, Pubkey) {
+        let mut bank = Bank::new();
+        let treasury = bank.wallet(LAMPORTS_PER_SOL);
+        let (config, bump) = Pubkey::find_program_address(&[b"config"], &crate::ID);
+        bank.put(
+            &config,
+            &Config {
+                admin: Pubkey::new_unique(),
+                treasury,
+                legacy_compat: true,
+                price_feed: Pubkey::default(),
+                bump,
+                legacy_compat_until: i64::MAX,
+                pending_admin: Pubkey::default(),
+            },
+        );
+        (bank, treasury)
+    }
+
+    fn hash_pair(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+        if a <= b {
+            hashv(&[a.as_ref(), b.as_ref()]).to_bytes()
+        } else {
+            hashv(&[b.as_ref(), a.as_ref()]).to_bytes()
+        }
+    }
+
+    #[test]
+    fn invite_proof_accepts_members_only() {
+        let members: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let leaves: Vec<[u8; 32]> = members
+            .iter()
+            .map(|member| hashv(&[member.as_ref()]).to_bytes())
+            .collect();
+        // Three leaves: the third is paired with the hash of the first two
+        let left = hash_pair(leaves[0], leaves[1]);
+        let root = hash_pair(left, leaves[2]);
+
+        assert!(verify_invite_proof(
+            &members[0],
+            &[leaves[1], leaves[2]],
+            &root
+        ));
+        assert!(verify_invite_proof(
+            &members[1],
+            &[leaves[0], leaves[2]],
+            &root
+        ));
+        assert!(verify_invite_proof(&members[2], &[left], &root));
+
+        let outsider = Pubkey::new_unique();
+        assert!(!verify_invite_proof(
+            &outsider,
+            &[leaves[1], leaves[2]],
+            &root
+        ));
+        assert!(!verify_invite_proof(&members[0], &[leaves[2]], &root));
+        assert!(!verify_invite_proof(&members[0], &[], &root));
+    }
+
+    #[test]
+    fn single_member_group_root_is_the_leaf() {
+        let member = Pubkey::new_unique();
+        let root = hashv(&[member.as_ref()]).to_bytes();
+        assert!(verify_invite_proof(&member, &[], &root));
+    }
+
+    fn league(players: usize, points: Vec<u32>) -> League {
+        League {
+            league_id: [0u8; 32],
+            organizer: Pubkey::default(),
+            entry_fee: 0,
+            max_players: 16,
+            format: LeagueFormat::RoundRobin,
+            rounds: 0,
+            points_per_result: LeaguePoints::default(),
+            schedule: Pubkey::default(),
+            status: TournamentStatus::InProgress,
+            round: 0,
+            players: (0..players).map(|_| Pubkey::new_unique()).collect(),
+            points,
+            pairings: Vec::new(),
+            fee_bps: 0,
+            created_at: 0,
+            settle_by: 0,
+            bump: 0,
+        }
+    }
+
+    /// Plays every round of a round-robin season, checking each player sits
+    /// in at most one game a round and every pair meets exactly once
+    fn check_round_robin(players: usize) {
+        let mut league = league(players, vec![0; players]);
+        let rounds = players + players % 2 - 1;
+        let mut met = vec![vec![0u32; players]; players];
+
+        for round in 0..rounds {
+            league.round = round as u8;
+            let pairings = league.round_robin_pairings();
+            assert_eq!(pairings.len(), players / 2);
+
+            let mut seated = vec![false; players];
+            for pairing in pairings {
+                let (a, b) = (pairing.player_a as usize, pairing.player_b as usize);
+                assert_ne!(a, b);
+                assert!(!seated[a] && !seated[b]);
+                seated[a] = true;
+                seated[b] = true;
+                met[a][b] += 1;
+                met[b][a] += 1;
+            }
+        }
+
+        for (a, row) in met.iter().enumerate() {
+            for (b, games) in row.iter().enumerate() {
+                assert_eq!(*games, if a == b { 0 } else { 1 }, "{a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn round_robin_pairs_everyone_once_with_even_field() {
+        for players in [2, 4, 6, 16] {
+            check_round_robin(players);
+        }
+    }
+
+    #[test]
+    fn round_robin_pairs_everyone_once_with_odd_field() {
+        for players in [3, 5, 7, 15] {
+            check_round_robin(players);
+        }
+    }
+
+    #[test]
+    fn standings_rank_by_points_then_join_order() {
+        let league = league(4, vec![3, 6, 3, 1]);
+        let p = &league.players;
+        assert_eq!(league.standings(), vec![p[1], p[0], p[2], p[3]]);
+    }
+
+    fn schedule(shares: Vec<u16>, paid_bps: u16) -> PayoutSchedule {
+        PayoutSchedule {
+            schedule_id: [0u8; 32],
+            authority: Pubkey::default(),
+            shares,
+            paid_bps,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn fixed_shares_are_used_as_is() {
+        let schedule = schedule(vec![6_000, 3_000, 1_000], 0);
+        assert_eq!(schedule.shares_for(2), vec![6_000, 3_000, 1_000]);
+        assert_eq!(schedule.shares_for(50), vec![6_000, 3_000, 1_000]);
+    }
+
+    #[test]
+    fn percentage_shares_give_the_remainder_to_first_place() {
+        // 30% of 10 entrants is 3 places; 10,000 / 3 leaves 1 bps over
+        let shares = schedule(Vec::new(), 3_000).shares_for(10);
+        assert_eq!(shares, vec![3_334, 3_333, 3_333]);
+        assert_eq!(shares.iter().map(|s| *s as u32).sum::<u32>(), 10_000);
+    }
+
+    #[test]
+    fn percentage_shares_pay_at_least_one_and_at_most_max_places() {
+        assert_eq!(schedule(Vec::new(), 1_000).shares_for(3), vec![10_000]);
+        let shares = schedule(Vec::new(), 10_000).shares_for(100);
+        assert_eq!(shares.len(), MAX_PAYOUT_PLACES);
+        assert_eq!(shares.iter().map(|s| *s as u32).sum::<u32>(), 10_000);
+    }
+
+    #[test]
+    fn lobby_bucket_boundaries() {
+        assert_eq!(lobby_bucket(MIN_STAKE_LAMPORTS), 0);
+        assert_eq!(lobby_bucket(49_999_999), 0);
+        assert_eq!(lobby_bucket(50_000_000), 1);
+        assert_eq!(lobby_bucket(99_999_999), 1);
+        assert_eq!(lobby_bucket(100_000_000), 2);
+        assert_eq!(lobby_bucket(500_000_000), 3);
+        assert_eq!(lobby_bucket(1_000_000_000), 4);
+        assert_eq!(lobby_bucket(4_999_999_999), 4);
+        assert_eq!(lobby_bucket(5_000_000_000), 5);
+        assert_eq!(lobby_bucket(u64::MAX), LOBBY_BUCKET_BOUNDS.len() as u8);
+    }
+
+    #[test]
+    fn relayer_gets_its_fee_from_the_pot_and_its_rent_from_close() {
+        let (mut bank, treasury) = bank_with_config();
+        let host = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let relayer = bank.wallet(LAMPORTS_PER_SOL);
+        let match_id = [38u8; 32];
+        let match_account = pda(&[b"match", &match_id]);
+        let escrow_vault = pda(&[b"escrow", &match_id]);
+        let stake = LAMPORTS_PER_SOL / 10;
+        let relayer_fee = 10_000;
+
+        bank.run(
+            crate::accounts::CreateMatchRelayed {
+                match_account,
+                escrow_vault,
+                host,
+                relayer,
+                system_program: system_program::ID,
+            },
+            crate::instruction::CreateMatchRelayed {
+                match_id,
+                stake_amount: stake,
+                relayer_fee,
+            },
+        )
+        .unwrap();
+        let rent = bank.lamports(&match_account);
+        assert_eq!(bank.lamports(&relayer), LAMPORTS_PER_SOL - rent);
+        let created: MatchAccount = bank.get(&match_account);
+        assert_eq!(created.relayer_advanced, relayer_fee);
+
+        bank.run(
+            crate::accounts::JoinMatch {
+                match_account,
+                escrow_vault,
+                challenger,
+                lobby: None,
+                host_backing_pool: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::JoinMatch {},
+        )
+        .unwrap();
+        bank.run(
+            crate::accounts::ClaimWinnerReward {
+                match_account,
+                escrow_vault,
+                caller: host,
+                winner_account: host,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: Some(relayer),
+                system_program: system_program::ID,
+            },
+            crate::instruction::ClaimWinnerReward { winner: host },
+        )
+        .unwrap();
+        assert_eq!(
+            bank.lamports(&relayer),
+            LAMPORTS_PER_SOL - rent + relayer_fee
+        );
+
+        let close = || crate::accounts::CloseMatch {
+            match_account,
+            rent_payer: relayer,
+        };
+        assert_eq!(
+            bank.run(close(), crate::instruction::CloseMatch {}),
+            Err(staking_error(StakingError::RematchWindowOpen))
+        );
+        warp(REMATCH_WINDOW_SECONDS);
+        bank.run(close(), crate::instruction::CloseMatch {})
+            .unwrap();
+
+        // The relayer ends up exactly its fee ahead; the players paid only
+        // the fee and the platform fee out of the pot
+        let platform_fee = platform_fee(2 * stake, PLATFORM_FEE_BPS);
+        assert_eq!(bank.lamports(&relayer), LAMPORTS_PER_SOL + relayer_fee);
+        assert_eq!(bank.lamports(&treasury), LAMPORTS_PER_SOL + platform_fee);
+        assert_eq!(
+            bank.lamports(&host),
+            10 * LAMPORTS_PER_SOL + stake - platform_fee - relayer_fee
+        );
+        assert_eq!(bank.lamports(&challenger), 10 * LAMPORTS_PER_SOL - stake);
+        assert_eq!(bank.lamports(&escrow_vault), 0);
+        assert_eq!(bank.lamports(&match_account), 0);
+    }
+
+    #[test]
+    fn finished_series_rent_goes_back_to_the_host() {
+        let mut bank = Bank::new();
+        let host = bank.wallet(LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(LAMPORTS_PER_SOL);
+        let series_id = [41u8; 32];
+        let (series, bump) = Pubkey::find_program_address(&[b"series", &series_id], &crate::ID);
+        let mut account = Series {
+            series_id,
+            host,
+            challenger,
+            stake_amount: LAMPORTS_PER_SOL / 10,
+            best_of: 3,
+            games_played: 2,
+            host_wins: 1,
+            challenger_wins: 1,
+            status: MatchStatus::InProgress,
+            winner: Pubkey::default(),
+            fee_bps: PLATFORM_FEE_BPS as u16,
+            created_at: 0,
+            bump,
+        };
+        bank.put(&series, &account);
+        let rent = bank.lamports(&series);
+
+        let close = |receiver| crate::accounts::CloseSeries {
+            series,
+            caller: challenger,
+            host: receiver,
+        };
+        assert_eq!(
+            bank.run(close(host), crate::instruction::CloseSeries {}),
+            Err(staking_error(StakingError::MatchNotFinished))
+        );
+
+        account.status = MatchStatus::Settled;
+        account.winner = challenger;
+        bank.put(&series, &account);
+        assert_eq!(
+            bank.run(close(challenger), crate::instruction::CloseSeries {}),
+            Err(staking_error(StakingError::RentPayerMismatch))
+        );
+        bank.run(close(host), crate::instruction::CloseSeries {})
+            .unwrap();
+        assert_eq!(bank.lamports(&host), LAMPORTS_PER_SOL + rent);
+        assert_eq!(bank.lamports(&challenger), LAMPORTS_PER_SOL);
+        assert_eq!(bank.lamports(&series), 0);
+    }
+
+    #[test]
+    fn admin_handover_waits_for_the_new_admin_to_accept() {
+        let (mut bank, treasury) = bank_with_config();
+        let config = pda(&[b"config"]);
+        let admin = bank.get::<Config>(&config).admin;
+        let new_admin = Pubkey::new_unique();
+        let new_treasury = Pubkey::new_unique();
+
+        bank.run(
+            crate::accounts::UpdateConfig { config, admin },
+            crate::instruction::UpdateConfig {
+                new_admin,
+                treasury: new_treasury,
+            },
+        )
+        .unwrap();
+        let proposed: Config = bank.get(&config);
+        assert_eq!(proposed.admin, admin);
+        assert_eq!(proposed.pending_admin, new_admin);
+        assert_eq!(proposed.treasury, new_treasury);
+        assert_ne!(treasury, new_treasury);
+
+        let accept = |new_admin| crate::accounts::AcceptAdmin { config, new_admin };
+        assert_eq!(
+            bank.run(
+                accept(Pubkey::new_unique()),
+                crate::instruction::AcceptAdmin {}
+            ),
+            Err(staking_error(StakingError::NotPendingAdmin))
+        );
+        bank.run(accept(new_admin), crate::instruction::AcceptAdmin {})
+            .unwrap();
+        let accepted: Config = bank.get(&config);
+        assert_eq!(accepted.admin, new_admin);
+        assert_eq!(accepted.pending_admin, Pubkey::default());
+
+        // The old admin is out
+        assert_eq!(
+            bank.run(
+                crate::accounts::UpdateConfig { config, admin },
+                crate::instruction::SetLegacyCompat { enabled: false },
+            ),
+            Err(staking_error(StakingError::NotAdmin))
+        );
+    }
+
+    #[test]
+    fn legacy_matches_finalize_only_within_the_window() {
+        let (mut bank, treasury) = bank_with_config();
+        let config = pda(&[b"config"]);
+        let mut settings: Config = bank.get(&config);
+        settings.legacy_compat_until = NOW.with(Cell::get) + 60;
+        bank.put(&config, &settings);
+
+        let host = bank.wallet(LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(LAMPORTS_PER_SOL);
+        let match_id = [30u8; 32];
+        let (match_account, bump) =
+            Pubkey::find_program_address(&[b"match", &match_id], &crate::ID);
+        let escrow_vault = pda(&[b"escrow", &match_id]);
+        let stake = LAMPORTS_PER_SOL / 10;
+        let mut legacy = MatchAccount::new(match_id, host, stake, bump).unwrap();
+        legacy.challenger = challenger;
+        legacy.winner = challenger;
+        legacy.status = MatchStatus::Completed;
+        bank.put(&match_account, &legacy);
+        bank.slot(&escrow_vault).lamports = 2 * stake;
+
+        let finalize = || crate::accounts::FinalizeLegacyMatch {
+            match_account,
+            escrow_vault,
+            winner: challenger,
+            config,
+            platform_treasury: treasury,
+            system_program: system_program::ID,
+        };
+        warp(61);
+        assert_eq!(
+            bank.run(finalize(), crate::instruction::FinalizeLegacyMatch {}),
+            Err(staking_error(StakingError::LegacyCompatDisabled))
+        );
+
+        settings.legacy_compat_until = NOW.with(Cell::get);
+        bank.put(&config, &settings);
+        let rent = bank.lamports(&match_account);
+        bank.run(finalize(), crate::instruction::FinalizeLegacyMatch {})
+            .unwrap();
+        let platform_fee = platform_fee(2 * stake, PLATFORM_FEE_BPS);
+        assert_eq!(
+            bank.lamports(&challenger),
+            LAMPORTS_PER_SOL + 2 * stake - platform_fee + rent
+        );
+        assert_eq!(bank.lamports(&treasury), LAMPORTS_PER_SOL + platform_fee);
+        assert_eq!(bank.lamports(&escrow_vault), 0);
+    }
+
+    #[test]
+    fn wager_nft_without_collection_needs_a_fixed_supply() {
+        use anchor_lang::solana_program::program_pack::Pack;
+
+        let check = |mint_authority: fn(&Pubkey) -> COption<Pubkey>| {
+            let key = Pubkey::new_unique();
+            let owner = spl_token_2022::ID;
+            let mut lamports = 0;
+            let mut data = vec![0u8; spl_token_2022::state::Mint::LEN];
+            spl_token_2022::state::Mint {
+                mint_authority: mint_authority(&key),
+                supply: 1,
+                decimals: 0,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            }
+            .pack_into_slice(&mut data);
+            let info = AccountInfo::new(
+                &key,
+                false,
+                false,
+                &mut lamports,
+                &mut data,
+                &owner,
+                false,
+                0,
+            );
+            let mint = InterfaceAccount::<Mint>::try_from(&info).unwrap();
+            verify_wager_nft(&mint, None, Pubkey::default())
+        };
+
+        assert!(check(|_| COption::None).is_ok());
+        assert!(check(|mint| {
+            COption::Some(metadata::mpl_token_metadata::accounts::MasterEdition::find_pda(mint).0)
+        })
+        .is_ok());
+        assert_eq!(
+            check(|_| COption::Some(Pubkey::new_unique())),
+            Err(StakingError::InvalidNft.into())
+        );
+    }
+
+    /// A SOL match between two fresh wallets, created and joined. Returns
+    /// (host, challenger, match account, escrow vault).
+    fn start_match(
+        bank: &mut Bank,
+        match_id: [u8; 32],
+        stake: u64,
+    ) -> (Pubkey, Pubkey, Pubkey, Pubkey) {
+        let host = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let match_account = pda(&[b"match", &match_id]);
+        let escrow_vault = pda(&[b"escrow", &match_id]);
+
+        bank.run(
+            crate::accounts::CreateMatch {
+                match_account,
+                escrow_vault,
+                host,
+                lobby: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::CreateMatch {
+                match_id,
+                stake_amount: stake,
+                metadata: MatchMetadata::default(),
+            },
+        )
+        .unwrap();
+        bank.run(
+            crate::accounts::JoinMatch {
+                match_account,
+                escrow_vault,
+                challenger,
+                lobby: None,
+                host_backing_pool: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::JoinMatch {},
+        )
+        .unwrap();
+        (host, challenger, match_account, escrow_vault)
+    }
+
+    #[test]
+    fn draw_offers_expire_and_need_the_opponent_to_accept() {
+        let (mut bank, treasury) = bank_with_config();
+        let stake = LAMPORTS_PER_SOL / 10;
+        let (host, challenger, match_account, escrow_vault) =
+            start_match(&mut bank, [26u8; 32], stake);
+        let rent = bank.lamports(&match_account);
+
+        let offer = |caller, reason| {
+            (
+                crate::accounts::OfferDraw {
+                    match_account,
+                    caller,
+                    session: None,
+                },
+                crate::instruction::OfferDraw { reason },
+            )
+        };
+        let accept = |caller| crate::accounts::AcceptDraw {
+            match_account,
+            escrow_vault,
+            caller,
+            host_account: host,
+            challenger_account: challenger,
+            config: pda(&[b"config"]),
+            platform_treasury: treasury,
+            session: None,
+            relayer: None,
+            system_program: system_program::ID,
+        };
+
+        let (accounts, args) = offer(host, DrawReason::Agreement);
+        bank.run(accounts, args).unwrap();
+        assert_eq!(
+            bank.run(accept(host), crate::instruction::AcceptDraw {}),
+            Err(staking_error(StakingError::CannotAcceptOwnDrawOffer))
+        );
+        let (accounts, args) = offer(challenger, DrawReason::Repetition);
+        assert_eq!(
+            bank.run(accounts, args),
+            Err(staking_error(StakingError::DrawOfferPending))
+        );
+
+        warp(DRAW_OFFER_TTL_SECONDS + 1);
+        assert_eq!(
+            bank.run(accept(challenger), crate::instruction::AcceptDraw {}),
+            Err(staking_error(StakingError::DrawOfferExpired))
+        );
+
+        // Once the host's offer lapsed the challenger can make their own
+        let (accounts, args) = offer(challenger, DrawReason::Stalemate);
+        bank.run(accounts, args).unwrap();
+        bank.run(accept(host), crate::instruction::AcceptDraw {})
+            .unwrap();
+
+        let drawn: MatchAccount = bank.get(&match_account);
+        assert!(drawn.status == MatchStatus::Draw);
+        assert!(drawn.draw_reason == DrawReason::Stalemate);
+        assert_eq!(bank.lamports(&host), 10 * LAMPORTS_PER_SOL - rent);
+        assert_eq!(bank.lamports(&challenger), 10 * LAMPORTS_PER_SOL);
+        assert_eq!(bank.lamports(&treasury), LAMPORTS_PER_SOL);
+        assert_eq!(bank.lamports(&escrow_vault), 0);
+    }
+
+    #[test]
+    fn rematch_swaps_colors_and_needs_the_other_player_to_stake() {
+        let (mut bank, treasury) = bank_with_config();
+        let stake = LAMPORTS_PER_SOL / 10;
+        let (host, challenger, previous_match, escrow_vault) =
+            start_match(&mut bank, [27u8; 32], stake);
+        let rematch_id = bank.get::<MatchAccount>(&previous_match).rematch_id();
+        let match_account = pda(&[b"match", &rematch_id]);
+        let rematch_escrow = pda(&[b"escrow", &rematch_id]);
+
+        let create = |rematch_id: [u8; 32]| {
+            (
+                crate::accounts::CreateRematch {
+                    previous_match,
+                    match_account: pda(&[b"match", &rematch_id]),
+                    escrow_vault: pda(&[b"escrow", &rematch_id]),
+                    proposer: challenger,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::CreateRematch {
+                    rematch_id,
+                    swap_colors: true,
+                },
+            )
+        };
+        let (accounts, args) = create(rematch_id);
+        assert_eq!(
+            bank.run(accounts, args),
+            Err(staking_error(StakingError::MatchNotFinished))
+        );
+
+        bank.run(
+            crate::accounts::ClaimWinnerReward {
+                match_account: previous_match,
+                escrow_vault,
+                caller: host,
+                winner_account: host,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::ClaimWinnerReward { winner: host },
+        )
+        .unwrap();
+
+        let (accounts, args) = create([0u8; 32]);
+        assert_eq!(
+            bank.run(accounts, args),
+            Err(staking_error(StakingError::InvalidRematchId))
+        );
+        let (accounts, args) = create(rematch_id);
+        bank.run(accounts, args).unwrap();
+        let proposed: MatchAccount = bank.get(&match_account);
+        assert!(proposed.status == MatchStatus::WaitingForRematch);
+        assert_eq!((proposed.host, proposed.challenger), (challenger, host));
+        assert_eq!(bank.lamports(&rematch_escrow), stake);
+
+        let accept = |caller| crate::accounts::AcceptRematch {
+            match_account,
+            escrow_vault: rematch_escrow,
+            caller,
+            system_program: system_program::ID,
+        };
+        assert_eq!(
+            bank.run(accept(challenger), crate::instruction::AcceptRematch {}),
+            Err(staking_error(StakingError::AlreadyStaked))
+        );
+        bank.run(accept(host), crate::instruction::AcceptRematch {})
+            .unwrap();
+        assert!(bank.get::<MatchAccount>(&match_account).status == MatchStatus::InProgress);
+        assert_eq!(bank.lamports(&rematch_escrow), 2 * stake);
+    }
+
+    #[test]
+    fn settlement_drains_the_escrow_and_the_admin_sweeps_orphans() {
+        let (mut bank, treasury) = bank_with_config();
+        let config = pda(&[b"config"]);
+        let admin = bank.get::<Config>(&config).admin;
+        let stake = LAMPORTS_PER_SOL / 10;
+        let match_id = [28u8; 32];
+        let (host, _, match_account, escrow_vault) = start_match(&mut bank, match_id, stake);
+        let rent = bank.lamports(&match_account);
+
+        let sweep = |admin| crate::accounts::SweepOrphanedEscrow {
+            config,
+            admin,
+            match_account,
+            escrow_vault,
+            platform_treasury: treasury,
+            system_program: system_program::ID,
+        };
+        assert_eq!(
+            bank.run(
+                sweep(admin),
+                crate::instruction::SweepOrphanedEscrow { match_id }
+            ),
+            Err(staking_error(StakingError::MatchStillOpen))
+        );
+
+        // Dust sent straight to the escrow goes to the winner with the pot
+        bank.slot(&escrow_vault).lamports += 7;
+        bank.run(
+            crate::accounts::ClaimWinnerReward {
+                match_account,
+                escrow_vault,
+                caller: host,
+                winner_account: host,
+                config,
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::ClaimWinnerReward { winner: host },
+        )
+        .unwrap();
+        let platform_fee = platform_fee(2 * stake, PLATFORM_FEE_BPS);
+        assert_eq!(bank.lamports(&escrow_vault), 0);
+        assert_eq!(
+            bank.lamports(&host),
+            10 * LAMPORTS_PER_SOL - rent + stake - platform_fee + 7
+        );
+
+        warp(REMATCH_WINDOW_SECONDS);
+        bank.run(
+            crate::accounts::CloseMatch {
+                match_account,
+                rent_payer: host,
+            },
+            crate::instruction::CloseMatch {},
+        )
+        .unwrap();
+
+        bank.slot(&escrow_vault).lamports += 5_000;
+        assert_eq!(
+            bank.run(
+                sweep(Pubkey::new_unique()),
+                crate::instruction::SweepOrphanedEscrow { match_id }
+            ),
+            Err(staking_error(StakingError::NotAdmin))
+        );
+        bank.run(
+            sweep(admin),
+            crate::instruction::SweepOrphanedEscrow { match_id },
+        )
+        .unwrap();
+        assert_eq!(bank.lamports(&escrow_vault), 0);
+        assert_eq!(
+            bank.lamports(&treasury),
+            LAMPORTS_PER_SOL + platform_fee + 5_000
+        );
+    }
+
+    /// A match account in the original (version 0) layout, at `key`
+    fn put_legacy_match(bank: &mut Bank, key: &Pubkey, match_id: [u8; 32], host: Pubkey, bump: u8) {
+        let mut bytes = MatchAccount::DISCRIMINATOR.to_vec();
+        bytes.extend_from_slice(&match_id);
+        bytes.extend_from_slice(host.as_ref());
+        bytes.extend_from_slice(Pubkey::new_unique().as_ref());
+        bytes.extend_from_slice(&(LAMPORTS_PER_SOL / 10).to_le_bytes());
+        bytes.push(MatchStatus::InProgress as u8);
+        bytes.extend_from_slice(Pubkey::default().as_ref());
+        bytes.extend_from_slice(&1_600_000_000i64.to_le_bytes());
+        bytes.push(bump);
+        assert_eq!(bytes.len(), MatchAccount::LEGACY_LEN);
+        bank.put_raw(key, &bytes);
+    }
+
+    #[test]
+    fn legacy_match_reads_zero_padded_and_migrates_in_place() {
+        let mut bank = Bank::new();
+        let payer = bank.wallet(LAMPORTS_PER_SOL);
+        let host = Pubkey::new_unique();
+        let match_id = [29u8; 32];
+        let (match_account, bump) =
+            Pubkey::find_program_address(&[b"match", &match_id], &crate::ID);
+        put_legacy_match(&mut bank, &match_account, match_id, host, bump);
+
+        let legacy: MatchAccount = bank.get(&match_account);
+        assert_eq!(legacy.version, 0);
+        assert_eq!(legacy.host, host);
+        assert!(legacy.status == MatchStatus::InProgress);
+        assert_eq!(legacy.fee_bps, PLATFORM_FEE_BPS as u16);
+        assert_eq!(legacy.challenger_stake, LAMPORTS_PER_SOL / 10);
+        assert_eq!(legacy.rent_payer, host);
+        assert!(legacy.kind == MatchKind::Sol);
+        assert_eq!(legacy.listed_bucket, None);
+
+        let migrate = |match_account| crate::accounts::MigrateMatch {
+            match_account,
+            payer,
+            system_program: system_program::ID,
+        };
+
+        // A legacy-looking account away from its PDA can't be migrated
+        let stray = Pubkey::new_unique();
+        put_legacy_match(&mut bank, &stray, match_id, host, bump);
+        assert_eq!(
+            bank.run(migrate(stray), crate::instruction::MigrateMatch {}),
+            Err(staking_error(StakingError::InvalidMatchAccount))
+        );
+
+        bank.run(migrate(match_account), crate::instruction::MigrateMatch {})
+            .unwrap();
+        assert_eq!(
+            bank.data(&match_account).len(),
+            8 + MatchAccount::INIT_SPACE
+        );
+        assert_eq!(
+            bank.lamports(&match_account),
+            Rent::default().minimum_balance(8 + MatchAccount::INIT_SPACE)
+        );
+        let migrated: MatchAccount = bank.get(&match_account);
+        assert_eq!(migrated.version, MATCH_ACCOUNT_VERSION);
+        assert_eq!(migrated.host, host);
+        assert_eq!(migrated.challenger_stake, LAMPORTS_PER_SOL / 10);
+        assert_eq!(migrated.created_at, 1_600_000_000);
+
+        assert_eq!(
+            bank.run(migrate(match_account), crate::instruction::MigrateMatch {}),
+            Err(staking_error(StakingError::AlreadyMigrated))
+        );
+    }
+
+    #[test]
+    fn sol_instructions_reject_token_matches() {
+        let (mut bank, treasury) = bank_with_config();
+        let host = bank.wallet(LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(LAMPORTS_PER_SOL);
+        let match_id = [31u8; 32];
+        let (match_account, bump) =
+            Pubkey::find_program_address(&[b"match", &match_id], &crate::ID);
+        let escrow_vault = pda(&[b"escrow", &match_id]);
+        bank.put(
+            &match_account,
+            &MatchAccount {
+                kind: MatchKind::Token,
+                stake_mint: Pubkey::new_unique(),
+                ..MatchAccount::new(match_id, host, 1_000_000, bump).unwrap()
+            },
+        );
+
+        assert_eq!(
+            bank.run(
+                crate::accounts::JoinMatch {
+                    match_account,
+                    escrow_vault,
+                    challenger,
+                    lobby: None,
+                    host_backing_pool: None,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::JoinMatch {},
+            ),
+            Err(staking_error(StakingError::WrongMatchKind))
+        );
+        assert_eq!(
+            bank.run(
+                crate::accounts::CancelMatch {
+                    match_account,
+                    escrow_vault,
+                    host,
+                    host_refund: host,
+                    config: pda(&[b"config"]),
+                    platform_treasury: treasury,
+                    relayer: None,
+                    lobby: None,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::CancelMatch {},
+            ),
+            Err(staking_error(StakingError::WrongMatchKind))
+        );
+        assert_eq!(bank.lamports(&host), LAMPORTS_PER_SOL);
+        assert_eq!(bank.lamports(&challenger), LAMPORTS_PER_SOL);
+    }
+
+    /// Token-2022 mint data with `extensions`, set up by `init`
+    fn token_2022_mint(
+        extensions: &[ExtensionType],
+        init: impl FnOnce(&mut StateWithExtensionsMut<spl_token_2022::state::Mint>),
+    ) -> Vec<u8> {
+        let len =
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(extensions)
+                .unwrap();
+        let mut data = vec![0u8; len];
+        let mut state =
+            StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data)
+                .unwrap();
+        init(&mut state);
+        state.base = spl_token_2022::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    fn with_mint<T>(mut data: Vec<u8>, f: impl FnOnce(&AccountInfo) -> T) -> T {
+        let key = Pubkey::new_unique();
+        let owner = spl_token_2022::ID;
+        let mut lamports = 0;
+        let info = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        f(&info)
+    }
+
+    fn transfer_fee_mint(basis_points: u16, maximum_fee: u64) -> Vec<u8> {
+        token_2022_mint(&[ExtensionType::TransferFeeConfig], |state| {
+            let fee = TransferFee {
+                epoch: 0.into(),
+                maximum_fee: maximum_fee.into(),
+                transfer_fee_basis_points: basis_points.into(),
+            };
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            config.older_transfer_fee = fee;
+            config.newer_transfer_fee = fee;
+        })
+    }
+
+    #[test]
+    fn gross_amount_covers_the_transfer_fee_exactly() {
+        let fee = |gross: u64, basis_points: u64, maximum_fee: u64| {
+            (gross * basis_points).div_ceil(10_000).min(maximum_fee)
+        };
+
+        for net in [1, 999, 1_000_000, 123_456_789] {
+            let gross = with_mint(transfer_fee_mint(100, u64::MAX), |mint| {
+                gross_for_net_amount(mint, net).unwrap()
+            });
+            assert_eq!(gross - fee(gross, 100, u64::MAX), net);
+        }
+
+        // A capped fee is just added on top
+        let gross = with_mint(transfer_fee_mint(100, 5_000), |mint| {
+            gross_for_net_amount(mint, 10_000_000).unwrap()
+        });
+        assert_eq!(gross, 10_005_000);
+
+        let plain = token_2022_mint(&[], |_| {});
+        assert_eq!(
+            with_mint(plain, |mint| gross_for_net_amount(mint, 10_000_000)).unwrap(),
+            10_000_000
+        );
+    }
+
+    #[test]
+    fn stake_mints_that_can_trap_the_escrow_are_rejected() {
+        let delegated = token_2022_mint(&[ExtensionType::PermanentDelegate], |state| {
+            state
+                .init_extension::<PermanentDelegate>(true)
+                .unwrap()
+                .delegate = Some(Pubkey::new_unique()).try_into().unwrap();
+        });
+        assert_eq!(
+            with_mint(delegated, validate_stake_mint),
+            Err(StakingError::UnsupportedMintExtension.into())
+        );
+
+        let frozen = token_2022_mint(&[ExtensionType::DefaultAccountState], |state| {
+            state
+                .init_extension::<DefaultAccountState>(true)
+                .unwrap()
+                .state = AccountState::Frozen as u8;
+        });
+        assert_eq!(
+            with_mint(frozen, validate_stake_mint),
+            Err(StakingError::UnsupportedMintExtension.into())
+        );
+
+        assert!(with_mint(transfer_fee_mint(100, u64::MAX), validate_stake_mint).is_ok());
+    }
+
+    #[test]
+    fn allowed_mints_carry_their_own_stake_range_and_fee() {
+        let (mut bank, _) = bank_with_config();
+        let config = pda(&[b"config"]);
+        let admin = bank.get::<Config>(&config).admin;
+        bank.slot(&admin).lamports = LAMPORTS_PER_SOL;
+        let mint = Pubkey::new_unique();
+        bank.put_raw(&mint, &token_2022_mint(&[], |_| {}));
+        bank.slot(&mint).owner = spl_token_2022::ID;
+        let mint_config = pda(&[b"mint_config", mint.as_ref()]);
+
+        let add = |admin| crate::accounts::AddAllowedMint {
+            config,
+            admin,
+            mint,
+            mint_config,
+            system_program: system_program::ID,
+        };
+        let limits = |min_stake, max_stake, fee_bps| crate::instruction::AddAllowedMint {
+            min_stake,
+            max_stake,
+            fee_bps,
+        };
+        assert_eq!(
+            bank.run(add(admin), limits(0, 1_000, 100)),
+            Err(staking_error(StakingError::InvalidStakeLimits))
+        );
+        assert_eq!(
+            bank.run(add(admin), limits(2_000, 1_000, 100)),
+            Err(staking_error(StakingError::InvalidStakeLimits))
+        );
+        assert_eq!(
+            bank.run(
+                add(admin),
+                limits(1_000, 2_000, pokechess_staking::MAX_MINT_FEE_BPS + 1)
+            ),
+            Err(staking_error(StakingError::FeeTooHigh))
+        );
+        let stranger = bank.wallet(LAMPORTS_PER_SOL);
+        assert_eq!(
+            bank.run(add(stranger), limits(1_000, 2_000, 100)),
+            Err(staking_error(StakingError::NotAdmin))
+        );
+
+        bank.run(add(admin), limits(1_000, 2_000, 100)).unwrap();
+        let added: MintConfig = bank.get(&mint_config);
+        assert_eq!(added.mint, mint);
+        assert_eq!(
+            (added.min_stake, added.max_stake, added.fee_bps),
+            (1_000, 2_000, 100)
+        );
+        assert!(added.enabled);
+
+        bank.run(
+            crate::accounts::UpdateAllowedMint {
+                config,
+                admin,
+                mint_config,
+            },
+            crate::instruction::UpdateAllowedMint {
+                min_stake: 500,
+                max_stake: 5_000,
+                fee_bps: 250,
+                enabled: false,
+            },
+        )
+        .unwrap();
+        let paused: MintConfig = bank.get(&mint_config);
+        assert_eq!(
+            (paused.min_stake, paused.max_stake, paused.fee_bps),
+            (500, 5_000, 250)
+        );
+        assert!(!paused.enabled);
+
+        let admin_before = bank.lamports(&admin);
+        let rent = bank.lamports(&mint_config);
+        bank.run(
+            crate::accounts::RemoveAllowedMint {
+                config,
+                admin,
+                mint_config,
+            },
+            crate::instruction::RemoveAllowedMint {},
+        )
+        .unwrap();
+        assert_eq!(bank.lamports(&mint_config), 0);
+        assert_eq!(bank.lamports(&admin), admin_before + rent);
+    }
+
+    /// Points the config at a price feed account publishing `feed`
+    fn put_price_feed(bank: &mut Bank, feed: PriceFeed) -> Pubkey {
+        let config = pda(&[b"config"]);
+        let mut config_account: Config = bank.get(&config);
+        if config_account.price_feed == Pubkey::default() {
+            config_account.price_feed = Pubkey::new_unique();
+            bank.put(&config, &config_account);
+        }
+        bank.put_raw(&config_account.price_feed, &feed.try_to_vec().unwrap());
+        config_account.price_feed
+    }
+
+    /// SOL at `dollars`, published now with a tight confidence interval
+    fn sol_price(dollars: i64) -> PriceFeed {
+        PriceFeed {
+            price: dollars * 100_000_000,
+            conf: dollars as u64 * 100_000,
+            expo: -8,
+            publish_time: NOW.with(Cell::get),
+        }
+    }
+
+    #[test]
+    fn usd_cents_convert_to_lamports_at_the_feed_price() {
+        assert_eq!(
+            sol_price(150).usd_cents_to_lamports(1_500).unwrap(),
+            LAMPORTS_PER_SOL / 10
+        );
+        // Same price with a positive exponent
+        let coarse = PriceFeed {
+            price: 15,
+            expo: 1,
+            ..sol_price(150)
+        };
+        assert_eq!(
+            coarse.usd_cents_to_lamports(1_500).unwrap(),
+            LAMPORTS_PER_SOL / 10
+        );
+        assert_eq!(
+            sol_price(1).usd_cents_to_lamports(u64::MAX),
+            Err(StakingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn usd_matches_lock_lamports_from_a_fresh_confident_price() {
+        let (mut bank, _) = bank_with_config();
+        let host = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let match_id = [35u8; 32];
+        let match_account = pda(&[b"match", &match_id]);
+        let escrow_vault = pda(&[b"escrow", &match_id]);
+        let config = pda(&[b"config"]);
+
+        let create = |bank: &mut Bank, price_feed| {
+            bank.run(
+                crate::accounts::CreateUsdMatch {
+                    match_account,
+                    escrow_vault,
+                    host,
+                    config,
+                    price_feed,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::CreateUsdMatch {
+                    match_id,
+                    stake_usd_cents: 1_500,
+                },
+            )
+        };
+
+        let stale = PriceFeed {
+            publish_time: NOW.with(Cell::get) - pokechess_staking::PRICE_MAX_AGE_SECONDS - 1,
+            ..sol_price(150)
+        };
+        let price_feed = put_price_feed(&mut bank, stale);
+        assert_eq!(
+            create(&mut bank, price_feed),
+            Err(staking_error(StakingError::StalePrice))
+        );
+        let uncertain = PriceFeed {
+            conf: 150 * 100_000_000 / 50,
+            ..sol_price(150)
+        };
+        put_price_feed(&mut bank, uncertain);
+        assert_eq!(
+            create(&mut bank, price_feed),
+            Err(staking_error(StakingError::PriceTooUncertain))
+        );
+
+        put_price_feed(&mut bank, sol_price(150));
+        create(&mut bank, price_feed).unwrap();
+        let created: MatchAccount = bank.get(&match_account);
+        assert_eq!(created.stake_usd_cents, 1_500);
+        assert_eq!(created.stake_amount, LAMPORTS_PER_SOL / 10);
+
+        let join = |bank: &mut Bank| {
+            bank.run(
+                crate::accounts::JoinUsdMatch {
+                    match_account,
+                    escrow_vault,
+                    challenger,
+                    config,
+                    price_feed,
+                    lobby: None,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::JoinUsdMatch {},
+            )
+        };
+
+        // A 10% move is past the drift the challenger can be held to
+        put_price_feed(&mut bank, sol_price(165));
+        assert_eq!(
+            join(&mut bank),
+            Err(staking_error(StakingError::PriceMoved))
+        );
+
+        // Within the drift, the challenger stakes the locked amount
+        put_price_feed(&mut bank, sol_price(151));
+        join(&mut bank).unwrap();
+        assert!(bank.get::<MatchAccount>(&match_account).status == MatchStatus::InProgress);
+        assert_eq!(
+            bank.lamports(&challenger),
+            10 * LAMPORTS_PER_SOL - LAMPORTS_PER_SOL / 10
+        );
+    }
+
+    #[test]
+    fn vault_balances_stake_matches_and_collect_winnings() {
+        let (mut bank, treasury) = bank_with_config();
+        let host = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let host_vault = PlayerVault::address(&host);
+        let challenger_vault = PlayerVault::address(&challenger);
+        let deposit = |bank: &mut Bank, owner, amount| {
+            bank.run(
+                crate::accounts::Deposit {
+                    player_vault: PlayerVault::address(&owner),
+                    owner,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::Deposit { amount },
+            )
+        };
+        let balance = |bank: &Bank, vault: &Pubkey| {
+            bank.lamports(vault) - Rent::default().minimum_balance(8 + PlayerVault::INIT_SPACE)
+        };
+
+        deposit(&mut bank, host, 2 * LAMPORTS_PER_SOL).unwrap();
+        deposit(&mut bank, host, LAMPORTS_PER_SOL).unwrap();
+        deposit(&mut bank, challenger, LAMPORTS_PER_SOL).unwrap();
+        assert_eq!(balance(&bank, &host_vault), 3 * LAMPORTS_PER_SOL);
+
+        let withdraw = |bank: &mut Bank, owner, amount| {
+            bank.run(
+                crate::accounts::Withdraw {
+                    player_vault: PlayerVault::address(&owner),
+                    owner,
+                },
+                crate::instruction::Withdraw { amount },
+            )
+        };
+        assert_eq!(
+            withdraw(&mut bank, challenger, LAMPORTS_PER_SOL + 1),
+            Err(staking_error(StakingError::InsufficientVaultBalance))
+        );
 
-    #[account(
-        mut,
-        seeds = [b"escrow", match_account.match_id.as_ref()],
-        bump
-    )]
-    /// CHECK: PDA escrow vault holding the staked SOL
-    pub escrow_vault: SystemAccount<'info>,
+        let match_id = [36u8; 32];
+        let match_account = pda(&[b"match", &match_id]);
+        let escrow_vault = pda(&[b"escrow", &match_id]);
+        bank.run(
+            crate::accounts::CreateMatchFromVault {
+                match_account,
+                escrow_vault,
+                player_vault: host_vault,
+                host,
+                system_program: system_program::ID,
+            },
+            crate::instruction::CreateMatchFromVault {
+                match_id,
+                stake_amount: LAMPORTS_PER_SOL,
+            },
+        )
+        .unwrap();
+        bank.run(
+            crate::accounts::JoinMatchFromVault {
+                match_account,
+                escrow_vault,
+                player_vault: challenger_vault,
+                challenger,
+                session: None,
+                host_backing_pool: None,
+                lobby: None,
+            },
+            crate::instruction::JoinMatchFromVault {},
+        )
+        .unwrap();
+        assert_eq!(balance(&bank, &host_vault), 2 * LAMPORTS_PER_SOL);
+        assert_eq!(balance(&bank, &challenger_vault), 0);
 
-    /// The caller (must be host or challenger) - this is the ONLY signer needed
-    #[account(mut)]
-    pub caller: Signer<'info>,
+        bank.run(
+            crate::accounts::ClaimWinnerReward {
+                match_account,
+                escrow_vault,
+                caller: host,
+                winner_account: host_vault,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::ClaimWinnerReward { winner: host },
+        )
+        .unwrap();
+        let winnings = 2 * LAMPORTS_PER_SOL - platform_fee(2 * LAMPORTS_PER_SOL, PLATFORM_FEE_BPS);
+        assert_eq!(balance(&bank, &host_vault), 2 * LAMPORTS_PER_SOL + winnings);
 
-    /// CHECK: The winner's account - does NOT need to sign, just receives SOL
-    #[account(mut)]
-    pub winner_account: SystemAccount<'info>,
+        let host_wallet = bank.lamports(&host);
+        withdraw(&mut bank, host, 2 * LAMPORTS_PER_SOL + winnings).unwrap();
+        assert_eq!(
+            bank.lamports(&host),
+            host_wallet + 2 * LAMPORTS_PER_SOL + winnings
+        );
+        assert_eq!(balance(&bank, &host_vault), 0);
+    }
 
-    /// CHECK: Platform treasury for fees
-    #[account(mut)]
-    pub platform_treasury: SystemAccount<'info>,
+    #[test]
+    fn session_keys_join_within_their_limits_until_they_expire() {
+        let (mut bank, _) = bank_with_config();
+        let owner = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let session_key = bank.wallet(0);
+        let session = pda(&[b"session", owner.as_ref()]);
+        let player_vault = PlayerVault::address(&owner);
+        bank.run(
+            crate::accounts::Deposit {
+                player_vault,
+                owner,
+                system_program: system_program::ID,
+            },
+            crate::instruction::Deposit {
+                amount: 5 * LAMPORTS_PER_SOL,
+            },
+        )
+        .unwrap();
 
-    pub system_program: Program<'info, System>,
-}
+        let create_session = |bank: &mut Bank, expires_at| {
+            bank.run(
+                crate::accounts::CreateSession {
+                    session,
+                    owner,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::CreateSession {
+                    session_key,
+                    expires_at,
+                    max_stake_per_match: LAMPORTS_PER_SOL,
+                    spend_cap: 3 * LAMPORTS_PER_SOL / 2,
+                },
+            )
+        };
+        let now = NOW.with(Cell::get);
+        assert_eq!(
+            create_session(&mut bank, now + pokechess_staking::MAX_SESSION_SECONDS + 1),
+            Err(staking_error(StakingError::SessionTooLong))
+        );
+        create_session(&mut bank, now + 60 * 60).unwrap();
 
-/// DEPRECATED: Use ClaimWinnerReward instead
-#[derive(Accounts)]
-pub struct DeclareWinner<'info> {
-    #[account(
-        mut,
-        seeds = [b"match", match_account.match_id.as_ref()],
-        bump = match_account.bump
-    )]
-    pub match_account: Account<'info, MatchAccount>,
+        // Open matches from other hosts, staked at `stake_amount`
+        let mut next_id = 0u8;
+        let mut open_match = |bank: &mut Bank, stake_amount| {
+            next_id += 1;
+            let match_id = [next_id; 32];
+            let (match_account, bump) =
+                Pubkey::find_program_address(&[b"match", &match_id], &crate::ID);
+            bank.put(
+                &match_account,
+                &MatchAccount::new(match_id, Pubkey::new_unique(), stake_amount, bump).unwrap(),
+            );
+            (match_account, pda(&[b"escrow", &match_id]))
+        };
+        let join = |bank: &mut Bank, (match_account, escrow_vault), challenger| {
+            bank.run(
+                crate::accounts::JoinMatchFromVault {
+                    match_account,
+                    escrow_vault,
+                    player_vault,
+                    challenger,
+                    session: Some(session),
+                    host_backing_pool: None,
+                    lobby: None,
+                },
+                crate::instruction::JoinMatchFromVault {},
+            )
+        };
 
-    #[account(mut)]
-    pub caller: Signer<'info>,
-}
+        let first = open_match(&mut bank, LAMPORTS_PER_SOL);
+        let stranger = bank.wallet(0);
+        assert_eq!(
+            join(&mut bank, first, stranger),
+            Err(staking_error(StakingError::SessionKeyMismatch))
+        );
+        let too_big = open_match(&mut bank, 2 * LAMPORTS_PER_SOL);
+        assert_eq!(
+            join(&mut bank, too_big, session_key),
+            Err(staking_error(StakingError::SessionStakeTooHigh))
+        );
+        join(&mut bank, first, session_key).unwrap();
+        let joined: MatchAccount = bank.get(&first.0);
+        assert_eq!(joined.challenger, owner);
+        assert_eq!(
+            bank.get::<SessionAuthority>(&session).spent,
+            LAMPORTS_PER_SOL
+        );
 
-/// DEPRECATED: Use ClaimWinnerReward instead
-#[derive(Accounts)]
-pub struct ClaimReward<'info> {
-    #[account(
-        mut,
-        seeds = [b"match", match_account.match_id.as_ref()],
-        bump = match_account.bump,
-        close = winner
-    )]
-    pub match_account: Account<'info, MatchAccount>,
+        let second = open_match(&mut bank, LAMPORTS_PER_SOL);
+        assert_eq!(
+            join(&mut bank, second, session_key),
+            Err(staking_error(StakingError::SessionSpendCapExceeded))
+        );
+        let small = open_match(&mut bank, LAMPORTS_PER_SOL / 2);
+        warp(60 * 60);
+        assert_eq!(
+            join(&mut bank, small, session_key),
+            Err(staking_error(StakingError::SessionExpired))
+        );
 
-    #[account(
-        mut,
-        seeds = [b"escrow", match_account.match_id.as_ref()],
-        bump
-    )]
-    /// CHECK: This is a PDA used as escrow vault - holds SOL for distribution
-    pub escrow_vault: SystemAccount<'info>,
+        // The main wallet can revoke at any time, getting the rent back
+        let rent = bank.lamports(&session);
+        let owner_before = bank.lamports(&owner);
+        bank.run(
+            crate::accounts::RevokeSession { session, owner },
+            crate::instruction::RevokeSession {},
+        )
+        .unwrap();
+        assert_eq!(bank.lamports(&owner), owner_before + rent);
+    }
 
-    #[account(mut)]
-    pub winner: Signer<'info>,
+    #[test]
+    fn odds_matches_stake_each_side_and_settle_the_whole_pot() {
+        let (mut bank, treasury) = bank_with_config();
+        let host = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let (host_stake, challenger_stake) = (3 * LAMPORTS_PER_SOL / 10, LAMPORTS_PER_SOL / 10);
 
-    /// CHECK: Platform treasury for fees - validated off-chain
-    #[account(mut)]
-    pub platform_treasury: SystemAccount<'info>,
+        let start = |bank: &mut Bank, match_id: [u8; 32]| {
+            let match_account = pda(&[b"match", &match_id]);
+            let escrow_vault = pda(&[b"escrow", &match_id]);
+            bank.run(
+                crate::accounts::CreateMatch {
+                    match_account,
+                    escrow_vault,
+                    host,
+                    lobby: None,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::CreateOddsMatch {
+                    match_id,
+                    host_stake,
+                    challenger_stake,
+                },
+            )
+            .unwrap();
+            bank.run(
+                crate::accounts::JoinMatch {
+                    match_account,
+                    escrow_vault,
+                    challenger,
+                    lobby: None,
+                    host_backing_pool: None,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::JoinMatch {},
+            )
+            .unwrap();
+            (match_account, escrow_vault)
+        };
 
-    pub system_program: Program<'info, System>,
-}
+        let (match_account, escrow_vault) = start(&mut bank, [39u8; 32]);
+        assert_eq!(bank.lamports(&escrow_vault), host_stake + challenger_stake);
+        let (host_before, challenger_before) = (bank.lamports(&host), bank.lamports(&challenger));
 
-#[derive(Accounts)]
-pub struct CancelMatch<'info> {
-    #[account(
-        mut,
-        seeds = [b"match", match_account.match_id.as_ref()],
-        bump = match_account.bump,
-        close = host
-    )]
-    pub match_account: Account<'info, MatchAccount>,
+        // A draw gives each side back its own stake
+        bank.run(
+            crate::accounts::OfferDraw {
+                match_account,
+                caller: host,
+                session: None,
+            },
+            crate::instruction::OfferDraw {
+                reason: DrawReason::Agreement,
+            },
+        )
+        .unwrap();
+        bank.run(
+            crate::accounts::AcceptDraw {
+                match_account,
+                escrow_vault,
+                caller: challenger,
+                host_account: host,
+                challenger_account: challenger,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::AcceptDraw {},
+        )
+        .unwrap();
+        assert_eq!(bank.lamports(&host), host_before + host_stake);
+        assert_eq!(
+            bank.lamports(&challenger),
+            challenger_before + challenger_stake
+        );
 
-    #[account(
-        mut,
-        seeds = [b"escrow", match_account.match_id.as_ref()],
-        bump
-    )]
-    /// CHECK: This is a PDA used as escrow vault - holds SOL for refund
-    pub escrow_vault: SystemAccount<'info>,
+        // The underdog's win takes the whole pot, less the fee on all of it
+        let (match_account, escrow_vault) = start(&mut bank, [40u8; 32]);
+        let challenger_before = bank.lamports(&challenger);
+        let treasury_before = bank.lamports(&treasury);
+        bank.run(
+            crate::accounts::ClaimWinnerReward {
+                match_account,
+                escrow_vault,
+                caller: challenger,
+                winner_account: challenger,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::ClaimWinnerReward { winner: challenger },
+        )
+        .unwrap();
+        let fee = platform_fee(host_stake + challenger_stake, PLATFORM_FEE_BPS);
+        assert_eq!(
+            bank.lamports(&challenger),
+            challenger_before + host_stake + challenger_stake - fee
+        );
+        assert_eq!(bank.lamports(&treasury), treasury_before + fee);
+    }
 
-    #[account(mut)]
-    pub host: Signer<'info>,
+    #[test]
+    fn tournament_bracket_runs_to_a_champion_and_pays_by_placement() {
+        let (mut bank, treasury) = bank_with_config();
+        let organizer = bank.wallet(LAMPORTS_PER_SOL);
+        let players: Vec<Pubkey> = (0..4).map(|_| bank.wallet(LAMPORTS_PER_SOL)).collect();
+        let payout_schedule = Pubkey::new_unique();
+        bank.put(&payout_schedule, &schedule(vec![7_000, 3_000], 0));
+        let tournament_id = [42u8; 32];
+        let tournament = pda(&[b"tournament", &tournament_id]);
+        let tournament_escrow = pda(&[b"tournament_escrow", &tournament_id]);
+        let entry_fee = LAMPORTS_PER_SOL / 10;
 
-    pub system_program: Program<'info, System>,
-}
+        bank.run(
+            crate::accounts::CreateTournament {
+                tournament,
+                schedule: payout_schedule,
+                organizer,
+                system_program: system_program::ID,
+            },
+            crate::instruction::CreateTournament {
+                tournament_id,
+                entry_fee,
+                size: 4,
+                settle_by: NOW.with(Cell::get) + 7 * 24 * 60 * 60,
+            },
+        )
+        .unwrap();
 
-#[derive(Accounts)]
-pub struct DeclareDraw<'info> {
-    #[account(
-        mut,
-        seeds = [b"match", match_account.match_id.as_ref()],
-        bump = match_account.bump,
-        close = caller
-    )]
-    pub match_account: Account<'info, MatchAccount>,
+        let register = |bank: &mut Bank, player| {
+            bank.run(
+                crate::accounts::RegisterForTournament {
+                    tournament,
+                    tournament_escrow,
+                    player,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::RegisterForTournament {},
+            )
+        };
+        register(&mut bank, players[0]).unwrap();
+        assert_eq!(
+            register(&mut bank, players[0]),
+            Err(staking_error(StakingError::AlreadyRegistered))
+        );
+        for player in &players[1..] {
+            register(&mut bank, *player).unwrap();
+        }
+        let late = bank.wallet(LAMPORTS_PER_SOL);
+        assert_eq!(
+            register(&mut bank, late),
+            Err(staking_error(StakingError::MatchNotJoinable))
+        );
+        let started: Tournament = bank.get(&tournament);
+        assert!(started.status == TournamentStatus::InProgress);
+        assert_eq!(started.alive, players);
 
-    #[account(
-        mut,
-        seeds = [b"escrow", match_account.match_id.as_ref()],
-        bump
-    )]
-    /// CHECK: This is a PDA used as escrow vault - holds SOL for refund
-    pub escrow_vault: SystemAccount<'info>,
+        let report = |bank: &mut Bank, reporter, match_index, winner, placements: Vec<Pubkey>| {
+            bank.run(
+                WithRemaining(
+                    crate::accounts::ReportTournamentResult {
+                        tournament,
+                        schedule: payout_schedule,
+                        tournament_escrow,
+                        reporter,
+                        config: pda(&[b"config"]),
+                        platform_treasury: treasury,
+                        system_program: system_program::ID,
+                    },
+                    placements,
+                ),
+                crate::instruction::ReportTournamentResult {
+                    match_index,
+                    winner,
+                },
+            )
+        };
 
-    #[account(mut)]
-    pub caller: Signer<'info>,
+        // Semifinals: players 0 v 1 and 2 v 3. Only the organizer or the
+        // loser can report.
+        assert_eq!(
+            report(&mut bank, players[0], 0, players[0], vec![]),
+            Err(staking_error(StakingError::NotOrganizer))
+        );
+        report(&mut bank, players[1], 0, players[0], vec![]).unwrap();
+        assert_eq!(
+            report(&mut bank, organizer, 0, players[1], vec![]),
+            Err(staking_error(StakingError::ResultAlreadyReported))
+        );
+        report(&mut bank, organizer, 1, players[3], vec![]).unwrap();
+        let final_round: Tournament = bank.get(&tournament);
+        assert_eq!(final_round.round, 1);
+        assert_eq!(final_round.alive, vec![players[0], players[3]]);
 
-    /// CHECK: Host account for refund - validated by address constraint
-    #[account(mut, address = match_account.host)]
-    pub host_account: SystemAccount<'info>,
+        // The final pays champion and runner-up, in placement order
+        let pool = 4 * entry_fee;
+        let fee = platform_fee(pool, PLATFORM_FEE_BPS);
+        let runner_up_prize = (pool - fee) * 3 / 10;
+        let before: Vec<u64> = players.iter().map(|player| bank.lamports(player)).collect();
+        let treasury_before = bank.lamports(&treasury);
+        report(
+            &mut bank,
+            organizer,
+            0,
+            players[3],
+            vec![players[3], players[0]],
+        )
+        .unwrap();
 
-    /// CHECK: Challenger account for refund - validated by address constraint
-    #[account(mut, address = match_account.challenger)]
-    pub challenger_account: SystemAccount<'info>,
+        let completed: Tournament = bank.get(&tournament);
+        assert!(completed.status == TournamentStatus::Completed);
+        assert_eq!(completed.placements[..2], [players[3], players[0]]);
+        assert_eq!(bank.lamports(&treasury), treasury_before + fee);
+        assert_eq!(bank.lamports(&players[0]), before[0] + runner_up_prize);
+        assert_eq!(
+            bank.lamports(&players[3]),
+            before[3] + pool - fee - runner_up_prize
+        );
+        assert_eq!(bank.lamports(&tournament_escrow), 0);
+    }
 
-    pub system_program: Program<'info, System>,
-}
+    #[test]
+    fn bet_pools_pay_winners_pro_rata_and_sweep_to_the_last_claim() {
+        let (mut bank, treasury) = bank_with_config();
+        let host = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(10 * LAMPORTS_PER_SOL);
+        let spectators: Vec<Pubkey> = (0..3).map(|_| bank.wallet(LAMPORTS_PER_SOL)).collect();
+        let match_id = [45u8; 32];
+        let match_account = pda(&[b"match", &match_id]);
+        let escrow_vault = pda(&[b"escrow", &match_id]);
+        let bet_pool = pda(&[b"bet_pool", &match_id]);
+        let bet_escrow = pda(&[b"bet_escrow", &match_id]);
+        let stake = LAMPORTS_PER_SOL / 10;
 
-// ============================================================================
-// State
-// ============================================================================
+        bank.run(
+            crate::accounts::CreateMatch {
+                match_account,
+                escrow_vault,
+                host,
+                lobby: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::CreateMatch {
+                match_id,
+                stake_amount: stake,
+                metadata: MatchMetadata::default(),
+            },
+        )
+        .unwrap();
+        bank.run(
+            crate::accounts::OpenBetPool {
+                bet_pool,
+                match_account,
+                opener: spectators[0],
+                system_program: system_program::ID,
+            },
+            crate::instruction::OpenBetPool {},
+        )
+        .unwrap();
 
-#[account]
-#[derive(InitSpace)]
-pub struct MatchAccount {
-    /// Unique match identifier (can be room code hash)
-    pub match_id: [u8; 32],
+        let bet = |bank: &mut Bank, bettor: Pubkey, outcome, amount| {
+            bank.run(
+                crate::accounts::PlaceBet {
+                    bet_pool,
+                    match_account,
+                    bet: pda(&[b"bet", &match_id, bettor.as_ref()]),
+                    bet_escrow,
+                    bettor,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::PlaceBet { outcome, amount },
+            )
+        };
+        assert_eq!(
+            bet(&mut bank, host, BetOutcome::HostWins, stake),
+            Err(staking_error(StakingError::PlayerCannotBet))
+        );
+        bet(&mut bank, spectators[0], BetOutcome::HostWins, stake).unwrap();
+        bet(&mut bank, spectators[1], BetOutcome::HostWins, 2 * stake).unwrap();
+        bet(
+            &mut bank,
+            spectators[2],
+            BetOutcome::ChallengerWins,
+            3 * stake,
+        )
+        .unwrap();
 
-    /// Host player (white, creates the match)
-    pub host: Pubkey,
+        bank.run(
+            crate::accounts::JoinMatch {
+                match_account,
+                escrow_vault,
+                challenger,
+                lobby: None,
+                host_backing_pool: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::JoinMatch {},
+        )
+        .unwrap();
+        let late = bank.wallet(LAMPORTS_PER_SOL);
+        assert_eq!(
+            bet(&mut bank, late, BetOutcome::Draw, stake),
+            Err(staking_error(StakingError::BettingClosed))
+        );
 
-    /// Challenger player (black, joins the match)
-    pub challenger: Pubkey,
+        let settle = || crate::accounts::SettleBetPool {
+            bet_pool,
+            match_account,
+            bet_escrow,
+            config: pda(&[b"config"]),
+            platform_treasury: treasury,
+            system_program: system_program::ID,
+        };
+        assert_eq!(
+            bank.run(settle(), crate::instruction::SettleBetPool {}),
+            Err(staking_error(StakingError::MatchNotInProgress))
+        );
+        bank.run(
+            crate::accounts::ClaimWinnerReward {
+                match_account,
+                escrow_vault,
+                caller: host,
+                winner_account: host,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::ClaimWinnerReward { winner: host },
+        )
+        .unwrap();
+        bank.run(settle(), crate::instruction::SettleBetPool {})
+            .unwrap();
+        let settled: BetPool = bank.get(&bet_pool);
+        assert!(settled.status == BetPoolStatus::Settled);
+        assert_eq!(settled.winning_total, 3 * stake);
+        let payout_pool = 6 * stake - platform_fee(6 * stake, pokechess_staking::BET_POOL_FEE_BPS);
+        assert_eq!(settled.payout_pool, payout_pool);
 
-    /// Stake amount per player in lamports
-    pub stake_amount: u64,
+        // Each claim also returns the bet account's rent
+        let bet_rent = Rent::default().minimum_balance(8 + Bet::INIT_SPACE);
+        let claim = |bank: &mut Bank, bettor: Pubkey| {
+            let before = bank.lamports(&bettor);
+            bank.run(
+                crate::accounts::ClaimBet {
+                    bet_pool,
+                    bet: pda(&[b"bet", &match_id, bettor.as_ref()]),
+                    bet_escrow,
+                    bettor,
+                    config: pda(&[b"config"]),
+                    platform_treasury: treasury,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::ClaimBet {},
+            )
+            .unwrap();
+            bank.lamports(&bettor) - before - bet_rent
+        };
+        let first_share = claim(&mut bank, spectators[0]);
+        assert_eq!(first_share, payout_pool / 3);
+        assert_eq!(claim(&mut bank, spectators[2]), 0);
+        assert_eq!(claim(&mut bank, spectators[1]), payout_pool - first_share);
+        assert_eq!(bank.lamports(&bet_escrow), 0);
+        assert_eq!(bank.get::<BetPool>(&bet_pool).open_bets, 0);
+    }
 
-    /// Current match status
-    pub status: MatchStatus,
+    #[test]
+    fn backers_split_the_winnings_pro_rata_and_the_player_keeps_the_markup() {
+        let (mut bank, treasury) = bank_with_config();
+        let host = bank.wallet(LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(LAMPORTS_PER_SOL);
+        let backers: Vec<Pubkey> = (0..2).map(|_| bank.wallet(LAMPORTS_PER_SOL)).collect();
+        let match_id = [46u8; 32];
+        let match_account = pda(&[b"match", &match_id]);
+        let escrow_vault = pda(&[b"escrow", &match_id]);
+        let backing_pool = BackingPool::address(&match_id, &host);
+        let stake = LAMPORTS_PER_SOL / 10;
 
-    /// Winner's public key (set after match ends)
-    pub winner: Pubkey,
+        let open = |bank: &mut Bank, markup_bps| {
+            bank.run(
+                crate::accounts::OpenBackingPool {
+                    backing_pool,
+                    player: host,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::OpenBackingPool {
+                    match_id,
+                    target: 3 * stake,
+                    markup_bps,
+                },
+            )
+        };
+        assert_eq!(
+            open(&mut bank, pokechess_staking::MAX_BACKING_MARKUP_BPS + 1),
+            Err(staking_error(StakingError::MarkupTooHigh))
+        );
+        open(&mut bank, 1_000).unwrap();
 
-    /// Unix timestamp when match was created
-    pub created_at: i64,
+        let back = |bank: &mut Bank, backer: Pubkey, amount| {
+            bank.run(
+                crate::accounts::BackPlayer {
+                    backing_pool,
+                    backing: pda(&[b"backing", &match_id, host.as_ref(), backer.as_ref()]),
+                    backer,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::BackPlayer { amount },
+            )
+        };
+        back(&mut bank, backers[0], stake).unwrap();
+        back(&mut bank, backers[1], stake).unwrap();
+        assert_eq!(
+            back(&mut bank, backers[1], 2 * stake),
+            Err(staking_error(StakingError::StakeTooHigh))
+        );
+        // Topping up adds to the same position
+        back(&mut bank, backers[1], stake).unwrap();
+        let pool: BackingPool = bank.get(&backing_pool);
+        assert_eq!(pool.total_backed, 3 * stake);
+        assert_eq!(pool.total_markup, 3 * stake / 10);
+        assert_eq!(pool.open_backings, 2);
 
-    /// PDA bump seed
-    pub bump: u8,
-}
+        bank.run(
+            crate::accounts::CreateBackedMatch {
+                match_account,
+                escrow_vault,
+                backing_pool,
+                host,
+                system_program: system_program::ID,
+            },
+            crate::instruction::CreateBackedMatch { match_id },
+        )
+        .unwrap();
+        bank.run(
+            crate::accounts::JoinMatch {
+                match_account,
+                escrow_vault,
+                challenger,
+                lobby: None,
+                host_backing_pool: Some(backing_pool),
+                system_program: system_program::ID,
+            },
+            crate::instruction::JoinMatch {},
+        )
+        .unwrap();
+        bank.run(
+            crate::accounts::ClaimWinnerReward {
+                match_account,
+                escrow_vault,
+                caller: host,
+                winner_account: backing_pool,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::ClaimWinnerReward { winner: host },
+        )
+        .unwrap();
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
-pub enum MatchStatus {
-    WaitingForChallenger,
-    InProgress,
-    Completed,
-    Cancelled,
-    Draw,
-}
+        bank.run(
+            crate::accounts::SettleBackingPool {
+                backing_pool,
+                match_account,
+            },
+            crate::instruction::SettleBackingPool {},
+        )
+        .unwrap();
+        let winnings = 6 * stake - platform_fee(6 * stake, PLATFORM_FEE_BPS);
+        assert_eq!(bank.get::<BackingPool>(&backing_pool).returned, winnings);
 
-impl Default for MatchStatus {
-    fn default() -> Self {
-        MatchStatus::WaitingForChallenger
+        let backing_rent = Rent::default().minimum_balance(8 + Backing::INIT_SPACE);
+        let claim = |bank: &mut Bank, backer: Pubkey| {
+            let before = bank.lamports(&backer);
+            bank.run(
+                crate::accounts::ClaimBacking {
+                    backing_pool,
+                    backing: pda(&[b"backing", &match_id, host.as_ref(), backer.as_ref()]),
+                    backer,
+                },
+                crate::instruction::ClaimBacking {},
+            )
+            .unwrap();
+            bank.lamports(&backer) - before - backing_rent
+        };
+        assert_eq!(claim(&mut bank, backers[0]), winnings / 3);
+        assert_eq!(claim(&mut bank, backers[1]), winnings * 2 / 3);
+
+        // The match started, so the markup is the player's
+        let host_before = bank.lamports(&host);
+        let pool_lamports = bank.lamports(&backing_pool);
+        bank.run(
+            crate::accounts::CloseBackingPool {
+                backing_pool,
+                player: host,
+            },
+            crate::instruction::CloseBackingPool {},
+        )
+        .unwrap();
+        assert_eq!(bank.lamports(&host), host_before + pool_lamports);
+        let dust = winnings - winnings / 3 - winnings * 2 / 3;
+        assert_eq!(
+            pool_lamports - Rent::default().minimum_balance(8 + BackingPool::INIT_SPACE),
+            3 * stake / 10 + dust
+        );
     }
-}
 
-// ============================================================================
-// Events
-// ============================================================================
+    #[test]
+    fn sponsor_bonuses_go_to_the_winner_or_follow_the_draw_policy() {
+        let (mut bank, treasury) = bank_with_config();
+        let sponsors: Vec<Pubkey> = (0..2).map(|_| bank.wallet(LAMPORTS_PER_SOL)).collect();
+        let stake = LAMPORTS_PER_SOL / 10;
+        let bonus = LAMPORTS_PER_SOL / 100;
+        let sponsorship_rent = Rent::default().minimum_balance(8 + Sponsorship::INIT_SPACE);
 
-#[event]
-pub struct MatchCreated {
-    pub match_id: [u8; 32],
-    pub host: Pubkey,
-    pub stake_amount: u64,
-}
+        let sponsor =
+            |bank: &mut Bank, match_id: [u8; 32], sponsor: Pubkey, amount, draw_policy| {
+                bank.run(
+                    crate::accounts::SponsorMatch {
+                        match_account: pda(&[b"match", &match_id]),
+                        escrow_vault: pda(&[b"escrow", &match_id]),
+                        sponsorship: pda(&[b"sponsorship", &match_id, sponsor.as_ref()]),
+                        sponsor,
+                        system_program: system_program::ID,
+                    },
+                    crate::instruction::SponsorMatch {
+                        amount,
+                        draw_policy,
+                    },
+                )
+            };
+        let close = |bank: &mut Bank, match_id: [u8; 32], sponsor: Pubkey| {
+            let before = bank.lamports(&sponsor);
+            bank.run(
+                crate::accounts::CloseSponsorship {
+                    sponsorship: pda(&[b"sponsorship", &match_id, sponsor.as_ref()]),
+                    match_account: pda(&[b"match", &match_id]),
+                    escrow_vault: pda(&[b"escrow", &match_id]),
+                    sponsor,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::CloseSponsorship {},
+            )
+            .unwrap();
+            bank.lamports(&sponsor) - before - sponsorship_rent
+        };
 
-#[event]
-pub struct MatchStarted {
-    pub match_id: [u8; 32],
-    pub host: Pubkey,
-    pub challenger: Pubkey,
-    pub total_pot: u64,
-}
+        // A win pays the whole bonus on top of the pot, free of the fee
+        let won_id = [47u8; 32];
+        let (host, _, match_account, escrow_vault) = start_match(&mut bank, won_id, stake);
+        sponsor(
+            &mut bank,
+            won_id,
+            sponsors[0],
+            2 * bonus,
+            SponsorDrawPolicy::Split,
+        )
+        .unwrap();
+        sponsor(
+            &mut bank,
+            won_id,
+            sponsors[1],
+            bonus,
+            SponsorDrawPolicy::Return,
+        )
+        .unwrap();
+        let host_before = bank.lamports(&host);
+        bank.run(
+            crate::accounts::ClaimWinnerReward {
+                match_account,
+                escrow_vault,
+                caller: host,
+                winner_account: host,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::ClaimWinnerReward { winner: host },
+        )
+        .unwrap();
+        assert_eq!(
+            bank.lamports(&host),
+            host_before + 2 * stake - platform_fee(2 * stake, PLATFORM_FEE_BPS) + 3 * bonus
+        );
+        assert_eq!(close(&mut bank, won_id, sponsors[1]), 0);
 
-#[event]
-pub struct WinnerDeclared {
-    pub match_id: [u8; 32],
-    pub winner: Pubkey,
-    pub declared_by: Pubkey,
-}
+        // A draw splits the split bonuses and holds the rest for their sponsors
+        let drawn_id = [48u8; 32];
+        let (host, challenger, match_account, escrow_vault) =
+            start_match(&mut bank, drawn_id, stake);
+        assert_eq!(
+            sponsor(
+                &mut bank,
+                drawn_id,
+                sponsors[0],
+                bonus / 100,
+                SponsorDrawPolicy::Split
+            ),
+            Err(staking_error(StakingError::StakeTooLow))
+        );
+        sponsor(
+            &mut bank,
+            drawn_id,
+            sponsors[0],
+            2 * bonus,
+            SponsorDrawPolicy::Split,
+        )
+        .unwrap();
+        sponsor(
+            &mut bank,
+            drawn_id,
+            sponsors[1],
+            4 * bonus,
+            SponsorDrawPolicy::Return,
+        )
+        .unwrap();
+        let (host_before, challenger_before) = (bank.lamports(&host), bank.lamports(&challenger));
+        bank.run(
+            crate::accounts::OfferDraw {
+                match_account,
+                caller: host,
+                session: None,
+            },
+            crate::instruction::OfferDraw {
+                reason: DrawReason::Agreement,
+            },
+        )
+        .unwrap();
+        bank.run(
+            crate::accounts::AcceptDraw {
+                match_account,
+                escrow_vault,
+                caller: challenger,
+                host_account: host,
+                challenger_account: challenger,
+                config: pda(&[b"config"]),
+                platform_treasury: treasury,
+                session: None,
+                relayer: None,
+                system_program: system_program::ID,
+            },
+            crate::instruction::AcceptDraw {},
+        )
+        .unwrap();
+        assert_eq!(bank.lamports(&host), host_before + stake + bonus);
+        assert_eq!(
+            bank.lamports(&challenger),
+            challenger_before + stake + bonus
+        );
+        assert_eq!(bank.lamports(&escrow_vault), 4 * bonus);
 
-#[event]
-pub struct RewardClaimed {
-    pub match_id: [u8; 32],
-    pub winner: Pubkey,
-    pub amount: u64,
-    pub platform_fee: u64,
-}
+        assert_eq!(close(&mut bank, drawn_id, sponsors[0]), 0);
+        assert_eq!(close(&mut bank, drawn_id, sponsors[1]), 4 * bonus);
+        assert_eq!(bank.lamports(&escrow_vault), 0);
+        let drawn: MatchAccount = bank.get(&match_account);
+        assert_eq!(drawn.sponsor_draw_refund, 0);
+        assert_eq!(drawn.draw_refund_sponsors, 0);
+    }
 
-#[event]
-pub struct MatchCancelled {
-    pub match_id: [u8; 32],
-    pub refunded_to: Pubkey,
-    pub amount: u64,
-}
+    #[test]
+    fn match_metadata_is_validated_stored_and_listed() {
+        let (mut bank, _) = bank_with_config();
+        let host = bank.wallet(LAMPORTS_PER_SOL);
+        let stake = LAMPORTS_PER_SOL / 10;
+        let bucket = lobby_bucket(stake);
+        let lobby = pda(&[b"lobby", &bucket.to_le_bytes()]);
+        bank.run(
+            crate::accounts::InitLobbyBucket {
+                lobby,
+                payer: host,
+                system_program: system_program::ID,
+            },
+            crate::instruction::InitLobbyBucket { bucket },
+        )
+        .unwrap();
 
-#[event]
-pub struct MatchDraw {
-    pub match_id: [u8; 32],
-    pub refund_amount: u64,
-}
+        let match_id = [48u8; 32];
+        let match_account = pda(&[b"match", &match_id]);
+        let create = |bank: &mut Bank, metadata| {
+            bank.run(
+                crate::accounts::CreateMatch {
+                    match_account,
+                    escrow_vault: pda(&[b"escrow", &match_id]),
+                    host,
+                    lobby: Some(lobby),
+                    system_program: system_program::ID,
+                },
+                crate::instruction::CreateMatch {
+                    match_id,
+                    stake_amount: stake,
+                    metadata,
+                },
+            )
+        };
+        let blitz = MatchMetadata {
+            time_control_base_seconds: 3 * 60,
+            time_control_increment_seconds: 2,
+            variant: 7,
+            rated: true,
+            host_color: ColorPreference::Black,
+        };
+        assert_eq!(
+            create(
+                &mut bank,
+                MatchMetadata {
+                    time_control_base_seconds: pokechess_staking::MAX_TIME_CONTROL_BASE_SECONDS + 1,
+                    ..blitz
+                }
+            ),
+            Err(staking_error(StakingError::InvalidTimeControl))
+        );
+        assert_eq!(
+            create(
+                &mut bank,
+                MatchMetadata {
+                    time_control_increment_seconds:
+                        pokechess_staking::MAX_TIME_CONTROL_INCREMENT_SECONDS + 1,
+                    ..blitz
+                }
+            ),
+            Err(staking_error(StakingError::InvalidTimeControl))
+        );
 
-// ============================================================================
-// Errors
-// ============================================================================
+        create(&mut bank, blitz).unwrap();
+        assert!(bank.get::<MatchAccount>(&match_account).metadata == blitz);
+        let listed: LobbyBucket = bank.get(&lobby);
+        assert_eq!(listed.entries.len(), 1);
+        assert!(listed.entries[0].metadata == blitz);
+        assert_eq!(listed.entries[0].match_id, match_id);
+    }
 
-#[error_code]
-pub enum StakingError {
-    #[msg("Stake amount is below minimum (0.01 SOL)")]
-    StakeTooLow,
+    fn queue_entry(stake_amount: u64, min_stake: u64, base: u32, increment: u32) -> QueueEntry {
+        QueueEntry {
+            player: Pubkey::new_unique(),
+            stake_amount,
+            min_stake,
+            time_control_base_seconds: base,
+            time_control_increment_seconds: increment,
+            enqueued_at: 0,
+        }
+    }
 
-    #[msg("Stake amount exceeds maximum (10 SOL)")]
-    StakeTooHigh,
+    #[test]
+    fn queue_entries_pair_on_time_control_and_the_smaller_stake() {
+        let blitz = queue_entry(150_000_000, 100_000_000, 180, 2);
+        assert!(blitz.can_pair_with(&queue_entry(120_000_000, 110_000_000, 180, 2)));
+        // The smaller stake is below what the blitz player accepts
+        assert!(!blitz.can_pair_with(&queue_entry(90_000_000, 50_000_000, 180, 2)));
+        // ... or below what the other player accepts
+        assert!(!blitz.can_pair_with(&queue_entry(200_000_000, 160_000_000, 180, 2)));
+        assert!(!blitz.can_pair_with(&queue_entry(150_000_000, 100_000_000, 180, 0)));
+        assert!(!blitz.can_pair_with(&queue_entry(150_000_000, 100_000_000, 600, 2)));
+    }
 
-    #[msg("Match is not joinable")]
-    MatchNotJoinable,
+    #[test]
+    fn strangers_pair_the_queue_oldest_first() {
+        let (mut bank, _) = bank_with_config();
+        let pairer = bank.wallet(LAMPORTS_PER_SOL);
+        let players: Vec<Pubkey> = (0..4).map(|_| bank.wallet(LAMPORTS_PER_SOL)).collect();
+        let bucket = lobby_bucket(LAMPORTS_PER_SOL / 10);
+        let queue = pda(&[b"queue", &bucket.to_le_bytes()]);
+        let queue_escrow = pda(&[b"queue_escrow", &bucket.to_le_bytes()]);
+        bank.run(
+            crate::accounts::InitMatchmakingQueue {
+                queue,
+                payer: pairer,
+                system_program: system_program::ID,
+            },
+            crate::instruction::InitMatchmakingQueue { bucket },
+        )
+        .unwrap();
 
-    #[msg("Cannot play against yourself")]
-    CannotPlaySelf,
+        // Player 1 wants a different time control; 0, 2 and 3 can all meet
+        for (player, (stake_amount, min_stake, base)) in players.iter().zip([
+            (150_000_000, 100_000_000, 180),
+            (100_000_000, 100_000_000, 600),
+            (120_000_000, 110_000_000, 180),
+            (140_000_000, 100_000_000, 180),
+        ]) {
+            bank.run(
+                crate::accounts::Enqueue {
+                    queue,
+                    queue_escrow,
+                    player: *player,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::Enqueue {
+                    stake_amount,
+                    min_stake,
+                    time_control_base_seconds: base,
+                    time_control_increment_seconds: 2,
+                },
+            )
+            .unwrap();
+        }
 
-    #[msg("Match is not in progress")]
-    MatchNotInProgress,
+        let pair = |bank: &mut Bank, pairer, index_a: u8, index_b: u8| {
+            let entries = bank.get::<MatchmakingQueue>(&queue).entries;
+            let (host, challenger) = (entries[index_a as usize], entries[index_b as usize]);
+            let match_id = queue_match_id(&host, &challenger);
+            bank.run(
+                crate::accounts::Pair {
+                    queue,
+                    queue_escrow,
+                    match_account: pda(&[b"match", &match_id]),
+                    escrow_vault: pda(&[b"escrow", &match_id]),
+                    host: host.player,
+                    challenger: challenger.player,
+                    pairer,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::Pair {
+                    match_id,
+                    index_a,
+                    index_b,
+                },
+            )
+            .map(|()| match_id)
+        };
+        assert_eq!(
+            pair(&mut bank, pairer, 0, 1),
+            Err(staking_error(StakingError::IncompatibleQueueEntries))
+        );
+        assert_eq!(
+            pair(&mut bank, pairer, 0, 3),
+            Err(staking_error(StakingError::PairNotInQueueOrder))
+        );
 
-    #[msg("Invalid winner address")]
-    InvalidWinner,
+        let host_before = bank.lamports(&players[0]);
+        let match_id = pair(&mut bank, pairer, 0, 2).unwrap();
+        let paired: MatchAccount = bank.get(&pda(&[b"match", &match_id]));
+        assert!(paired.status == MatchStatus::InProgress);
+        assert_eq!((paired.host, paired.challenger), (players[0], players[2]));
+        assert_eq!(paired.stake_amount, 120_000_000);
+        assert_eq!(paired.rent_payer, pairer);
+        assert_eq!(bank.lamports(&pda(&[b"escrow", &match_id])), 240_000_000);
+        // The host's excess stake comes back
+        assert_eq!(bank.lamports(&players[0]), host_before + 30_000_000);
 
-    #[msg("Caller is not a match participant")]
-    NotParticipant,
+        let left: Vec<Pubkey> = bank
+            .get::<MatchmakingQueue>(&queue)
+            .entries
+            .iter()
+            .map(|entry| entry.player)
+            .collect();
+        assert_eq!(left, vec![players[1], players[3]]);
+        assert_eq!(bank.lamports(&queue_escrow), 240_000_000);
+    }
 
-    #[msg("Match is not completed")]
-    MatchNotCompleted,
+    #[test]
+    fn series_pay_out_once_clinched_and_refund_a_tie() {
+        let (mut bank, treasury) = bank_with_config();
+        let host = bank.wallet(LAMPORTS_PER_SOL);
+        let challenger = bank.wallet(LAMPORTS_PER_SOL);
+        let stake = LAMPORTS_PER_SOL / 10;
 
-    #[msg("Caller is not the winner")]
-    NotWinner,
+        let start = |bank: &mut Bank, series_id: [u8; 32]| {
+            let series = pda(&[b"series", &series_id]);
+            let series_escrow = pda(&[b"series_escrow", &series_id]);
+            bank.run(
+                crate::accounts::CreateSeries {
+                    series,
+                    series_escrow,
+                    host,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::CreateSeries {
+                    series_id,
+                    stake_amount: stake,
+                    best_of: 3,
+                },
+            )
+            .unwrap();
+            bank.run(
+                crate::accounts::JoinSeries {
+                    series,
+                    series_escrow,
+                    challenger,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::JoinSeries {},
+            )
+            .unwrap();
+            series
+        };
+        let record = |bank: &mut Bank, series: Pubkey, winner| {
+            let series_id = bank.get::<Series>(&series).series_id;
+            bank.run(
+                crate::accounts::RecordSeriesGame {
+                    series,
+                    series_escrow: pda(&[b"series_escrow", &series_id]),
+                    caller: host,
+                    host_account: host,
+                    challenger_account: challenger,
+                    config: pda(&[b"config"]),
+                    platform_treasury: treasury,
+                    system_program: system_program::ID,
+                },
+                crate::instruction::RecordSeriesGame { winner },
+            )
+        };
 
-    #[msg("Cannot cancel a match that has started")]
-    CannotCancelStartedMatch,
+        // Two straight wins clinch a best of three
+        let clinched = start(&mut bank, [41u8; 32]);
+        let challenger_before = bank.lamports(&challenger);
+        assert_eq!(
+            record(&mut bank, clinched, Some(Pubkey::new_unique())),
+            Err(staking_error(StakingError::InvalidWinner))
+        );
+        record(&mut bank, clinched, Some(challenger)).unwrap();
+        assert!(bank.get::<Series>(&clinched).status == MatchStatus::InProgress);
+        record(&mut bank, clinched, Some(challenger)).unwrap();
+        let settled: Series = bank.get(&clinched);
+        assert!(settled.status == MatchStatus::Settled);
+        assert_eq!(settled.winner, challenger);
+        assert_eq!(
+            bank.lamports(&challenger),
+            challenger_before + 2 * stake - platform_fee(2 * stake, PLATFORM_FEE_BPS)
+        );
+        assert_eq!(
+            record(&mut bank, clinched, Some(host)),
+            Err(staking_error(StakingError::MatchNotInProgress))
+        );
 
-    #[msg("Caller is not the host")]
-    NotHost,
+        // A win each and a drawn game refunds both stakes
+        let tied = start(&mut bank, [42u8; 32]);
+        let (host_before, challenger_before) = (bank.lamports(&host), bank.lamports(&challenger));
+        record(&mut bank, tied, Some(host)).unwrap();
+        record(&mut bank, tied, None).unwrap();
+        record(&mut bank, tied, Some(challenger)).unwrap();
+        assert!(bank.get::<Series>(&tied).status == MatchStatus::Draw);
+        assert_eq!(bank.lamports(&host), host_before + stake);
+        assert_eq!(bank.lamports(&challenger), challenger_before + stake);
+        assert_eq!(bank.lamports(&pda(&[b"series_escrow", &[42u8; 32]])), 0);
+    }
 }
@@ -1,6 +1,6 @@
 import React, { useState, useEffect } from 'react';
 import { useWallet, useConnection } from '@solana/wallet-adapter-react';
-import { PublicKey, Transaction } from '@solana/web3.js';
+import { Transaction } from '@solana/web3.js';
 import { AnchorProvider } from '@coral-xyz/anchor';
 import {
     Trophy,
@@ -13,6 +13,7 @@ import {
     X
 } from 'lucide-react';
 import { stakingService } from '../services/stakingService';
+import { MatchStatus } from '../services/stakingTypes';
 import { toast } from 'react-hot-toast';
 import confetti from 'canvas-confetti';
 
@@ -51,6 +52,8 @@ export const GameEndModal: React.FC<GameEndModalProps> = ({
 
     const [isClaiming, setIsClaiming] = useState(false);
     const [hasClaimed, setHasClaimed] = useState(false);
+    const [drawOfferedBy, setDrawOfferedBy] = useState<string | null>(null);
+    const [drawOfferExpiresAt, setDrawOfferExpiresAt] = useState<Date | null>(null);
 
     // Initialize staking service with Anchor provider
     const initializeStakingService = () => {
@@ -74,6 +77,37 @@ export const GameEndModal: React.FC<GameEndModalProps> = ({
         return true;
     };
 
+    // A draw refund needs one player to offer it and the other to accept
+    const drawOfferLive = drawOfferExpiresAt !== null && drawOfferExpiresAt > new Date();
+    const opponentOfferedDraw =
+        drawOfferLive && drawOfferedBy !== null && drawOfferedBy !== publicKey?.toBase58();
+    const weOfferedDraw =
+        drawOfferLive && drawOfferedBy !== null && drawOfferedBy === publicKey?.toBase58();
+
+    const refreshDrawOffer = async () => {
+        if (!initializeStakingService()) return;
+
+        const info = await stakingService.getMatchInfo(matchId.toUpperCase());
+        if (!info) return;
+        if (info.status === MatchStatus.Draw) {
+            // The opponent accepted our offer and the refunds went through
+            setHasClaimed(true);
+            return;
+        }
+        setDrawOfferedBy(info.drawOfferedBy);
+        setDrawOfferExpiresAt(info.drawOfferExpiresAt);
+    };
+
+    // Poll the match for the opponent's draw offer (or our offer being accepted)
+    useEffect(() => {
+        if (result !== 'draw' || hasClaimed) return;
+
+        refreshDrawOffer();
+        const interval = setInterval(refreshDrawOffer, 5000);
+        return () => clearInterval(interval);
+        // eslint-disable-next-line react-hooks/exhaustive-deps
+    }, [result, hasClaimed, publicKey]);
+
     // Trigger confetti on win
     useEffect(() => {
         if (result === 'win') {
@@ -128,7 +162,7 @@ export const GameEndModal: React.FC<GameEndModalProps> = ({
         }
     };
 
-    const handleDeclareDraw = async () => {
+    const handleDraw = async () => {
         if (!publicKey || !signTransaction) {
             toast.error('Wallet not connected');
             return;
@@ -141,23 +175,29 @@ export const GameEndModal: React.FC<GameEndModalProps> = ({
                 throw new Error('Failed to initialize staking service');
             }
 
-            console.log('[GameEndModal] Declaring draw for match:', matchId);
+            const wallet = {
+                publicKey,
+                signTransaction: signTransaction as (tx: Transaction) => Promise<Transaction>,
+            };
 
-            await stakingService.declareDraw(
-                {
-                    publicKey,
-                    signTransaction: signTransaction as (tx: Transaction) => Promise<Transaction>,
-                },
-                matchId.toUpperCase(),
-                new PublicKey(stakeInfo.hostAddress),
-                new PublicKey(stakeInfo.challengerAddress!)
-            );
+            if (opponentOfferedDraw) {
+                console.log('[GameEndModal] Accepting draw for match:', matchId);
 
-            setHasClaimed(true);
-            toast.success('Draw declared! Stakes refunded.');
+                await stakingService.acceptDraw(wallet, matchId.toUpperCase());
+
+                setHasClaimed(true);
+                toast.success('Draw accepted! Stakes refunded.');
+            } else {
+                console.log('[GameEndModal] Offering draw for match:', matchId);
+
+                await stakingService.offerDraw(wallet, matchId.toUpperCase());
+
+                toast.success('Draw offered! Waiting for your opponent to accept.');
+                await refreshDrawOffer();
+            }
         } catch (err: any) {
-            console.error('Draw declaration error:', err);
-            toast.error(err.message || 'Failed to declare draw');
+            console.error('Draw error:', err);
+            toast.error(err.message || 'Failed to settle draw');
         } finally {
             setIsClaiming(false);
         }
@@ -276,8 +316,8 @@ export const GameEndModal: React.FC<GameEndModalProps> = ({
 
                     {result === 'draw' && !hasClaimed && (
                         <button
-                            onClick={handleDeclareDraw}
-                            disabled={isClaiming}
+                            onClick={handleDraw}
+                            disabled={isClaiming || weOfferedDraw}
                             className="w-full bg-gradient-to-r from-blue-500 to-indigo-500 hover:from-blue-400 hover:to-indigo-400 disabled:from-slate-600 disabled:to-slate-700 disabled:cursor-not-allowed text-white font-bold py-4 rounded-xl shadow-lg transition-all flex items-center justify-center gap-3"
                         >
                             {isClaiming ? (
@@ -285,10 +325,15 @@ export const GameEndModal: React.FC<GameEndModalProps> = ({
                                     <Loader2 className="w-5 h-5 animate-spin" />
                                     <span>Processing Refund...</span>
                                 </>
+                            ) : weOfferedDraw ? (
+                                <>
+                                    <Loader2 className="w-5 h-5 animate-spin" />
+                                    <span>Waiting for Opponent to Accept...</span>
+                                </>
                             ) : (
                                 <>
                                     <Handshake className="w-5 h-5" />
-                                    <span>Claim Refund</span>
+                                    <span>{opponentOfferedDraw ? 'Accept Draw & Refund' : 'Offer Draw Refund'}</span>
                                 </>
                             )}
                         </button>
@@ -11,20 +11,25 @@ import {
     getPlatformTreasury,
     MIN_STAKE_LAMPORTS,
     MAX_STAKE_LAMPORTS,
-    MatchStatus,
     StakeInfo,
     CreateMatchParams,
+    DEFAULT_MATCH_METADATA,
     JoinMatchParams,
-    DeclareWinnerParams,
+    DrawReason,
     STAKING_IDL,
     roomCodeToMatchId,
     matchIdToRoomCode,
     solToLamports,
+    toMatchStatus,
 } from './stakingTypes';
 
 // PDA seed constants
 const MATCH_SEED = 'match';
 const ESCROW_SEED = 'escrow';
+const CONFIG_SEED = 'config';
+const PLAYER_VAULT_SEED = 'player_vault';
+const BACKING_POOL_SEED = 'backing_pool';
+const LOBBY_SEED = 'lobby';
 
 /**
  * Staking Service - Handles all interactions with the PokeChess staking program
@@ -65,6 +70,73 @@ class StakingService {
         );
     }
 
+    /**
+     * Get PDA for the program config
+     */
+    getConfigPDA(): [PublicKey, number] {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from(CONFIG_SEED)],
+            getStakingProgramId()
+        );
+    }
+
+    /**
+     * Get PDA for a player's backing pool in a match
+     */
+    getBackingPoolPDA(matchId: Uint8Array, player: PublicKey): [PublicKey, number] {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from(BACKING_POOL_SEED), matchId, player.toBuffer()],
+            getStakingProgramId()
+        );
+    }
+
+    /**
+     * Get PDA for a lobby stake bucket
+     */
+    getLobbyPDA(bucket: number): [PublicKey, number] {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from(LOBBY_SEED), Buffer.from([bucket])],
+            getStakingProgramId()
+        );
+    }
+
+    /**
+     * Lobby bucket the match is listed in, if any
+     */
+    // eslint-disable-next-line @typescript-eslint/no-explicit-any
+    private getListedLobby(account: any): PublicKey | null {
+        return account.listedBucket === null ? null : this.getLobbyPDA(account.listedBucket)[0];
+    }
+
+    /**
+     * Relayer that advanced the match's fees, if any
+     */
+    // eslint-disable-next-line @typescript-eslint/no-explicit-any
+    private getRelayer(account: any): PublicKey | null {
+        return account.relayer.equals(PublicKey.default) ? null : account.relayer;
+    }
+
+    /**
+     * Where a player's winnings and refunds go: their backing pool, vault or wallet
+     */
+    // eslint-disable-next-line @typescript-eslint/no-explicit-any
+    getPayoutAddress(account: any, player: PublicKey): PublicKey {
+        const isHost = player.equals(account.host);
+        const backed = isHost ? account.hostBacked : account.challengerBacked;
+        const usesVault = isHost ? account.hostUsesVault : account.challengerUsesVault;
+
+        if (backed) {
+            return this.getBackingPoolPDA(Uint8Array.from(account.matchId), player)[0];
+        }
+        if (usesVault) {
+            return PublicKey.findProgramAddressSync(
+                [Buffer.from(PLAYER_VAULT_SEED), player.toBuffer()],
+                getStakingProgramId()
+            )[0];
+        }
+        return player;
+    }
+
     /**
      * Prepare a transaction with recent blockhash and fee payer
      */
@@ -100,11 +172,16 @@ class StakingService {
         const [escrowPDA] = this.getEscrowPDA(matchIdBytes);
 
         const tx = await this.program.methods
-            .createMatch(Array.from(matchIdBytes), new BN(stakeAmountLamports.toString()))
+            .createMatch(
+                Array.from(matchIdBytes),
+                new BN(stakeAmountLamports.toString()),
+                DEFAULT_MATCH_METADATA
+            )
             .accountsStrict({
                 matchAccount: matchPDA,
                 escrowVault: escrowPDA,
                 host: wallet.publicKey,
+                lobby: null,
                 systemProgram: SystemProgram.programId,
             })
             .transaction();
@@ -132,12 +209,19 @@ class StakingService {
         const [matchPDA] = this.getMatchPDA(matchIdBytes);
         const [escrowPDA] = this.getEscrowPDA(matchIdBytes);
 
+        // eslint-disable-next-line @typescript-eslint/no-explicit-any
+        const account = await (this.program.account as any).matchAccount.fetch(matchPDA);
+
         const tx = await this.program.methods
             .joinMatch()
             .accountsStrict({
                 matchAccount: matchPDA,
                 escrowVault: escrowPDA,
                 challenger: wallet.publicKey,
+                lobby: this.getListedLobby(account),
+                hostBackingPool: account.hostBacked
+                    ? this.getBackingPoolPDA(matchIdBytes, account.host)[0]
+                    : null,
                 systemProgram: SystemProgram.programId,
             })
             .transaction();
@@ -168,14 +252,22 @@ class StakingService {
         const [matchPDA] = this.getMatchPDA(matchIdBytes);
         const [escrowPDA] = this.getEscrowPDA(matchIdBytes);
 
+        const [configPDA] = this.getConfigPDA();
+
+        // eslint-disable-next-line @typescript-eslint/no-explicit-any
+        const account = await (this.program.account as any).matchAccount.fetch(matchPDA);
+
         const tx = await this.program.methods
             .claimWinnerReward(winner)
             .accountsStrict({
                 matchAccount: matchPDA,
                 escrowVault: escrowPDA,
                 caller: wallet.publicKey,
-                winnerAccount: winner,
+                winnerAccount: this.getPayoutAddress(account, winner),
+                config: configPDA,
                 platformTreasury: getPlatformTreasury(),
+                session: null,
+                relayer: this.getRelayer(account),
                 systemProgram: SystemProgram.programId,
             })
             .transaction();
@@ -189,25 +281,34 @@ class StakingService {
     }
 
     /**
-     * @deprecated Use claimWinnerReward instead - it combines both operations in one tx
-     * Declare the winner of a match (requires separate claimReward call)
+     * Pay out a match whose winner was declared by the retired declare_winner.
+     * The winner doesn't need to sign; the match account closes to them.
      */
-    async declareWinner(
+    async finalizeLegacyMatch(
         wallet: { publicKey: PublicKey; signTransaction: (tx: Transaction) => Promise<Transaction> },
-        params: DeclareWinnerParams
+        matchId: string
     ): Promise<string> {
         if (!this.program) {
             throw new Error('Program not initialized. Call initializeProgram first.');
         }
 
-        const matchIdBytes = roomCodeToMatchId(params.matchId);
+        const matchIdBytes = roomCodeToMatchId(matchId);
         const [matchPDA] = this.getMatchPDA(matchIdBytes);
+        const [escrowPDA] = this.getEscrowPDA(matchIdBytes);
+        const [configPDA] = this.getConfigPDA();
+
+        // eslint-disable-next-line @typescript-eslint/no-explicit-any
+        const account = await (this.program.account as any).matchAccount.fetch(matchPDA);
 
         const tx = await this.program.methods
-            .declareWinner(params.winner)
+            .finalizeLegacyMatch()
             .accountsStrict({
                 matchAccount: matchPDA,
-                caller: wallet.publicKey,
+                escrowVault: escrowPDA,
+                winner: account.winner,
+                config: configPDA,
+                platformTreasury: getPlatformTreasury(),
+                systemProgram: SystemProgram.programId,
             })
             .transaction();
 
@@ -220,10 +321,9 @@ class StakingService {
     }
 
     /**
-     * @deprecated Use claimWinnerReward instead - it combines both operations in one tx
-     * Claim reward as the winner (requires declareWinner to be called first)
+     * Cancel a match (only before challenger joins)
      */
-    async claimReward(
+    async cancelMatch(
         wallet: { publicKey: PublicKey; signTransaction: (tx: Transaction) => Promise<Transaction> },
         matchId: string
     ): Promise<string> {
@@ -235,13 +335,22 @@ class StakingService {
         const [matchPDA] = this.getMatchPDA(matchIdBytes);
         const [escrowPDA] = this.getEscrowPDA(matchIdBytes);
 
+        const [configPDA] = this.getConfigPDA();
+
+        // eslint-disable-next-line @typescript-eslint/no-explicit-any
+        const account = await (this.program.account as any).matchAccount.fetch(matchPDA);
+
         const tx = await this.program.methods
-            .claimReward()
+            .cancelMatch()
             .accountsStrict({
                 matchAccount: matchPDA,
                 escrowVault: escrowPDA,
-                winner: wallet.publicKey,
+                host: wallet.publicKey,
+                hostRefund: this.getPayoutAddress(account, account.host),
+                config: configPDA,
                 platformTreasury: getPlatformTreasury(),
+                relayer: this.getRelayer(account),
+                lobby: this.getListedLobby(account),
                 systemProgram: SystemProgram.programId,
             })
             .transaction();
@@ -255,11 +364,12 @@ class StakingService {
     }
 
     /**
-     * Cancel a match (only before challenger joins)
+     * Offer a draw refund; the opponent has to accept it before it expires
      */
-    async cancelMatch(
+    async offerDraw(
         wallet: { publicKey: PublicKey; signTransaction: (tx: Transaction) => Promise<Transaction> },
-        matchId: string
+        matchId: string,
+        reason: DrawReason = 'agreement'
     ): Promise<string> {
         if (!this.program) {
             throw new Error('Program not initialized. Call initializeProgram first.');
@@ -267,15 +377,13 @@ class StakingService {
 
         const matchIdBytes = roomCodeToMatchId(matchId);
         const [matchPDA] = this.getMatchPDA(matchIdBytes);
-        const [escrowPDA] = this.getEscrowPDA(matchIdBytes);
 
         const tx = await this.program.methods
-            .cancelMatch()
+            .offerDraw({ [reason]: {} })
             .accountsStrict({
                 matchAccount: matchPDA,
-                escrowVault: escrowPDA,
-                host: wallet.publicKey,
-                systemProgram: SystemProgram.programId,
+                caller: wallet.publicKey,
+                session: null,
             })
             .transaction();
 
@@ -288,13 +396,11 @@ class StakingService {
     }
 
     /**
-     * Declare a draw (refunds both players)
+     * Accept the opponent's draw offer (refunds both players)
      */
-    async declareDraw(
+    async acceptDraw(
         wallet: { publicKey: PublicKey; signTransaction: (tx: Transaction) => Promise<Transaction> },
-        matchId: string,
-        hostPubkey: PublicKey,
-        challengerPubkey: PublicKey
+        matchId: string
     ): Promise<string> {
         if (!this.program) {
             throw new Error('Program not initialized. Call initializeProgram first.');
@@ -303,15 +409,23 @@ class StakingService {
         const matchIdBytes = roomCodeToMatchId(matchId);
         const [matchPDA] = this.getMatchPDA(matchIdBytes);
         const [escrowPDA] = this.getEscrowPDA(matchIdBytes);
+        const [configPDA] = this.getConfigPDA();
+
+        // eslint-disable-next-line @typescript-eslint/no-explicit-any
+        const account = await (this.program.account as any).matchAccount.fetch(matchPDA);
 
         const tx = await this.program.methods
-            .declareDraw()
+            .acceptDraw()
             .accountsStrict({
                 matchAccount: matchPDA,
                 escrowVault: escrowPDA,
                 caller: wallet.publicKey,
-                hostAccount: hostPubkey,
-                challengerAccount: challengerPubkey,
+                hostAccount: this.getPayoutAddress(account, account.host),
+                challengerAccount: this.getPayoutAddress(account, account.challenger),
+                config: configPDA,
+                platformTreasury: getPlatformTreasury(),
+                session: null,
+                relayer: this.getRelayer(account),
                 systemProgram: SystemProgram.programId,
             })
             .transaction();
@@ -349,11 +463,17 @@ class StakingService {
                     : account.challenger.toBase58(),
                 stakeAmountSol,
                 totalPot: stakeAmountSol * 2,
-                status: account.status as MatchStatus,
+                status: toMatchStatus(account.status),
                 winner: account.winner.equals(PublicKey.default)
                     ? null
                     : account.winner.toBase58(),
                 createdAt: new Date(Number(account.createdAt) * 1000),
+                drawOfferedBy: account.drawOfferedBy.equals(PublicKey.default)
+                    ? null
+                    : account.drawOfferedBy.toBase58(),
+                drawOfferExpiresAt: account.drawOfferedBy.equals(PublicKey.default)
+                    ? null
+                    : new Date(Number(account.drawOfferExpiresAt) * 1000),
             };
         } catch (error) {
             console.error('Failed to fetch match info:', error);
@@ -44,8 +44,19 @@ export enum MatchStatus {
     Completed = 2,
     Cancelled = 3,
     Draw = 4,
+    Settled = 5,
+    WaitingForRematch = 6,
 }
 
+// Anchor decodes Rust enums as `{ variantName: {} }`
+export function toMatchStatus(status: Record<string, unknown>): MatchStatus {
+    const variant = Object.keys(status)[0];
+    return MatchStatus[(variant.charAt(0).toUpperCase() + variant.slice(1)) as keyof typeof MatchStatus];
+}
+
+// Draw reason enum matching Rust, in the IDL's camelCase variant names
+export type DrawReason = 'agreement' | 'stalemate' | 'repetition' | 'insufficientMaterial';
+
 // Match account structure matching Rust MatchAccount
 export interface MatchAccount {
     matchId: Uint8Array; // [u8; 32]
@@ -56,6 +67,9 @@ export interface MatchAccount {
     winner: PublicKey;
     createdAt: bigint;
     bump: number;
+    version: number;
+    drawOfferedBy: PublicKey;
+    drawOfferExpiresAt: bigint;
 }
 
 // Frontend-friendly stake info
@@ -68,6 +82,8 @@ export interface StakeInfo {
     status: MatchStatus;
     winner: string | null;
     createdAt: Date;
+    drawOfferedBy: string | null;
+    drawOfferExpiresAt: Date | null;
 }
 
 // Create match parameters
@@ -81,11 +97,14 @@ export interface JoinMatchParams {
     matchId: string;
 }
 
-// Declare winner parameters
-export interface DeclareWinnerParams {
-    matchId: string;
-    winner: PublicKey;
-}
+// Game settings for create_match; all zero (the default) leaves them unspecified
+export const DEFAULT_MATCH_METADATA = {
+    timeControlBaseSeconds: 0,
+    timeControlIncrementSeconds: 0,
+    variant: 0,
+    rated: false,
+    hostColor: { random: {} },
+};
 
 // Export the IDL for use in the staking service
 export const STAKING_IDL = POKECHESS_STAKING_IDL;